@@ -26,10 +26,26 @@ $ cargo install yatotp --features=cli
   ```sh
   $ yatotp-cli -i [database file path] add -e
   ```
+  Or you can input whole `otpauth://` URI (including Steam Guard one)
+  with switch `-u`:
+  ```sh
+  $ yatotp-cli -i [database file path] add -u
+  ```
 - Show TOTP value of the entry.
   ```sh
   $ yatotp-cli -i [database file path] show [entry name]
   ```
+  TOTP value at another datetime, or of the next/previous time window,
+  can be shown with `--at`, `--next` and `--previous` like this:
+  ```sh
+  $ yatotp-cli -i [database file path] show [entry name] --at 2021-12-24T12:00:00Z --next
+  ```
+- Show OCRA or S/KEY response of the entry to the challenge.
+  ```sh
+  $ yatotp-cli -i [database file path] respond [entry name] [challenge]
+  ```
+  S/KEY challenge can be omitted, then the sequence number stored in the entry is used.
+  S/KEY challenge above that sequence number is refused, and the one below it asks to confirm.
 - List entries in database.
   ```sh
   $ yatotp-cli -i [database file path] list
@@ -38,10 +54,131 @@ $ cargo install yatotp --features=cli
   ```sh
   $ yatotp-cli -i [database file path] remove [entry name]
   ```
+- Import Steam Guard accounts from Steam Desktop Authenticator maFiles.
+  ```sh
+  $ yatotp-cli -i [database file path] import --format sda [maFile paths]
+  ```
 - Change database password to new one.
   ```sh
   $ yatotp-cli -i [database file path] newpass
   ```
+- Use a keyfile as a second factor, like KeePass.
+  ```sh
+  $ yatotp-cli keyfile generate [keyfile path]
+  $ yatotp-cli -i [database file path] -k [keyfile path] create
+  ```
+  Then every command on the database requires `-k [keyfile path]`.
+  If the password is left empty, the keyfile alone unlocks the database.
+  The keyfile can be replaced or removed with `newpass --new-keyfile [keyfile path]`
+  or `newpass --remove-keyfile`.
+- Manage key slots, so several passwords (and keyfiles) can open one database.
+  ```sh
+  $ yatotp-cli -i [database file path] slot list
+  $ yatotp-cli -i [database file path] slot add [slot name]
+  $ yatotp-cli -i [database file path] slot remove [slot index]
+  ```
+  The database is encrypted with a random master key, and each slot wraps it,
+  so slots are added or revoked without re-encrypting entries.
+  `newpass` changes the password of the slot you unlocked with.
+  Note that revoking a slot does not change the master key.
+- Encrypt the database to [age](https://age-encryption.org) recipients.
+  ```sh
+  $ yatotp-cli -i [database file path] recipients add [age1...] --name [slot name]
+  $ yatotp-cli -i [database file path] -I [identity file path] list
+  $ yatotp-cli -i [database file path] recipients remove [age1... or slot name]
+  ```
+  Each recipient gets a key slot, so its identity file, such as the one generated by
+  `age-keygen`, unlocks the database without password. Recipients are listed by `slot list`.
+- Unlock with an ed25519 SSH key, held by ssh-agent or in a private key file.
+  ```sh
+  $ yatotp-cli -i [database file path] slot add-ssh [slot name]
+  $ yatotp-cli -i [database file path] --ssh-agent list
+  $ yatotp-cli -i [database file path] -s ~/.ssh/id_ed25519 list
+  ```
+  `slot add-ssh` asks ssh-agent to sign, or reads the key given with `--private-key`.
+  If ssh-agent has several ed25519 keys, choose one with `--public-key [.pub file path]`.
+  The slot stores a random challenge, and the master key is wrapped under the hash of
+  its signature, so neither a password nor the private key itself is stored.
+- Detect rollback of the database file.
+  Every save, including changes of key slots, increments the revision encrypted in the file,
+  and the latest revision of each database is remembered in `yatotp/revisions.json`
+  in the local state directory.
+  If an older file is loaded, e.g. replaced by an attacker or a stale sync, it is refused.
+  The hash of the key slots is encrypted too, so a revoked slot put back into the file is refused.
+  ```sh
+  $ yatotp-cli -i [database file path] --allow-rollback list
+  ```
+  `--allow-rollback` loads it anyway with a warning, and accepts it as the latest one.
+- Each entry is encrypted separately.
+  `show`, `list` and `remove` decrypt only the entries they need,
+  so other secrets never appear in memory while a code is shown.
+  Older databases are converted to this format on the next save.
+- Protect an entry with its own passphrase.
+  ```sh
+  $ yatotp-cli -i [database file path] add --protect
+  $ yatotp-cli -i [database file path] protect [entry name]
+  ```
+  The passphrase is asked only when the code of the entry is shown,
+  in addition to the database password. `protect --remove` removes it.
+- Set a duress password, which opens a decoy database instead of the real one.
+  ```sh
+  $ yatotp-cli -i [database file path] decoy set
+  $ yatotp-cli -i [database file path] add  # Enter the duress password to add decoy entries.
+  ```
+  Every database file has a second payload of the same size as the real one,
+  filled with random bytes unless a decoy is set, so the file does not tell whether it has a decoy.
+  Both payloads are encrypted again on every save, whichever password is used.
+  `newpass`, `slot` and `decoy` work with the duress password too, for the decoy,
+  and the real payload is padded as the decoy grows. `decoy remove` removes it.
+- Check the strength of new passwords.
+  The strength of new passwords is estimated like zxcvbn, together with the time to crack them offline
+  with the parameters of Argon2id. Passwords weaker than the minimum are refused.
+  ```sh
+  $ yatotp-cli -i [database file path] --min-strength 3 newpass
+  ```
+  The minimum is from 0 (too guessable) to 4 (very unguessable), and defaults to 2.
+  It is not required if a keyfile is given.
+- Recover access with shares of a recovery key.
+  Add a recovery slot, and split its key into shares, any `threshold` of which recover access.
+  ```sh
+  $ yatotp-cli -i [database file path] recovery split --shares 5 --threshold 3
+  ```
+  Give each share to a different person. Splitting again replaces the recovery slot and revokes
+  the old shares. Combine the shares to add a key slot with new password.
+  ```sh
+  $ yatotp-cli -i [database file path] recovery combine
+  ```
+- Print a paper backup.
+  Entries are printed into an HTML file, each with a QR code. A TOTP entry is printed as its
+  otpauth URI in the QR code and its secret key in plain, which any authenticator app can restore.
+  Other entries, and TOTP entries which otpauth URI can not represent, are printed as numbered
+  lines of base32 text. Print it, or save it as PDF from a browser, and delete the file.
+  ```sh
+  $ yatotp-cli -i [database file path] paper export [HTML file path] [names of entries]
+  ```
+  All entries are printed if no name is given. The paper contains the secret keys in plain.
+  Restore an entry by typing in its lines, the text of its QR code, or its secret key and the
+  parameters printed under it. Each line has check characters, so a typo is found at the line.
+  ```sh
+  $ yatotp-cli -i [database file path] paper restore
+  ```
+- Move an entry to other apps with a QR code.
+  The otpauth URI of a TOTP entry is shown as a QR code in the terminal after confirmation,
+  since it reveals the secret key.
+  ```sh
+  $ yatotp-cli -i [database file path] qr [name]
+  ```
+  Give `--invert` for terminals of dark text on light background.
+  Give `-o [file path]` to save it into a new .png or .svg file instead.
+- Add entries from QR code images.
+  otpauth URIs and exports of Google Authenticator (otpauth-migration URIs) are read
+  from QR codes in a PNG or JPEG image, such as a screenshot.
+  ```sh
+  $ yatotp-cli -i [database file path] add --qr-image [image file path]
+  ```
+  Every account found is added after its name is confirmed; unsupported ones are skipped.
+  An account whose name is already taken is added with a number after the name, such as
+  `alice (2)`.
 
 Current version: {{version}}
 
@@ -0,0 +1,76 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Benchmark of bulk TOTP calculation.
+//!
+//! `rekey_per_code` builds a new client for each code, which costs as much as
+//! the HMAC key schedule rebuilt on every call.
+//! `cached_client` and `batch` reuse the HMAC state precomputed in the client.
+
+use chrono::prelude::*;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use yatotp::otp::{HashType, TotpClient};
+
+const KEY: &[u8] = b"12345678901234567890";
+const COUNTERS: u64 = 1000;
+
+fn bench_hotp(c: &mut Criterion) {
+    let mut group = c.benchmark_group("totp_1000_counters");
+    for hashtype in [HashType::Sha1, HashType::Sha256, HashType::Sha512] {
+        let name = format!("{:?}", hashtype);
+        group.bench_with_input(
+            BenchmarkId::new("rekey_per_code", &name),
+            &hashtype,
+            |b, hashtype| {
+                let mut result = [0];
+                b.iter(|| {
+                    for counter in 0..COUNTERS {
+                        let client = TotpClient::new(KEY.to_vec(), 30, 0, 6, *hashtype);
+                        client.totp_batch(&[black_box(counter)], &mut result);
+                        black_box(result);
+                    }
+                })
+            },
+        );
+        let client = TotpClient::new(KEY.to_vec(), 30, 0, 6, hashtype);
+        let epoch = Utc.timestamp(0, 0);
+        group.bench_with_input(
+            BenchmarkId::new("cached_client", &name),
+            &client,
+            |b, client| {
+                b.iter(|| {
+                    for code in client.codes(&epoch).unwrap().take(COUNTERS as usize) {
+                        black_box(code);
+                    }
+                })
+            },
+        );
+        let counters: Vec<u64> = (0..COUNTERS).collect();
+        let mut results = vec![0; COUNTERS as usize];
+        group.bench_with_input(BenchmarkId::new("batch", &name), &client, |b, client| {
+            b.iter(|| {
+                client.totp_batch(black_box(&counters), &mut results);
+                black_box(&results);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_hotp);
+criterion_main!(benches);
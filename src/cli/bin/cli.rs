@@ -21,13 +21,33 @@
 //! Each command loads database file, do some works, and then save database file if needed.
 //! Some command such as `add` takes user input from stdin.
 
-use anyhow::{ensure, Context, Result};
-use chrono::Utc;
-use std::path::Path;
+use anyhow::{bail, ensure, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use std::path::{Path, PathBuf};
+use yatotp::secret::Zeroizing;
 use yatotp::*;
 
+/// Factors to unlock the database and how to load it, given by command-line options.
+pub struct Unlock {
+    /// Keyfile given with `--keyfile`.
+    pub keyfile: Option<PathBuf>,
+    /// age identity file given with `--identity`.
+    pub identity: Option<PathBuf>,
+    /// SSH private key file given with `--ssh-key`.
+    pub ssh_key: Option<PathBuf>,
+    /// Whether `--ssh-agent` is given.
+    pub ssh_agent: bool,
+    /// Whether `--allow-rollback` is given.
+    pub allow_rollback: bool,
+    /// Minimum strength of new passwords, given with `--min-strength`.
+    pub min_strength: u8,
+}
+
 /// Create and save new database.
-pub fn create<P: AsRef<Path>>(db_path: &P) -> Result<()> {
+///
+/// If keyfile is given, the database requires it in addition to the password,
+/// or instead of the password if it is left empty.
+pub fn create<P: AsRef<Path>>(db_path: &P, unlock: &Unlock) -> Result<()> {
     if db_path.as_ref().is_file() {
         println!("The database is already exists.");
         if !dialoguer::Confirm::new()
@@ -40,16 +60,15 @@ pub fn create<P: AsRef<Path>>(db_path: &P) -> Result<()> {
     } else {
         println!("Create a new database.");
     }
-    let password: String = dialoguer::Password::new()
-        .with_prompt("Please enter password for new database")
-        .with_confirmation("Confirm new password", "Passwords don't match.")
-        .interact()
-        .unwrap();
-    let db = database::TotpDatabase::new();
-    database::save_database(&db, &db_path, &password).context(format!(
-        "Failed to save database to {}",
-        db_path.as_ref().display()
-    ))?;
+    let key = input_new_key(
+        "Please enter password for new database",
+        unlock.keyfile.as_deref(),
+        unlock.min_strength,
+        &database::KdfParams::default(),
+    )?;
+    let mut keyring = keyslot::Keyring::new(&key)?;
+    let db = database::LazyDatabase::seal(&database::TotpDatabase::new(), &keyring)?;
+    save_database(&db, db_path, &mut keyring)?;
     println!(
         "New database file is successfuly created: {}.",
         db_path.as_ref().display()
@@ -57,43 +76,60 @@ pub fn create<P: AsRef<Path>>(db_path: &P) -> Result<()> {
     Ok(())
 }
 
-/// Change password of database.
-pub fn change_password<P: AsRef<Path>>(db_path: &P) -> Result<()> {
-    let password: String = dialoguer::Password::new()
-        .with_prompt("Current database password")
-        .interact()
-        .unwrap();
-    let db = database::load_database(&db_path, &password)?;
-    let password: String = dialoguer::Password::new()
-        .with_prompt("New password")
-        .with_confirmation("Confirm new password", "Passwords don't match.")
-        .interact()
-        .unwrap();
-    database::save_database(&db, &db_path, &password).context(format!(
-        "Failed to save database to {}",
-        db_path.as_ref().display()
-    ))?;
+/// Change password of the key slot which unlocks the database.
+///
+/// The keyfile is kept unless `new_keyfile` is given or `remove_keyfile` is true.
+pub fn change_password<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    new_keyfile: Option<&Path>,
+    remove_keyfile: bool,
+) -> Result<()> {
+    use database::Factor::{Keyfile, Password};
+    let (db, mut keyring) = load_database(db_path, unlock)?;
+    if let Some(slot) = keyring.unlocked_slot() {
+        ensure!(
+            keyring.slots()[slot]
+                .factors()
+                .iter()
+                .all(|f| *f == Password || *f == Keyfile),
+            "The key slot {} has no password. Please use `slot add` to add one.",
+            keyring.slots()[slot]
+        );
+    }
+    let keyfile = match remove_keyfile {
+        true => None,
+        false => new_keyfile.or(unlock.keyfile.as_deref()),
+    };
+    let key = input_new_key(
+        "New password",
+        keyfile,
+        unlock.min_strength,
+        &keyring.change_key_kdf(),
+    )?;
+    keyring.change_key(&key)?;
+    save_database(&db, db_path, &mut keyring)?;
     println!("Password is successfully changed.");
     Ok(())
 }
 
 /// Add an entry to database.
 ///
+/// The entry is taken from `otpauth://` URI if `uri` is true, otherwise input interactively.
+/// An entry whose name is already taken is added with a number after the name.
+/// If `protect` is true, the entry is protected by its own passphrase.
 /// If database file doesn't exist, then create new one with user's permission.
-pub fn add<P: AsRef<Path>>(db_path: &P, base32_encode: bool) -> Result<()> {
-    let db_path = db_path.as_ref();
-    let (mut db, password) = match db_path.is_file() {
-        true => {
-            let password: String = dialoguer::Password::new()
-                .with_prompt("Database password")
-                .interact()
-                .unwrap();
-            let db = database::load_database(&db_path, &password).context(format!(
-                "Failed to load database from {}.",
-                db_path.display()
-            ))?;
-            (db, password)
-        }
+pub fn add<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    base32_encode: bool,
+    uri: bool,
+    qr_image: Option<&Path>,
+    protect: bool,
+) -> Result<()> {
+    let scanned = qr_image.map(read_qr_image).transpose()?;
+    let (mut db, mut keyring) = match db_path.as_ref().is_file() {
+        true => load_database(db_path, unlock)?,
         false => {
             println!("Database file does not exist.");
             if dialoguer::Confirm::new()
@@ -102,34 +138,168 @@ pub fn add<P: AsRef<Path>>(db_path: &P, base32_encode: bool) -> Result<()> {
                 .interact()
                 .unwrap()
             {
-                let password: String = dialoguer::Password::new()
-                    .with_prompt("Please enter password for new database")
-                    .with_confirmation("Confirm new password", "Passwords don't match.")
-                    .interact()
-                    .unwrap();
-                let db = database::TotpDatabase::new();
-                (db, password)
+                let key = input_new_key(
+                    "Please enter password for new database",
+                    unlock.keyfile.as_deref(),
+                    unlock.min_strength,
+                    &database::KdfParams::default(),
+                )?;
+                let keyring = keyslot::Keyring::new(&key)?;
+                let db = database::LazyDatabase::seal(&database::TotpDatabase::new(), &keyring)?;
+                (db, keyring)
             } else {
                 return Ok(());
             }
         }
     };
+    let mut entries: Vec<(String, database::Entry)> = match (scanned, uri) {
+        (Some(accounts), _) => accounts
+            .into_iter()
+            .map(|(label, client)| {
+                let label = unique_name(&label, |n| db.kind(n).is_some());
+                (input_name(&label), client)
+            })
+            .collect(),
+        (None, true) => vec![input_uri()?],
+        (None, false) => vec![input_entry(base32_encode)?],
+    };
+    for (name, client) in &mut entries {
+        let unique = unique_name(name, |n| db.kind(n).is_some());
+        if unique != *name {
+            println!(
+                "Entry named {} does already exist, so it is added as {}.",
+                name, unique
+            );
+            *name = unique;
+        }
+        match protect {
+            true => {
+                let passphrase = input_new_password(
+                    &format!("Passphrase of {}", name),
+                    false,
+                    unlock.min_strength,
+                    &database::KdfParams::default(),
+                );
+                db.insert_protected(name, client, &passphrase)?;
+            }
+            false => db.insert(name, client)?,
+        }
+    }
+    save_database(&db, db_path, &mut keyring)?;
+    for (name, _) in &entries {
+        println!("Success to add item: {}", name);
+    }
+    Ok(())
+}
+
+/// Read accounts from otpauth or migration URIs in the QR codes of the image.
+///
+/// Unsupported QR codes and accounts are skipped with messages.
+fn read_qr_image(path: &Path) -> Result<Vec<(String, database::Entry)>> {
+    let texts = scan::scan_file(&path)
+        .context(format!("Failed to read QR codes from {}.", path.display()))?;
+    ensure!(
+        !texts.is_empty(),
+        "No QR code is found in {}.",
+        path.display()
+    );
+    let mut accounts = Vec::new();
+    for text in &texts {
+        if import::is_migration_uri(text) {
+            for account in import::parse_migration_uri(text)? {
+                match account.client {
+                    Ok(client) => accounts.push((account.label, client.into())),
+                    Err(e) => println!("Skipped account {}: {}", account.label, e),
+                }
+            }
+            continue;
+        }
+        match uri::parse(text) {
+            Ok(parsed) => accounts.push((parsed.label, parsed.client.into())),
+            Err(e) => println!("Skipped QR code which is not otpauth URI: {}", e),
+        }
+    }
+    ensure!(!accounts.is_empty(), "No account is found in the QR codes.");
+    println!("Found {} accounts in {}.", accounts.len(), path.display());
+    Ok(accounts)
+}
+
+/// Return the name, or the name followed by the smallest number from 2 which is not taken.
+fn unique_name(name: &str, taken: impl Fn(&str) -> bool) -> String {
+    match taken(name) {
+        false => name.to_string(),
+        true => (2..)
+            .map(|i| format!("{} ({})", name, i))
+            .find(|n| !taken(n))
+            .expect("Some number is not taken."),
+    }
+}
+
+fn input_name(initial: &str) -> String {
+    dialoguer::Input::new()
+        .with_prompt("Name")
+        .with_initial_text(initial)
+        .interact_text()
+        .unwrap()
+}
+
+/// Input a new entry interactively.
+///
+/// The secret key of mOTP is always hex-encoded, and that of Yandex.Key is always base32-encoded,
+/// as these services provide. The secret key of S/KEY is its pass phrase.
+fn input_entry(base32_encode: bool) -> Result<(String, database::Entry)> {
     let name: String = dialoguer::Input::new()
         .with_prompt("Name")
         .interact_text()
         .unwrap();
-    ensure!(
-        !db.contains_key(&name),
-        "Entry named {} does already exist in the database",
-        &name
-    );
-    let key = dialoguer::Password::new()
-        .with_prompt("Secret key")
+    let key = input_password("Secret key");
+    let types = vec!["TOTP", "Steam Guard", "OCRA", "mOTP", "Yandex.Key", "S/KEY"];
+    let selection = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .items(&types)
+        .default(0)
         .interact()
         .unwrap();
+    match types[selection] {
+        "Steam Guard" => {
+            let key = decode_key(&key, base32_encode)?;
+            return Ok((name, otp::TotpClient::new_steam(key).into()));
+        }
+        "OCRA" => {
+            let key = decode_key(&key, base32_encode)?;
+            return Ok((name, input_ocra(key)?.into()));
+        }
+        "mOTP" => {
+            let pin = input_pin();
+            return Ok((
+                name,
+                motp::MotpClient::new(key.to_string(), pin.to_string())?.into(),
+            ));
+        }
+        "Yandex.Key" => {
+            let pin = input_pin();
+            return Ok((
+                name,
+                yandex::YandexClient::from_base32key(&key, pin.to_string())?.into(),
+            ));
+        }
+        "S/KEY" => return Ok((name, input_skey(key.to_string())?.into())),
+        &_ => {}
+    }
+    let key = decode_key(&key, base32_encode)?;
+    Ok((name, input_totp(key)?.into()))
+}
+
+/// Input parameters of TOTP entry interactively.
+fn input_totp(key: Vec<u8>) -> Result<otp::TotpClient> {
     let timestep: u64 = dialoguer::Input::new()
         .with_prompt("Time step")
         .default(30)
+        .validate_with(|input: &u64| -> Result<(), &str> {
+            match *input > 0 {
+                true => Ok(()),
+                false => Err("Please input positive number."),
+            }
+        })
         .interact_text()
         .unwrap();
     let t0: u64 = dialoguer::Input::new()
@@ -149,7 +319,7 @@ pub fn add<P: AsRef<Path>>(db_path: &P, base32_encode: bool) -> Result<()> {
         })
         .interact_text()
         .unwrap();
-    let hashtypes = vec!["SHA-1", "SHA-256", "SHA-512"];
+    let hashtypes = vec!["SHA-1", "SHA-256", "SHA-512", "SHA3-256", "SHA3-512"];
     let selection = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
         .items(&hashtypes)
         .default(0)
@@ -159,69 +329,1035 @@ pub fn add<P: AsRef<Path>>(db_path: &P, base32_encode: bool) -> Result<()> {
         "SHA-1" => otp::HashType::Sha1,
         "SHA-256" => otp::HashType::Sha256,
         "SHA-512" => otp::HashType::Sha512,
+        "SHA3-256" => otp::HashType::Sha3_256,
+        "SHA3-512" => otp::HashType::Sha3_512,
         &_ => otp::HashType::Sha1,
     };
-    let client = match base32_encode {
-        true => otp::TotpClient::from_base32key(key, timestep, t0, digit, hashtype)?,
-        false => otp::TotpClient::new(key.as_bytes().to_vec(), timestep, t0, digit, hashtype),
+    let encodings = vec!["Decimal", "Hexadecimal", "Custom alphabet"];
+    let selection = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .items(&encodings)
+        .default(0)
+        .interact()
+        .unwrap();
+    let encoding = match encodings[selection] {
+        "Hexadecimal" => otp::Encoding::Hex,
+        "Custom alphabet" => {
+            let alphabet: String = dialoguer::Input::new()
+                .with_prompt("Alphabet")
+                .validate_with(|input: &String| -> Result<(), String> {
+                    otp::Alphabet::new(input)
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                })
+                .interact_text()
+                .unwrap();
+            otp::Encoding::Alphabet(otp::Alphabet::new(&alphabet)?)
+        }
+        &_ => otp::Encoding::Decimal,
     };
-    db.insert(name.clone(), client);
-    database::save_database(&db, &db_path, &password)
-        .context(format!("Failed to save database to {}", db_path.display()))?;
-    println!("Success to add item: {}", name);
-    Ok(())
+    Ok(otp::TotpClient::new(key, timestep, t0, digit, hashtype).with_encoding(encoding))
 }
 
-/// Remove an entry from database.
-pub fn remove<P: AsRef<Path>>(db_path: &P, name: &str) -> Result<()> {
-    let password: String = dialoguer::Password::new()
-        .with_prompt("Database password")
+/// Input parameters of OCRA entry interactively.
+fn input_ocra(key: Vec<u8>) -> Result<ocra::OcraClient> {
+    let suite: String = dialoguer::Input::new()
+        .with_prompt("OCRA suite")
+        .default("OCRA-1:HOTP-SHA1-6:QN08".to_string())
+        .validate_with(|input: &String| -> Result<(), String> {
+            input
+                .parse::<ocra::OcraSuite>()
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .interact_text()
+        .unwrap();
+    let suite: ocra::OcraSuite = suite.parse()?;
+    let counter: u64 = match suite.uses_counter() {
+        true => dialoguer::Input::new()
+            .with_prompt("Counter")
+            .default(0)
+            .interact_text()
+            .unwrap(),
+        false => 0,
+    };
+    let pin: Option<String> = suite.pin_hashtype().map(|_| input_pin().to_string());
+    Ok(ocra::OcraClient::new(key, suite, counter, pin))
+}
+
+/// Input parameters of S/KEY entry interactively.
+fn input_skey(passphrase: String) -> Result<skey::SkeyClient> {
+    let algorithms = vec!["MD5", "SHA-1"];
+    let selection = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .items(&algorithms)
+        .default(0)
         .interact()
         .unwrap();
-    let mut db = database::load_database(db_path, &password).context(format!(
+    let algorithm = match algorithms[selection] {
+        "SHA-1" => skey::SkeyAlgorithm::Sha1,
+        &_ => skey::SkeyAlgorithm::Md5,
+    };
+    let seed: String = dialoguer::Input::new()
+        .with_prompt("Seed")
+        .interact_text()
+        .unwrap();
+    let sequence: u32 = dialoguer::Input::new()
+        .with_prompt("Sequence number")
+        .interact_text()
+        .unwrap();
+    skey::SkeyClient::new(algorithm, passphrase, &seed, sequence)
+}
+
+/// Load database with its keyring, asking the password if it is required.
+///
+/// The entries are left encrypted until they are used.
+fn load_database<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+) -> Result<(database::LazyDatabase, keyslot::Keyring)> {
+    let db_path = db_path.as_ref();
+    let accepted = database::accepted_factors(&db_path).context(format!(
         "Failed to load database from {}.",
-        db_path.as_ref().display()
+        db_path.display()
     ))?;
-    db.remove(name);
-    database::save_database(&db, db_path, &password).context(format!(
+    let key = input_key(&accepted, unlock)?;
+    load_database_with_key(db_path, &key, unlock.allow_rollback)
+}
+
+/// Load database with its keyring, unlocking it with the key.
+fn load_database_with_key(
+    db_path: &Path,
+    key: &database::CompositeKey,
+    allow_rollback: bool,
+) -> Result<(database::LazyDatabase, keyslot::Keyring)> {
+    let (db, keyring) = database::open_database_lazy(&db_path, key).context(format!(
+        "Failed to load database from {}.",
+        db_path.display()
+    ))?;
+    check_revision(db_path, &keyring, allow_rollback)?;
+    Ok((db, keyring))
+}
+
+/// Decrypt the entry, asking its passphrase if it is protected.
+///
+/// The passphrase is returned too, to encrypt the entry again after it is updated.
+fn get_entry(
+    db: &database::LazyDatabase,
+    name: &str,
+) -> Result<(database::Entry, Option<Zeroizing<String>>)> {
+    ensure!(
+        db.kind(name).is_some(),
+        "Entry named {} does not exist in the database",
+        name
+    );
+    let (entry, passphrase) = match db.is_protected(name) {
+        true => {
+            let passphrase = input_password(&format!("Passphrase of {}", name));
+            (db.get_protected(name, &passphrase)?, Some(passphrase))
+        }
+        false => (db.get(name)?, None),
+    };
+    Ok((entry.expect("Entry of the name exists."), passphrase))
+}
+
+/// Refuse the database older than the one loaded last time, unless rollback is allowed.
+fn check_revision(db_path: &Path, keyring: &keyslot::Keyring, allow_rollback: bool) -> Result<()> {
+    let store = match revision_store() {
+        Some(store) => store,
+        None => return Ok(()),
+    };
+    if let revision::RevisionCheck::Rollback { seen } = store.check(keyring)? {
+        let message = format!(
+            "{} is revision {}, older than revision {} loaded last time. \
+             It may have been replaced with an old copy by an attacker or a stale sync.",
+            db_path.display(),
+            keyring.revision(),
+            seen
+        );
+        ensure!(
+            allow_rollback,
+            "{} If you are sure it is the right one, run again with --allow-rollback.",
+            message
+        );
+        eprintln!("WARNING: {}", message);
+        return store.accept_rollback(keyring);
+    }
+    store.update(keyring)
+}
+
+fn revision_store() -> Option<revision::RevisionStore> {
+    match revision::RevisionStore::default_path() {
+        Ok(path) => Some(revision::RevisionStore::new(&path)),
+        Err(e) => {
+            eprintln!("Warning: {} Rollback of the database is not detected.", e);
+            None
+        }
+    }
+}
+
+/// Input the factors to unlock any of the accepted sets of factors.
+///
+/// If keyfile is given, the sets with it are used, preferring the one without password.
+fn input_key(
+    accepted: &[Vec<database::Factor>],
+    unlock: &Unlock,
+) -> Result<database::CompositeKey> {
+    use database::Factor::{Identity, Keyfile, Password, SshKey};
+    if unlock.ssh_key.is_some() || unlock.ssh_agent {
+        ensure!(
+            accepted.iter().any(|a| a == &[SshKey]),
+            "The database has no key slot of SSH key."
+        );
+        let signer = match &unlock.ssh_key {
+            Some(path) => read_ssh_private_key(path)?,
+            None => ssh::SshSigner::agent()?,
+        };
+        return Ok(database::CompositeKey::default().with_ssh_signer(signer));
+    }
+    if let Some(identity) = &unlock.identity {
+        ensure!(
+            accepted.iter().any(|a| a == &[Identity]),
+            "The database has no age recipient to unlock with identity."
+        );
+        return database::CompositeKey::default().with_identity_file(identity);
+    }
+    let candidates: &[&[database::Factor]] = match unlock.keyfile {
+        Some(_) => &[&[Keyfile], &[Password, Keyfile]],
+        None => &[&[Password]],
+    };
+    let factors = candidates
+        .iter()
+        .find(|c| accepted.iter().any(|a| a == *c))
+        .context(match unlock.keyfile {
+            Some(_) => "The database can not be unlocked with keyfile.",
+            None => "The database requires keyfile. Please give it with --keyfile.",
+        })?;
+    let mut key = database::CompositeKey::default();
+    if factors.contains(&Password) {
+        key = key.with_password(&input_password("Database password"));
+    }
+    if let Some(keyfile) = &unlock.keyfile {
+        key = key.with_keyfile(keyfile)?;
+    }
+    Ok(key)
+}
+
+fn read_ssh_private_key(path: &Path) -> Result<ssh::SshSigner> {
+    let passphrase = match ssh::is_encrypted_private_key(&path)? {
+        true => Some(input_password("Passphrase of the SSH key")),
+        false => None,
+    };
+    ssh::SshSigner::from_private_key_file(&path, passphrase.as_deref().map(|p| p.as_str()))
+}
+
+fn save_database<P: AsRef<Path>>(
+    db: &database::LazyDatabase,
+    db_path: &P,
+    keyring: &mut keyslot::Keyring,
+) -> Result<()> {
+    database::save_lazy_database_with_keyring(db, db_path, keyring).context(format!(
         "Failed to save database to {}",
         db_path.as_ref().display()
     ))?;
-    println!("Success to remove item: {}", name);
-    Ok(())
+    match revision_store() {
+        Some(store) => store.update(keyring),
+        None => Ok(()),
+    }
+}
+
+/// Input new password, and combine it with the keyfile if given.
+///
+/// The password can be left empty only if the keyfile is given.
+///
+/// The minimum strength is not required if the keyfile is given,
+/// which makes the key unguessable by itself.
+/// The key is to be derived with `kdf`, which the cost to crack it is estimated for.
+fn input_new_key(
+    prompt: &str,
+    keyfile: Option<&Path>,
+    min_strength: u8,
+    kdf: &database::KdfParams,
+) -> Result<database::CompositeKey> {
+    let min_strength = match keyfile {
+        Some(_) => 0,
+        None => min_strength,
+    };
+    let password = input_new_password(prompt, keyfile.is_some(), min_strength, kdf);
+    let mut key = database::CompositeKey::default();
+    if !password.is_empty() {
+        key = key.with_password(&password);
+    }
+    if let Some(keyfile) = keyfile {
+        key = key.with_keyfile(&keyfile)?;
+    }
+    Ok(key)
+}
+
+/// Input password, which is zeroized when dropped.
+fn input_password(prompt: &str) -> Zeroizing<String> {
+    Zeroizing::new(
+        dialoguer::Password::new()
+            .with_prompt(prompt)
+            .interact()
+            .unwrap(),
+    )
+}
+
+/// Input new password with confirmation, which is zeroized when dropped.
+///
+/// Its strength is shown before confirmation, and it is asked again
+/// if it is weaker than `min_strength` (see [strength::estimate]).
+fn input_new_password(
+    prompt: &str,
+    allow_empty: bool,
+    min_strength: u8,
+    kdf: &database::KdfParams,
+) -> Zeroizing<String> {
+    loop {
+        let password = Zeroizing::new(
+            dialoguer::Password::new()
+                .with_prompt(prompt)
+                .allow_empty_password(allow_empty)
+                .interact()
+                .unwrap(),
+        );
+        if password.is_empty() {
+            return password;
+        }
+        let strength = strength::estimate(&password);
+        report_strength(&strength, kdf);
+        if strength.score < min_strength {
+            println!(
+                "The password is too weak. Strength {} or more is required.",
+                min_strength
+            );
+            continue;
+        }
+        let confirmation = input_password("Confirm new password");
+        if *confirmation == *password {
+            return password;
+        }
+        println!("Passwords don't match.");
+    }
+}
+
+/// Show the strength of new password, and the cost to crack it offline
+/// when it is derived with `params`.
+fn report_strength(strength: &strength::Strength, params: &database::KdfParams) {
+    const LABELS: [&str; 5] = [
+        "too guessable",
+        "very guessable",
+        "somewhat guessable",
+        "safely unguessable",
+        "very unguessable",
+    ];
+    println!(
+        "Strength: {}/4 ({})",
+        strength.score, LABELS[strength.score as usize]
+    );
+    if let Some(warning) = strength.warning {
+        println!("{}", warning);
+    }
+    let seconds = strength::crack_seconds(strength.guesses, params);
+    println!(
+        "Estimated time to crack it offline with Argon2id (m={} KiB, t={}, p={}): {} with a GPU, {} with 1000 GPUs.",
+        params.m_cost,
+        params.t_cost,
+        params.p_cost,
+        strength::format_duration(seconds),
+        strength::format_duration(seconds / 1000.0)
+    );
 }
 
-/// Show present TOTP value of entry.
-pub fn show<P: AsRef<Path>>(db_path: &P, name: &str) -> Result<()> {
-    let password: String = dialoguer::Password::new()
-        .with_prompt("Database password")
+/// Input PIN, which is zeroized when dropped.
+fn input_pin() -> Zeroizing<String> {
+    Zeroizing::new(
+        dialoguer::Password::new()
+            .with_prompt("PIN")
+            .interact()
+            .unwrap(),
+    )
+}
+
+fn decode_key(key: &str, base32_encode: bool) -> Result<Vec<u8>> {
+    Ok(match base32_encode {
+        true => data_encoding::BASE32
+            .decode(key.as_bytes())
+            .context("Failed to decode base32-encoded key.")?,
+        false => key.as_bytes().to_vec(),
+    })
+}
+
+/// Input a new entry as `otpauth://` URI.
+fn input_uri() -> Result<(String, database::Entry)> {
+    let uri: String = dialoguer::Password::new()
+        .with_prompt("otpauth URI")
         .interact()
         .unwrap();
-    let db = database::load_database(db_path, &password).context(format!(
+    let parsed = uri::parse(&uri)?;
+    Ok((input_name(&parsed.label), parsed.client.into()))
+}
+
+/// Remove an entry from database.
+pub fn remove<P: AsRef<Path>>(db_path: &P, unlock: &Unlock, name: &str) -> Result<()> {
+    let (mut db, mut keyring) = load_database(db_path, unlock)?;
+    db.remove(name);
+    save_database(&db, db_path, &mut keyring)?;
+    println!("Success to remove item: {}", name);
+    Ok(())
+}
+
+/// Protect the entry with a new passphrase, or remove the protection if `remove` is true.
+///
+/// The current passphrase is asked if the entry is already protected.
+pub fn protect<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    name: &str,
+    remove: bool,
+) -> Result<()> {
+    let (mut db, mut keyring) = load_database(db_path, unlock)?;
+    ensure!(
+        !remove || db.kind(name).is_none() || db.is_protected(name),
+        "Entry named {} is not protected.",
+        name
+    );
+    let (entry, _) = get_entry(&db, name)?;
+    match remove {
+        true => db.insert(name, &entry)?,
+        false => {
+            let passphrase = input_new_password(
+                &format!("New passphrase of {}", name),
+                false,
+                unlock.min_strength,
+                &database::KdfParams::default(),
+            );
+            db.insert_protected(name, &entry, &passphrase)?;
+        }
+    }
+    save_database(&db, db_path, &mut keyring)?;
+    match remove {
+        true => println!("Success to remove protection of item: {}", name),
+        false => println!("Success to protect item: {}", name),
+    }
+    Ok(())
+}
+
+/// Time window of TOTP value to be shown, relative to the given datetime.
+pub enum Window {
+    Previous,
+    Current,
+    Next,
+}
+
+/// Show TOTP value of entry at given datetime.
+///
+/// The passphrase of the entry is asked if it is protected.
+pub fn show<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    name: &str,
+    datetime: DateTime<Utc>,
+    window: Window,
+) -> Result<()> {
+    let (db, _) = load_database(db_path, unlock)?;
+    let kind = db.kind(name).context(format!(
+        "Entry named {} does not exist in the database",
+        name
+    ))?;
+    ensure!(
+        kind.is_time_based(),
+        "Entry named {} is not time-based. Use `respond` command instead.",
+        name
+    );
+    let (entry, _) = get_entry(&db, name)?;
+    let timestep = entry.timestep().context(format!(
+        "Entry named {} is not time-based. Use `respond` command instead.",
+        name
+    ))?;
+    let step = i64::try_from(timestep)
+        .ok()
+        .and_then(|s| s.checked_mul(1000))
+        .map(Duration::milliseconds);
+    let datetime = match window {
+        Window::Previous => step.and_then(|s| datetime.checked_sub_signed(s)),
+        Window::Current => Some(datetime),
+        Window::Next => step.and_then(|s| datetime.checked_add_signed(s)),
+    }
+    .context("Time window is out of range.")?;
+    let code = entry
+        .code(&datetime)
+        .context(format!("Failed to show TOTP value of {}.", name))?;
+    println!("{}", code);
+    Ok(())
+}
+
+/// Show response of challenge-response entry (OCRA or S/KEY) to the challenge.
+///
+/// OCRA entry requires the challenge question.
+/// S/KEY entry takes the challenge such as `otp-md5 487 dog2`,
+/// or uses its own sequence number if omitted.
+/// The challenge above its sequence number is refused,
+/// and the one skipping below it is responded only if confirmed.
+/// If the entry uses counter or sequence number, the updated one is saved to database.
+/// The passphrase of the entry is asked if it is protected.
+pub fn respond<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    name: &str,
+    challenge: Option<&str>,
+    session: Option<&str>,
+) -> Result<()> {
+    let (mut db, mut keyring) = load_database(db_path, unlock)?;
+    let (mut entry, passphrase) = get_entry(&db, name)?;
+    let (response, updated) = match &mut entry {
+        database::Entry::Ocra(client) => {
+            let question = challenge.context("OCRA entry requires challenge question.")?;
+            let session = match session {
+                Some(s) => Some(
+                    data_encoding::HEXLOWER_PERMISSIVE
+                        .decode(s.as_bytes())
+                        .context("Failed to decode hex-encoded session information.")?,
+                ),
+                None => None,
+            };
+            let response = client.respond(question, session.as_deref(), &Utc::now())?;
+            (response, client.suite().uses_counter())
+        }
+        database::Entry::Skey(client) => {
+            let challenge = match challenge {
+                Some(c) => Some(c.parse::<skey::SkeyChallenge>()?),
+                None => None,
+            };
+            if let (Some(challenge), Some(sequence)) = (&challenge, client.sequence()) {
+                if client.skips(challenge)
+                    && !dialoguer::Confirm::new()
+                        .with_prompt(format!(
+                            "Sequence numbers {} to {} are skipped and can not be used. Continue?",
+                            challenge.sequence + 1,
+                            sequence
+                        ))
+                        .interact()
+                        .unwrap()
+                {
+                    return Ok(());
+                }
+            }
+            (client.respond(challenge.as_ref())?, true)
+        }
+        _ => bail!("Entry named {} is not challenge-response.", name),
+    };
+    if updated {
+        match passphrase {
+            Some(passphrase) => db.insert_protected(name, &entry, &passphrase)?,
+            None => db.insert(name, &entry)?,
+        }
+        save_database(&db, db_path, &mut keyring)?;
+    }
+    println!("{}", response);
+    Ok(())
+}
+
+/// Show list of entry names.
+pub fn list<P: AsRef<Path>>(db_path: &P, unlock: &Unlock) -> Result<()> {
+    let (db, _) = load_database(db_path, unlock)?;
+    for name in db.names() {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Show list of key slots.
+///
+/// Key slots are shown without unlocking the database.
+pub fn list_slots<P: AsRef<Path>>(db_path: &P) -> Result<()> {
+    let slots = database::key_slots(db_path).context(format!(
         "Failed to load database from {}.",
         db_path.as_ref().display()
     ))?;
-    let client = &db[name];
+    if slots.is_empty() {
+        println!("The database has no key slots yet. It will have one when it is saved next.");
+    }
+    for (i, slot) in slots.iter().enumerate() {
+        println!("{}: {}", i, slot);
+    }
+    Ok(())
+}
+
+/// Add a key slot which unlocks the database with new password, and keyfile if given.
+pub fn add_slot<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    name: &str,
+    new_keyfile: Option<&Path>,
+) -> Result<()> {
+    let (db, mut keyring) = load_database(db_path, unlock)?;
+    let key = input_new_key(
+        "Password for the new key slot",
+        new_keyfile,
+        unlock.min_strength,
+        &database::KdfParams::default(),
+    )?;
+    keyring.add_slot(name, &key)?;
+    save_database(&db, db_path, &mut keyring)?;
+    println!("Success to add key slot: {}", name);
+    Ok(())
+}
+
+/// Add a key slot unlocked by an ed25519 SSH key.
+///
+/// The key is read from `private_key`, or else ssh-agent is asked to sign.
+/// `public_key` chooses the key from the ones in ssh-agent.
+pub fn add_ssh_slot<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    name: &str,
+    private_key: Option<&Path>,
+    public_key: Option<&Path>,
+) -> Result<()> {
+    let (db, mut keyring) = load_database(db_path, unlock)?;
+    let signer = match private_key {
+        Some(path) => read_ssh_private_key(path)?,
+        None => ssh::SshSigner::agent()?,
+    };
+    let mut available = signer.public_keys()?;
+    let public_key = match public_key {
+        Some(path) => {
+            let public_key = ssh::SshPublicKey::from_file(&path)?;
+            ensure!(
+                available.contains(&public_key),
+                "The SSH key {} is not available.",
+                public_key.fingerprint()
+            );
+            public_key
+        }
+        None => match available.len() {
+            0 => bail!("ssh-agent has no ed25519 key."),
+            1 => available.remove(0),
+            _ => bail!("ssh-agent has several ed25519 keys. Please choose one with --public-key."),
+        },
+    };
+    keyring.add_ssh_key(name, &signer, &public_key)?;
+    save_database(&db, db_path, &mut keyring)?;
     println!(
-        "{:0>digit$}",
-        client.totp(&Utc::now()),
-        digit = *client.digit() as usize
+        "Success to add key slot: {} ({})",
+        name,
+        public_key.fingerprint()
     );
     Ok(())
 }
 
-/// Show list of entry names.
-pub fn list<P: AsRef<Path>>(db_path: &P) -> Result<()> {
-    let password: String = dialoguer::Password::new()
-        .with_prompt("Database password")
+/// Revoke the key slot.
+pub fn remove_slot<P: AsRef<Path>>(db_path: &P, unlock: &Unlock, index: usize) -> Result<()> {
+    let (db, mut keyring) = load_database(db_path, unlock)?;
+    let slot = keyring
+        .slots()
+        .get(index)
+        .context(format!("Key slot {} does not exist.", index))?;
+    if keyring.unlocked_slot() == Some(index) {
+        println!(
+            "The key slot {} is the one you have just unlocked with.",
+            slot
+        );
+    }
+    if !dialoguer::Confirm::new()
+        .with_prompt(format!("Revoke key slot {}?", slot))
         .interact()
-        .unwrap();
-    let db = database::load_database(db_path, &password).context(format!(
+        .unwrap()
+    {
+        return Ok(());
+    }
+    let slot = keyring.remove_slot(index)?;
+    save_database(&db, db_path, &mut keyring)?;
+    println!("Success to revoke key slot: {}", slot);
+    Ok(())
+}
+
+/// Set the duress password, which opens the decoy database instead of this one.
+///
+/// The decoy database is empty, and its entries are added by unlocking it with the duress password.
+pub fn set_decoy<P: AsRef<Path>>(db_path: &P, unlock: &Unlock) -> Result<()> {
+    let (db, mut keyring) = load_database(db_path, unlock)?;
+    if keyring.has_decoy()
+        && !dialoguer::Confirm::new()
+            .with_prompt("The decoy database is replaced with an empty one. Continue?")
+            .interact()
+            .unwrap()
+    {
+        return Ok(());
+    }
+    let duress = input_new_key(
+        "Duress password",
+        None,
+        unlock.min_strength,
+        &database::KdfParams::default(),
+    )?;
+    keyring.set_decoy(&duress)?;
+    save_database(&db, db_path, &mut keyring)?;
+    println!("Success to set duress password.");
+    println!("Please add entries to the decoy database, unlocking it with the duress password.");
+    Ok(())
+}
+
+/// Remove the decoy database and its duress password.
+pub fn remove_decoy<P: AsRef<Path>>(db_path: &P, unlock: &Unlock) -> Result<()> {
+    let (db, mut keyring) = load_database(db_path, unlock)?;
+    ensure!(keyring.has_decoy(), "The database has no decoy.");
+    if !dialoguer::Confirm::new()
+        .with_prompt("Remove the decoy database?")
+        .interact()
+        .unwrap()
+    {
+        return Ok(());
+    }
+    keyring.remove_decoy();
+    save_database(&db, db_path, &mut keyring)?;
+    println!("Success to remove the decoy database.");
+    Ok(())
+}
+
+/// Add a recovery slot, and print the shares of its key, any `threshold` of which unlock it.
+///
+/// The recovery slot of the same name is replaced, which revokes its old shares.
+pub fn split_recovery<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    name: &str,
+    shares: u8,
+    threshold: u8,
+) -> Result<()> {
+    recovery::check_threshold(threshold, shares)?;
+    let (db, mut keyring) = load_database(db_path, unlock)?;
+    if let Some(index) = keyring.slots().iter().position(|s| s.name() == name) {
+        ensure!(
+            keyring.slots()[index].factors() == [database::Factor::Recovery],
+            "Key slot named {} is not a recovery slot.",
+            name
+        );
+        if !dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "Revoke the shares of the recovery slot {} and split a new key?",
+                name
+            ))
+            .interact()
+            .unwrap()
+        {
+            return Ok(());
+        }
+        keyring.remove_slot(index)?;
+    }
+    let recovery_key = keyring.add_recovery_key(name)?;
+    let split = recovery::split(recovery_key.expose(), threshold, shares)?;
+    save_database(&db, db_path, &mut keyring)?;
+    println!("Success to add recovery slot: {}", name);
+    println!(
+        "Give each share to a different person, printed or written down. They are not shown again."
+    );
+    println!(
+        "Any {} of them recover access to the database with `recovery combine`.",
+        threshold
+    );
+    for share in split {
+        println!();
+        println!("Share {} of {}:", share.index(), shares);
+        println!("{}", share);
+    }
+    Ok(())
+}
+
+/// Reconstruct the recovery key from the shares, and add a key slot with new password.
+pub fn combine_recovery<P: AsRef<Path>>(db_path: &P, unlock: &Unlock, name: &str) -> Result<()> {
+    let db_path = db_path.as_ref();
+    let accepted = database::accepted_factors(&db_path).context(format!(
         "Failed to load database from {}.",
-        db_path.as_ref().display()
+        db_path.display()
     ))?;
-    for name in db.keys() {
-        println!("{}", name);
+    ensure!(
+        accepted.iter().any(|a| a == &[database::Factor::Recovery]),
+        "The database has no recovery slot."
+    );
+    let mut shares: Vec<recovery::Share> = Vec::new();
+    loop {
+        let prompt = match shares.first() {
+            Some(first) => format!("Share ({} of {})", shares.len() + 1, first.threshold()),
+            None => "Share".to_string(),
+        };
+        match input_password(&prompt).parse::<recovery::Share>() {
+            Ok(share) => shares.push(share),
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        }
+        if shares.len() >= shares[0].threshold() as usize {
+            break;
+        }
+    }
+    let key = database::CompositeKey::default().with_recovery_key(recovery::combine(&shares)?);
+    let (db, mut keyring) = load_database_with_key(db_path, &key, unlock.allow_rollback)?;
+    let new_key = input_new_key(
+        "New password",
+        unlock.keyfile.as_deref(),
+        unlock.min_strength,
+        &database::KdfParams::default(),
+    )?;
+    keyring.add_slot(name, &new_key)?;
+    save_database(&db, &db_path, &mut keyring)?;
+    println!("Success to recover access. New key slot: {}", name);
+    Ok(())
+}
+
+/// Show otpauth URI of the TOTP entry as a QR code in the terminal, or save it into the files.
+///
+/// The QR code reveals the secret key, so it is shown only after confirmation.
+pub fn qr<P: AsRef<Path>, Q: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    name: &str,
+    outputs: &[Q],
+    invert: bool,
+) -> Result<()> {
+    let (db, _) = load_database(db_path, unlock)?;
+    ensure!(
+        db.kind(name)
+            .is_none_or(|kind| kind == database::EntryKind::Totp),
+        "Only TOTP entries can be shown as otpauth URI."
+    );
+    let (entry, _) = get_entry(&db, name)?;
+    let client = match &entry {
+        database::Entry::Totp(client) => client,
+        _ => bail!("Only TOTP entries can be shown as otpauth URI."),
+    };
+    let image = qr::QrImage::new(&uri::format(name, client)?)?;
+    let prompt = match outputs.is_empty() {
+        true => format!("Show the secret key of {} as a QR code?", name),
+        false => format!("Save the secret key of {} as QR code files?", name),
+    };
+    if !dialoguer::Confirm::new()
+        .with_prompt(prompt)
+        .default(false)
+        .interact()
+        .unwrap()
+    {
+        return Ok(());
+    }
+    if outputs.is_empty() {
+        print!("{}", image.to_terminal(invert));
+    }
+    for output in outputs {
+        image.save(output).context(format!(
+            "Failed to save QR code to {}.",
+            output.as_ref().display()
+        ))?;
+        println!("Success to save QR code to {}", output.as_ref().display());
+    }
+    Ok(())
+}
+
+/// Print the entries, or all of them if no name is given, into a new HTML file of paper backup.
+pub fn export_paper<P: AsRef<Path>, Q: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    output: &Q,
+    names: &[String],
+) -> Result<()> {
+    let (db, _) = load_database(db_path, unlock)?;
+    let names: Vec<String> = match names.is_empty() {
+        true => {
+            let mut names: Vec<String> = db.names().iter().map(|n| n.to_string()).collect();
+            names.sort();
+            names
+        }
+        false => names.to_vec(),
+    };
+    let mut sheets = Vec::new();
+    for name in &names {
+        let (entry, _) = get_entry(&db, name)?;
+        sheets.push(paper::Sheet::new(name, &entry)?);
+    }
+    let title = format!(
+        "Paper backup of {}, {}",
+        db_path
+            .as_ref()
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default(),
+        Utc::now().format("%Y-%m-%d")
+    );
+    paper::save_html(output, &title, &sheets).context(format!(
+        "Failed to save paper backup to {}.",
+        output.as_ref().display()
+    ))?;
+    println!(
+        "Success to print {} entries to {}. Print it, and delete the file.",
+        sheets.len(),
+        output.as_ref().display()
+    );
+    println!(
+        "The paper contains the secret keys in plain. Keep it as safe as the keys themselves."
+    );
+    Ok(())
+}
+
+/// Restore an entry from paper backup, by typing in its lines, the text of its QR code or its
+/// secret key.
+pub fn restore_paper<P: AsRef<Path>>(db_path: &P, unlock: &Unlock) -> Result<()> {
+    let (mut db, mut keyring) = load_database(db_path, unlock)?;
+    let mut reader = paper::SheetReader::default();
+    let (name, entry) = loop {
+        let prompt = match reader.total_lines() {
+            Some(total) => format!("Line {} of {}", reader.next_line(), total),
+            None => "Line 1, text of the QR code, or secret key".to_string(),
+        };
+        let input: Zeroizing<String> = Zeroizing::new(
+            dialoguer::Input::new()
+                .with_prompt(prompt)
+                .interact_text()
+                .unwrap(),
+        );
+        let is_line = input
+            .split_whitespace()
+            .next()
+            .is_some_and(|token| token.bytes().all(|b| b.is_ascii_digit()));
+        let result = match reader.total_lines() {
+            None if input.contains(':') => {
+                paper::decode(&input).map(|(name, entry)| Some((Some(name), entry)))
+            }
+            None if !is_line => input_paper_secret(&input).map(|entry| Some((None, entry))),
+            _ => reader
+                .push_line(&input)
+                .and_then(|_| match reader.is_complete() {
+                    true => reader
+                        .finish()
+                        .map(|(name, entry)| Some((Some(name), entry))),
+                    false => Ok(None),
+                }),
+        };
+        match result {
+            Ok(Some(restored)) => break restored,
+            Ok(None) => {}
+            Err(e) => println!("{}", e),
+        }
+    };
+    let name: String = match name {
+        Some(name) => dialoguer::Input::new()
+            .with_prompt("Name")
+            .default(name)
+            .interact_text()
+            .unwrap(),
+        None => dialoguer::Input::new()
+            .with_prompt("Name")
+            .interact_text()
+            .unwrap(),
+    };
+    ensure!(
+        db.kind(&name).is_none(),
+        "Entry named {} does already exist in the database",
+        &name
+    );
+    db.insert(&name, &entry)?;
+    save_database(&db, db_path, &mut keyring)?;
+    println!("Success to restore item: {}", name);
+    Ok(())
+}
+
+/// Input the parameters printed under the secret key of TOTP entry in paper backup.
+fn input_paper_secret(secret: &str) -> Result<database::Entry> {
+    let key = uri::decode_secret(secret)?;
+    let types = vec!["TOTP", "Steam Guard"];
+    let selection = dialoguer::Select::with_theme(&dialoguer::theme::ColorfulTheme::default())
+        .items(&types)
+        .default(0)
+        .interact()
+        .unwrap();
+    Ok(match types[selection] {
+        "Steam Guard" => otp::TotpClient::new_steam(key).into(),
+        &_ => input_totp(key)?.into(),
+    })
+}
+
+/// Add a key slot which encrypts the database key to the age recipient.
+pub fn add_recipient<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    recipient: &str,
+    name: Option<&str>,
+) -> Result<()> {
+    let (db, mut keyring) = load_database(db_path, unlock)?;
+    keyring.add_recipient(name.unwrap_or(recipient), recipient)?;
+    save_database(&db, db_path, &mut keyring)?;
+    println!("Success to add recipient: {}", recipient);
+    Ok(())
+}
+
+/// Revoke the key slot of the age recipient, given by the recipient or the slot name.
+pub fn remove_recipient<P: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    recipient: &str,
+) -> Result<()> {
+    let (db, mut keyring) = load_database(db_path, unlock)?;
+    let index = keyring
+        .slots()
+        .iter()
+        .position(|s| {
+            s.recipient().is_some() && (s.recipient() == Some(recipient) || s.name() == recipient)
+        })
+        .context(format!("Recipient {} does not exist.", recipient))?;
+    if !dialoguer::Confirm::new()
+        .with_prompt(format!("Revoke key slot {}?", keyring.slots()[index]))
+        .interact()
+        .unwrap()
+    {
+        return Ok(());
+    }
+    let slot = keyring.remove_slot(index)?;
+    save_database(&db, db_path, &mut keyring)?;
+    println!("Success to revoke recipient: {}", slot);
+    Ok(())
+}
+
+/// Generate a new keyfile.
+pub fn generate_keyfile<P: AsRef<Path>>(path: &P) -> Result<()> {
+    database::generate_keyfile(path).context(format!(
+        "Failed to generate keyfile {}.",
+        path.as_ref().display()
+    ))?;
+    println!(
+        "New keyfile is successfully generated: {}. Keep it safe and make a backup.",
+        path.as_ref().display()
+    );
+    Ok(())
+}
+
+/// Import Steam Guard entries from Steam Desktop Authenticator maFiles.
+///
+/// Entries whose name already exists in the database are skipped.
+pub fn import_sda<P: AsRef<Path>, Q: AsRef<Path>>(
+    db_path: &P,
+    unlock: &Unlock,
+    files: &[Q],
+) -> Result<()> {
+    let mut passkey: Option<Zeroizing<String>> = None;
+    let mut entries = Vec::new();
+    for file in files {
+        let file = file.as_ref();
+        if passkey.is_none() && import::is_sda_encrypted(&file)? {
+            passkey = Some(input_password("SDA passkey"));
+        }
+        entries.push(
+            import::read_sda_mafile(&file, passkey.as_deref().map(|p| p.as_str()))
+                .context(format!("Failed to import {}.", file.display()))?,
+        );
+    }
+    let (mut db, mut keyring) = load_database(db_path, unlock)?;
+    for (name, client) in entries {
+        if db.kind(&name).is_some() {
+            println!("Entry named {} does already exist. Skipped.", name);
+            continue;
+        }
+        db.insert(&name, &client.into())?;
+        println!("Success to import item: {}", name);
     }
+    save_database(&db, db_path, &mut keyring)?;
     Ok(())
 }
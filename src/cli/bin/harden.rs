@@ -0,0 +1,113 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Protection of the process holding decrypted secrets.
+//!
+//! Secrets in memory can leak through core dumps, swap, or `ptrace` by another process
+//! of the same user. [harden_process] closes these paths as far as the platform allows,
+//! and warns about each protection which can not be applied.
+//!
+//! Memory is locked with `mlockall`, so allocations beyond the limit of locked memory may fail.
+//! The soft limit is raised to the hard limit first to make that unlikely.
+
+/// Disable core dumps, forbid `ptrace` attachment and lock memory against swapping.
+///
+/// It must be called before any secret is read.
+pub fn harden_process() {
+    #[cfg(unix)]
+    if let Err(e) = disable_core_dump() {
+        warn(&format!("Failed to disable core dumps: {}", e));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        if let Err(e) = set_non_dumpable() {
+            warn(&format!("Failed to mark the process non-dumpable: {}", e));
+        }
+        if let Err(e) = raise_memlock_limit() {
+            warn(&format!(
+                "Failed to raise the limit of locked memory: {}",
+                e
+            ));
+        }
+        if let Err(e) = lock_memory() {
+            warn(&format!("Failed to lock memory: {}", e));
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    warn("Memory locking and ptrace protection are not supported on this platform");
+}
+
+fn warn(message: &str) {
+    eprintln!(
+        "Warning: {}. Secrets may leak out of this process.",
+        message
+    );
+}
+
+#[cfg(unix)]
+fn disable_core_dump() -> std::io::Result<()> {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid rlimit which lives during the call.
+    match unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Mark the process non-dumpable, which also forbids `ptrace` by non-root processes.
+#[cfg(target_os = "linux")]
+fn set_non_dumpable() -> std::io::Result<()> {
+    // SAFETY: PR_SET_DUMPABLE takes only an integer argument.
+    match unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Raise the soft limit of locked memory to the hard limit.
+#[cfg(target_os = "linux")]
+fn raise_memlock_limit() -> std::io::Result<()> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: `limit` is a valid rlimit to be written, and then to be read.
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut limit) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        limit.rlim_cur = limit.rlim_max;
+        if libc::setrlimit(libc::RLIMIT_MEMLOCK, &limit) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Lock all current and future pages of the process, so secrets are not swapped out.
+#[cfg(target_os = "linux")]
+fn lock_memory() -> std::io::Result<()> {
+    // SAFETY: mlockall takes only flags, and only keeps pages in memory.
+    match unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
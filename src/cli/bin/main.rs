@@ -31,10 +31,26 @@
 //!   ```sh
 //!   $ yatotp-cli -i [database file path] add -e
 //!   ```
+//!   Or you can input whole `otpauth://` URI (including Steam Guard one)
+//!   with switch `-u`:
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] add -u
+//!   ```
 //! - Show TOTP value of the entry.
 //!   ```sh
 //!   $ yatotp-cli -i [database file path] show [entry name]
 //!   ```
+//!   TOTP value at another datetime, or of the next/previous time window,
+//!   can be shown with `--at`, `--next` and `--previous` like this:
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] show [entry name] --at 2021-12-24T12:00:00Z --next
+//!   ```
+//! - Show OCRA or S/KEY response of the entry to the challenge.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] respond [entry name] [challenge]
+//!   ```
+//!   S/KEY challenge can be omitted, then the sequence number stored in the entry is used.
+//!   S/KEY challenge above that sequence number is refused, and the one below it asks to confirm.
 //! - List entries in database.
 //!   ```sh
 //!   $ yatotp-cli -i [database file path] list
@@ -43,14 +59,137 @@
 //!   ```sh
 //!   $ yatotp-cli -i [database file path] remove [entry name]
 //!   ```
+//! - Import Steam Guard accounts from Steam Desktop Authenticator maFiles.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] import --format sda [maFile paths]
+//!   ```
 //! - Change database password to new one.
 //!   ```sh
 //!   $ yatotp-cli -i [database file path] newpass
 //!   ```
+//! - Use a keyfile as a second factor, like KeePass.
+//!   ```sh
+//!   $ yatotp-cli keyfile generate [keyfile path]
+//!   $ yatotp-cli -i [database file path] -k [keyfile path] create
+//!   ```
+//!   Then every command on the database requires `-k [keyfile path]`.
+//!   If the password is left empty, the keyfile alone unlocks the database.
+//!   The keyfile can be replaced or removed with `newpass --new-keyfile [keyfile path]`
+//!   or `newpass --remove-keyfile`.
+//! - Manage key slots, so several passwords (and keyfiles) can open one database.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] slot list
+//!   $ yatotp-cli -i [database file path] slot add [slot name]
+//!   $ yatotp-cli -i [database file path] slot remove [slot index]
+//!   ```
+//!   The database is encrypted with a random master key, and each slot wraps it,
+//!   so slots are added or revoked without re-encrypting entries.
+//!   `newpass` changes the password of the slot you unlocked with.
+//!   Note that revoking a slot does not change the master key.
+//! - Encrypt the database to [age](https://age-encryption.org) recipients.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] recipients add [age1...] --name [slot name]
+//!   $ yatotp-cli -i [database file path] -I [identity file path] list
+//!   $ yatotp-cli -i [database file path] recipients remove [age1... or slot name]
+//!   ```
+//!   Each recipient gets a key slot, so its identity file, such as the one generated by
+//!   `age-keygen`, unlocks the database without password. Recipients are listed by `slot list`.
+//! - Unlock with an ed25519 SSH key, held by ssh-agent or in a private key file.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] slot add-ssh [slot name]
+//!   $ yatotp-cli -i [database file path] --ssh-agent list
+//!   $ yatotp-cli -i [database file path] -s ~/.ssh/id_ed25519 list
+//!   ```
+//!   `slot add-ssh` asks ssh-agent to sign, or reads the key given with `--private-key`.
+//!   If ssh-agent has several ed25519 keys, choose one with `--public-key [.pub file path]`.
+//!   The slot stores a random challenge, and the master key is wrapped under the hash of
+//!   its signature, so neither a password nor the private key itself is stored.
+//! - Detect rollback of the database file.
+//!   Every save, including changes of key slots, increments the revision encrypted in the file,
+//!   and the latest revision of each database is remembered in `yatotp/revisions.json`
+//!   in the local state directory.
+//!   If an older file is loaded, e.g. replaced by an attacker or a stale sync, it is refused.
+//!   The hash of the key slots is encrypted too, so a revoked slot put back into the file is refused.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] --allow-rollback list
+//!   ```
+//!   `--allow-rollback` loads it anyway with a warning, and accepts it as the latest one.
+//! - Each entry is encrypted separately.
+//!   `show`, `list` and `remove` decrypt only the entries they need,
+//!   so other secrets never appear in memory while a code is shown.
+//!   Older databases are converted to this format on the next save.
+//! - Protect an entry with its own passphrase.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] add --protect
+//!   $ yatotp-cli -i [database file path] protect [entry name]
+//!   ```
+//!   The passphrase is asked only when the code of the entry is shown,
+//!   in addition to the database password. `protect --remove` removes it.
+//! - Set a duress password, which opens a decoy database instead of the real one.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] decoy set
+//!   $ yatotp-cli -i [database file path] add  # Enter the duress password to add decoy entries.
+//!   ```
+//!   Every database file has a second payload of the same size as the real one,
+//!   filled with random bytes unless a decoy is set, so the file does not tell whether it has a decoy.
+//!   Both payloads are encrypted again on every save, whichever password is used.
+//!   `newpass`, `slot` and `decoy` work with the duress password too, for the decoy,
+//!   and the real payload is padded as the decoy grows. `decoy remove` removes it.
+//! - Check the strength of new passwords.
+//!   The strength of new passwords is estimated like zxcvbn, together with the time to crack them offline
+//!   with the parameters of Argon2id. Passwords weaker than the minimum are refused.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] --min-strength 3 newpass
+//!   ```
+//!   The minimum is from 0 (too guessable) to 4 (very unguessable), and defaults to 2.
+//!   It is not required if a keyfile is given.
+//! - Recover access with shares of a recovery key.
+//!   Add a recovery slot, and split its key into shares, any `threshold` of which recover access.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] recovery split --shares 5 --threshold 3
+//!   ```
+//!   Give each share to a different person. Splitting again replaces the recovery slot and revokes
+//!   the old shares. Combine the shares to add a key slot with new password.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] recovery combine
+//!   ```
+//! - Print a paper backup.
+//!   Entries are printed into an HTML file, each with a QR code. A TOTP entry is printed as its
+//!   otpauth URI in the QR code and its secret key in plain, which any authenticator app can restore.
+//!   Other entries, and TOTP entries which otpauth URI can not represent, are printed as numbered
+//!   lines of base32 text. Print it, or save it as PDF from a browser, and delete the file.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] paper export [HTML file path] [names of entries]
+//!   ```
+//!   All entries are printed if no name is given. The paper contains the secret keys in plain.
+//!   Restore an entry by typing in its lines, the text of its QR code, or its secret key and the
+//!   parameters printed under it. Each line has check characters, so a typo is found at the line.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] paper restore
+//!   ```
+//! - Move an entry to other apps with a QR code.
+//!   The otpauth URI of a TOTP entry is shown as a QR code in the terminal after confirmation,
+//!   since it reveals the secret key.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] qr [name]
+//!   ```
+//!   Give `--invert` for terminals of dark text on light background.
+//!   Give `-o [file path]` to save it into a new .png or .svg file instead.
+//! - Add entries from QR code images.
+//!   otpauth URIs and exports of Google Authenticator (otpauth-migration URIs) are read
+//!   from QR codes in a PNG or JPEG image, such as a screenshot.
+//!   ```sh
+//!   $ yatotp-cli -i [database file path] add --qr-image [image file path]
+//!   ```
+//!   Every account found is added after its name is confirmed; unsupported ones are skipped.
+//!   An account whose name is already taken is added with a number after the name, such as
+//!   `alice (2)`.
 
 mod cli;
+mod harden;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
@@ -59,7 +198,48 @@ struct Args {
     #[structopt(subcommand)]
     command: Command,
     #[structopt(short = "i", long = "database", parse(from_os_str))]
-    database: std::path::PathBuf,
+    database: Option<std::path::PathBuf>,
+    #[structopt(
+        short = "k",
+        long = "keyfile",
+        parse(from_os_str),
+        help = "Keyfile to unlock the database, or to be required by new database."
+    )]
+    keyfile: Option<std::path::PathBuf>,
+    #[structopt(
+        short = "I",
+        long = "identity",
+        parse(from_os_str),
+        conflicts_with = "keyfile",
+        help = "age identity file to unlock the database instead of password."
+    )]
+    identity: Option<std::path::PathBuf>,
+    #[structopt(
+        short = "s",
+        long = "ssh-key",
+        parse(from_os_str),
+        conflicts_with_all = &["keyfile", "identity", "ssh-agent"],
+        help = "ed25519 SSH private key file to unlock the database instead of password."
+    )]
+    ssh_key: Option<std::path::PathBuf>,
+    #[structopt(
+        long = "ssh-agent",
+        conflicts_with_all = &["keyfile", "identity"],
+        help = "Unlock the database with an ed25519 SSH key in ssh-agent."
+    )]
+    ssh_agent: bool,
+    #[structopt(
+        long = "allow-rollback",
+        help = "Load the database even if it is older than the one loaded last time."
+    )]
+    allow_rollback: bool,
+    #[structopt(
+        long = "min-strength",
+        default_value = "2",
+        possible_values = &["0", "1", "2", "3", "4"],
+        help = "Minimum strength of new passwords, from 0 (too guessable) to 4 (very unguessable)."
+    )]
+    min_strength: u8,
 }
 
 #[derive(StructOpt)]
@@ -70,32 +250,332 @@ enum Command {
     Add {
         #[structopt(short = "e", long, help = "Treat key as base32 encoded.")]
         base32_encode: bool,
+        #[structopt(
+            short = "u",
+            long,
+            conflicts_with = "base32-encode",
+            help = "Input entry as otpauth URI."
+        )]
+        uri: bool,
+        #[structopt(
+            long,
+            parse(from_os_str),
+            conflicts_with_all = &["base32-encode", "uri"],
+            help = "Add entries from otpauth or otpauth-migration URIs in QR codes of the PNG or JPEG image."
+        )]
+        qr_image: Option<std::path::PathBuf>,
+        #[structopt(long, help = "Protect the entry with its own passphrase.")]
+        protect: bool,
     },
     /// Remove specified entry from database.
     Remove {
         #[structopt(help = "Name of entry.")]
         name: String,
     },
+    /// Protect specified entry with its own passphrase, or change it.
+    Protect {
+        #[structopt(help = "Name of entry.")]
+        name: String,
+        #[structopt(long, help = "Remove the protection instead.")]
+        remove: bool,
+    },
     /// Show TOTP value of specified entry.
     Show {
         #[structopt(help = "Name of entry.")]
         name: String,
+        #[structopt(
+            long,
+            help = "Show TOTP value at given RFC 3339 datetime instead of now."
+        )]
+        at: Option<DateTime<Utc>>,
+        #[structopt(long, help = "Show TOTP value of the next time window.")]
+        next: bool,
+        #[structopt(
+            long,
+            conflicts_with = "next",
+            help = "Show TOTP value of the previous time window."
+        )]
+        previous: bool,
+    },
+    /// Show OCRA or S/KEY response of specified entry to the challenge.
+    Respond {
+        #[structopt(help = "Name of entry.")]
+        name: String,
+        #[structopt(help = "Challenge question, or S/KEY challenge such as \"otp-md5 487 dog2\".")]
+        challenge: Option<String>,
+        #[structopt(long, help = "Hex-encoded session information, if the suite requires.")]
+        session: Option<String>,
     },
     /// Print list of TOTP entries.
     List,
+    /// Show otpauth URI of specified TOTP entry as a QR code, to move it to other apps.
+    Qr {
+        #[structopt(help = "Name of entry.")]
+        name: String,
+        #[structopt(
+            short,
+            long,
+            parse(from_os_str),
+            help = "Save the QR code into new .png or .svg file instead of showing it."
+        )]
+        output: Vec<std::path::PathBuf>,
+        #[structopt(
+            long,
+            help = "Draw dark modules, for terminals of dark text on light background."
+        )]
+        invert: bool,
+    },
+    /// Import entries from other authenticator's files.
+    Import {
+        #[structopt(
+            short,
+            long,
+            possible_values = &["sda"],
+            help = "Format of files. `sda` for Steam Desktop Authenticator maFile."
+        )]
+        format: String,
+        #[structopt(help = "Files to import.", required = true, parse(from_os_str))]
+        files: Vec<std::path::PathBuf>,
+    },
     /// Change database password to new one.
-    Newpass,
+    Newpass {
+        #[structopt(long, parse(from_os_str), help = "Require this keyfile instead.")]
+        new_keyfile: Option<std::path::PathBuf>,
+        #[structopt(long, conflicts_with = "new-keyfile", help = "Stop requiring keyfile.")]
+        remove_keyfile: bool,
+    },
+    /// Manage key slots, each of which unlocks the database with its own credential.
+    Slot(SlotCommand),
+    /// Manage age recipients, whose identities unlock the database without password.
+    Recipients(RecipientsCommand),
+    /// Manage the decoy database, which is opened by a duress password.
+    Decoy(DecoyCommand),
+    /// Recover access to the database with shares of a recovery key.
+    Recovery(RecoveryCommand),
+    /// Print entries on paper as backup, and restore them.
+    Paper(PaperCommand),
+    /// Manage keyfiles.
+    Keyfile(KeyfileCommand),
+}
+
+#[derive(StructOpt)]
+enum SlotCommand {
+    /// Print list of key slots.
+    List,
+    /// Add a key slot with new password, and keyfile if given.
+    Add {
+        #[structopt(help = "Name of key slot.")]
+        name: String,
+        #[structopt(long, parse(from_os_str), help = "Keyfile required by the new slot.")]
+        new_keyfile: Option<std::path::PathBuf>,
+    },
+    /// Add a key slot unlocked by an ed25519 SSH key, held by ssh-agent or in a file.
+    AddSsh {
+        #[structopt(help = "Name of key slot.")]
+        name: String,
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "Private key file. If not given, ssh-agent is used."
+        )]
+        private_key: Option<std::path::PathBuf>,
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "Public key file to choose the key in ssh-agent."
+        )]
+        public_key: Option<std::path::PathBuf>,
+    },
+    /// Revoke a key slot.
+    Remove {
+        #[structopt(help = "Index of key slot shown by `slot list`.")]
+        index: usize,
+    },
+}
+
+#[derive(StructOpt)]
+enum PaperCommand {
+    /// Print entries into a new HTML file with QR codes, to be printed.
+    Export {
+        #[structopt(parse(from_os_str), help = "HTML file to be created.")]
+        output: std::path::PathBuf,
+        #[structopt(help = "Names of entries. All entries are printed if none is given.")]
+        names: Vec<String>,
+    },
+    /// Restore an entry by typing in its lines, the text of its QR code or its secret key.
+    Restore,
+}
+
+#[derive(StructOpt)]
+enum RecoveryCommand {
+    /// Add a recovery slot, and print the shares of its key.
+    Split {
+        #[structopt(long, help = "Number of shares.")]
+        shares: u8,
+        #[structopt(long, help = "Number of shares required to recover access.")]
+        threshold: u8,
+        #[structopt(
+            long,
+            default_value = "recovery",
+            help = "Name of the recovery slot. The slot of the same name is replaced."
+        )]
+        name: String,
+    },
+    /// Combine shares, and add a key slot with new password.
+    Combine {
+        #[structopt(long, default_value = "recovered", help = "Name of the new key slot.")]
+        name: String,
+    },
+}
+
+#[derive(StructOpt)]
+enum DecoyCommand {
+    /// Set the duress password, replacing the decoy database with an empty one.
+    Set,
+    /// Remove the decoy database and its duress password.
+    Remove,
+}
+
+#[derive(StructOpt)]
+enum RecipientsCommand {
+    /// Add an age X25519 recipient.
+    Add {
+        #[structopt(help = "Recipient (age1...), such as the one printed by `age-keygen`.")]
+        recipient: String,
+        #[structopt(long, help = "Name of key slot. Defaults to the recipient.")]
+        name: Option<String>,
+    },
+    /// Revoke an age recipient.
+    Remove {
+        #[structopt(help = "Recipient or name of its key slot.")]
+        recipient: String,
+    },
+}
+
+#[derive(StructOpt)]
+enum KeyfileCommand {
+    /// Generate a new random keyfile.
+    Generate {
+        #[structopt(help = "Path of the new keyfile.", parse(from_os_str))]
+        path: std::path::PathBuf,
+    },
 }
 
 fn main() -> Result<()> {
     let args = Args::from_args();
+    harden::harden_process();
+    if let Command::Keyfile(KeyfileCommand::Generate { path }) = &args.command {
+        return cli::generate_keyfile(path);
+    }
+    let database = args
+        .database
+        .context("Database file path is required. Please give it with -i.")?;
+    let unlock = cli::Unlock {
+        keyfile: args.keyfile,
+        identity: args.identity,
+        ssh_key: args.ssh_key,
+        ssh_agent: args.ssh_agent,
+        allow_rollback: args.allow_rollback,
+        min_strength: args.min_strength,
+    };
     match args.command {
-        Command::Create => cli::create(&args.database),
-        Command::Add { base32_encode } => cli::add(&args.database, base32_encode),
-        Command::Remove { name } => cli::remove(&args.database, &name),
-        Command::Show { name } => cli::show(&args.database, &name),
-        Command::List => cli::list(&args.database),
-        Command::Newpass => cli::change_password(&args.database),
+        Command::Create => cli::create(&database, &unlock),
+        Command::Add {
+            base32_encode,
+            uri,
+            qr_image,
+            protect,
+        } => cli::add(
+            &database,
+            &unlock,
+            base32_encode,
+            uri,
+            qr_image.as_deref(),
+            protect,
+        ),
+        Command::Remove { name } => cli::remove(&database, &unlock, &name),
+        Command::Protect { name, remove } => cli::protect(&database, &unlock, &name, remove),
+        Command::Show {
+            name,
+            at,
+            next,
+            previous,
+        } => {
+            let window = match (next, previous) {
+                (true, _) => cli::Window::Next,
+                (_, true) => cli::Window::Previous,
+                _ => cli::Window::Current,
+            };
+            cli::show(
+                &database,
+                &unlock,
+                &name,
+                at.unwrap_or_else(Utc::now),
+                window,
+            )
+        }
+        Command::Respond {
+            name,
+            challenge,
+            session,
+        } => cli::respond(
+            &database,
+            &unlock,
+            &name,
+            challenge.as_deref(),
+            session.as_deref(),
+        ),
+        Command::List => cli::list(&database, &unlock),
+        Command::Qr {
+            name,
+            output,
+            invert,
+        } => cli::qr(&database, &unlock, &name, &output, invert),
+        Command::Import { format, files } => match format.as_str() {
+            "sda" => cli::import_sda(&database, &unlock, &files),
+            _ => unreachable!("Format is validated by possible_values."),
+        },
+        Command::Newpass {
+            new_keyfile,
+            remove_keyfile,
+        } => cli::change_password(&database, &unlock, new_keyfile.as_deref(), remove_keyfile),
+        Command::Slot(SlotCommand::List) => cli::list_slots(&database),
+        Command::Slot(SlotCommand::Add { name, new_keyfile }) => {
+            cli::add_slot(&database, &unlock, &name, new_keyfile.as_deref())
+        }
+        Command::Slot(SlotCommand::AddSsh {
+            name,
+            private_key,
+            public_key,
+        }) => cli::add_ssh_slot(
+            &database,
+            &unlock,
+            &name,
+            private_key.as_deref(),
+            public_key.as_deref(),
+        ),
+        Command::Slot(SlotCommand::Remove { index }) => cli::remove_slot(&database, &unlock, index),
+        Command::Recipients(RecipientsCommand::Add { recipient, name }) => {
+            cli::add_recipient(&database, &unlock, &recipient, name.as_deref())
+        }
+        Command::Recipients(RecipientsCommand::Remove { recipient }) => {
+            cli::remove_recipient(&database, &unlock, &recipient)
+        }
+        Command::Decoy(DecoyCommand::Set) => cli::set_decoy(&database, &unlock),
+        Command::Decoy(DecoyCommand::Remove) => cli::remove_decoy(&database, &unlock),
+        Command::Recovery(RecoveryCommand::Split {
+            shares,
+            threshold,
+            name,
+        }) => cli::split_recovery(&database, &unlock, &name, shares, threshold),
+        Command::Recovery(RecoveryCommand::Combine { name }) => {
+            cli::combine_recovery(&database, &unlock, &name)
+        }
+        Command::Paper(PaperCommand::Export { output, names }) => {
+            cli::export_paper(&database, &unlock, &output, &names)
+        }
+        Command::Paper(PaperCommand::Restore) => cli::restore_paper(&database, &unlock),
+        Command::Keyfile(_) => unreachable!("Keyfile commands are handled above."),
     }?;
     Ok(())
 }
@@ -18,137 +18,1413 @@
 
 //! Save and load TOTP clients database file.
 //!
-//! Database file is encrypted with Argon2id and ChaCha20Poly1305.
-//! Salt for Argon2id and nonce for ChaCha20 is also attatched to database file.
+//! Database file is encrypted with XChaCha20Poly1305 under a random master key,
+//! which is wrapped in key slots (see [keyslot]).
+//! Each slot derives its key by Argon2id from password, keyfile, or both of them
+//! (see [CompositeKey]).
+//! Salt and parameters for Argon2id and nonce for XChaCha20 are also attatched to database file,
+//! and authenticated as associated data. Files of older format are still loaded.
+//!
+//! Every database file also has the payload of the decoy database (see [Keyring::set_decoy])
+//! with its hidden key slot, or random bytes of the same size if no decoy is set.
+//! Both payloads are padded to the same size, so the file does not tell whether it has a decoy.
+//! Every save encrypts both payloads again under fresh nonces and keeps the hidden slot,
+//! whichever of the databases is saved, so neither do two versions of the file.
 
+use crate::keyslot::{Decoy, KeySlot, Keyring};
+use crate::secret::{Secret, Zeroizing};
+use crate::ssh::SshSigner;
 use crate::*;
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Context, Result};
 use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
-    Algorithm, Argon2, ParamsBuilder, Version,
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        Salt, SaltString,
+    },
+    Algorithm, Argon2, Params, Version,
 };
-use chacha20poly1305::aead::{Aead, NewAead};
-use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
-use chrono::Utc;
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, XChaCha20Poly1305, XNonce};
+use chrono::{DateTime, Utc};
 use data_encoding::BASE64;
-use rand::distributions::Standard;
-use rand::{thread_rng, Rng};
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
 use serde_json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
 const CHACHA20_NONCE_LEN: usize = 12;
+const XCHACHA20_NONCE_LEN: usize = 24;
 const CHACHA20_KEY_LEN: usize = 32;
+/// Version of database files written by [save_database_with_keyring].
+///
+/// 1. ChaCha20Poly1305 with timestamp-based nonce, without associated data.
+/// 2. XChaCha20Poly1305 with random nonce, authenticating [PayloadHeader] as associated data.
+/// 3. The encrypted data holds the revision counter together with entries.
+/// 4. Each entry is encrypted separately under the entry key (see [LazyDatabase]).
+/// 5. The decoy payload is attached, and both payloads are padded.
+/// 6. Each payload is encrypted again under its outer key (see [seal_payload]).
+const FORMAT_VERSION: u32 = 6;
+const TAG_LEN: usize = 16;
+/// Size of the length of the inner payload in front of it (see [wrap_payload]).
+const LENGTH_PREFIX_LEN: usize = 4;
+/// The encrypted data is padded to a multiple of this size.
+const PADDING_BLOCK: usize = 16 * 1024;
+const ENTRY_KEY_CONTEXT: &[u8] = b"yatotp entry key";
+const OUTER_KEY_CONTEXT: &[u8] = b"yatotp outer key";
+const KEYFILE_LEN: usize = 64;
 
-/// The collection of TOTP clients.
-pub type TotpDatabase = std::collections::HashMap<String, otp::TotpClient>;
+/// The collection of OTP entries.
+pub type TotpDatabase = HashMap<String, Entry>;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct EncryptedDatabase {
+/// An entry of the database.
+///
+/// It is serialized with its kind in `type` field.
+/// A TOTP client without the field is also loaded, as older database files have.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase", from = "EntryFormat")]
+pub enum Entry {
+    /// Time-based OTP, including Steam Guard.
+    Totp(otp::TotpClient),
+    /// OCRA challenge-response.
+    Ocra(ocra::OcraClient),
+    /// Mobile-OTP.
+    Motp(motp::MotpClient),
+    /// Yandex.Key.
+    Yandex(yandex::YandexClient),
+    /// S/KEY challenge-response.
+    Skey(skey::SkeyClient),
+}
+
+/// Serialized form of [Entry], tagged or not.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EntryFormat {
+    Tagged(TaggedEntry),
+    Totp(otp::TotpClient),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum TaggedEntry {
+    Totp(otp::TotpClient),
+    Ocra(ocra::OcraClient),
+    Motp(motp::MotpClient),
+    Yandex(yandex::YandexClient),
+    Skey(skey::SkeyClient),
+}
+
+impl From<EntryFormat> for Entry {
+    fn from(format: EntryFormat) -> Entry {
+        match format {
+            EntryFormat::Tagged(TaggedEntry::Totp(client)) | EntryFormat::Totp(client) => {
+                Entry::Totp(client)
+            }
+            EntryFormat::Tagged(TaggedEntry::Ocra(client)) => Entry::Ocra(client),
+            EntryFormat::Tagged(TaggedEntry::Motp(client)) => Entry::Motp(client),
+            EntryFormat::Tagged(TaggedEntry::Yandex(client)) => Entry::Yandex(client),
+            EntryFormat::Tagged(TaggedEntry::Skey(client)) => Entry::Skey(client),
+        }
+    }
+}
+
+/// Kind of an entry, which is known without decrypting the entry.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    /// Time-based OTP, including Steam Guard.
+    Totp,
+    /// OCRA challenge-response.
+    Ocra,
+    /// Mobile-OTP.
+    Motp,
+    /// Yandex.Key.
+    Yandex,
+    /// S/KEY challenge-response.
+    Skey,
+}
+
+impl EntryKind {
+    /// Return whether the entry shows time-based codes.
+    pub fn is_time_based(&self) -> bool {
+        matches!(self, EntryKind::Totp | EntryKind::Motp | EntryKind::Yandex)
+    }
+}
+
+impl Entry {
+    /// Return the kind of the entry.
+    pub fn kind(&self) -> EntryKind {
+        match self {
+            Entry::Totp(_) => EntryKind::Totp,
+            Entry::Ocra(_) => EntryKind::Ocra,
+            Entry::Motp(_) => EntryKind::Motp,
+            Entry::Yandex(_) => EntryKind::Yandex,
+            Entry::Skey(_) => EntryKind::Skey,
+        }
+    }
+
+    /// Calculate the time-based code of given datetime.
+    ///
+    /// Returns error if the entry is not time-based, or has no code at the datetime.
+    pub fn code(&self, datetime: &DateTime<Utc>) -> Result<String> {
+        let code = match self {
+            Entry::Totp(client) => client.code(datetime),
+            Entry::Motp(client) => client.code(datetime),
+            Entry::Yandex(client) => client.code(datetime),
+            Entry::Ocra(_) | Entry::Skey(_) => bail!("Entry is not time-based."),
+        };
+        code.context(format!("Entry has no code at {}.", datetime.to_rfc3339()))
+    }
+
+    /// Return time step in seconds, if the entry is time-based.
+    pub fn timestep(&self) -> Option<u64> {
+        match self {
+            Entry::Totp(client) => Some(*client.timestep()),
+            Entry::Ocra(_) => None,
+            Entry::Motp(_) => Some(motp::MOTP_TIMESTEP),
+            Entry::Yandex(_) => Some(yandex::YANDEX_TIMESTEP),
+            Entry::Skey(_) => None,
+        }
+    }
+}
+
+impl From<otp::TotpClient> for Entry {
+    fn from(client: otp::TotpClient) -> Entry {
+        Entry::Totp(client)
+    }
+}
+
+impl From<ocra::OcraClient> for Entry {
+    fn from(client: ocra::OcraClient) -> Entry {
+        Entry::Ocra(client)
+    }
+}
+
+impl From<motp::MotpClient> for Entry {
+    fn from(client: motp::MotpClient) -> Entry {
+        Entry::Motp(client)
+    }
+}
+
+impl From<yandex::YandexClient> for Entry {
+    fn from(client: yandex::YandexClient) -> Entry {
+        Entry::Yandex(client)
+    }
+}
+
+impl From<skey::SkeyClient> for Entry {
+    fn from(client: skey::SkeyClient) -> Entry {
+        Entry::Skey(client)
+    }
+}
+
+/// A factor needed to unlock the database.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Factor {
+    /// Password typed by the user.
+    Password,
+    /// File whose content is used as a key, like KeePass keyfile.
+    Keyfile,
+    /// age identity file, whose recipient the master key is encrypted to.
+    Identity,
+    /// ed25519 SSH key, in a private key file or held by ssh-agent.
+    SshKey,
+    /// Recovery key reconstructed from shares (see [recovery](crate::recovery)).
+    Recovery,
+}
+
+/// Factors to unlock the database: password, keyfile, or both of them.
+///
+/// Like KeePass, SHA-256 hashes of the password and the keyfile content are concatenated
+/// and passed to Argon2id, so both are needed to derive the key.
+/// Password-only key passes the password as it is, so old database files are still loaded.
+///
+/// It can also hold [age] identities, which open the key slots encrypted to their recipients
+/// instead of password and keyfile, an [SshSigner] to open the slots of SSH keys,
+/// and the recovery key to open the recovery slot.
+///
+/// # Example
+///
+/// ```no_run
+/// # use yatotp::database::*;
+/// let key = CompositeKey::from_password("password").with_keyfile(&"key.txt").unwrap();
+/// let database = load_database_with_key(&"database.yatotp", &key).unwrap();
+/// ```
+#[derive(Default, Debug)]
+pub struct CompositeKey {
+    password: Option<Secret<String>>,
+    keyfile: Option<Secret<Vec<u8>>>,
+    identity: Option<Secret<String>>,
+    ssh: Option<SshSigner>,
+    recovery: Option<Secret<[u8; recovery::RECOVERY_KEY_LEN]>>,
+}
+
+impl CompositeKey {
+    /// Create a key with password only.
+    pub fn from_password(password: &str) -> CompositeKey {
+        CompositeKey::default().with_password(password)
+    }
+
+    /// Add password to the key.
+    pub fn with_password(mut self, password: &str) -> CompositeKey {
+        self.password = Some(Secret::new(password.to_string()));
+        self
+    }
+
+    /// Add keyfile to the key. Any file can be used as a keyfile, and its hash is held.
+    pub fn with_keyfile<P: AsRef<Path>>(mut self, path: &P) -> Result<CompositeKey> {
+        let content = Zeroizing::new(std::fs::read(path).context(format!(
+            "Failed to read keyfile {}.",
+            path.as_ref().display()
+        ))?);
+        ensure!(!content.is_empty(), "Keyfile is empty.");
+        self.keyfile = Some(Secret::new(Sha256::digest(content.as_slice()).to_vec()));
+        Ok(self)
+    }
+
+    /// Add age identity file to the key, such as the one generated by `age-keygen`.
+    pub fn with_identity_file<P: AsRef<Path>>(mut self, path: &P) -> Result<CompositeKey> {
+        let content = Secret::new(std::fs::read_to_string(path).context(format!(
+            "Failed to read identity file {}.",
+            path.as_ref().display()
+        ))?);
+        self.identity = Some(content);
+        ensure!(
+            !self.identities()?.is_empty(),
+            "Identity file has no identity."
+        );
+        Ok(self)
+    }
+
+    /// Add SSH key to the key, read from a private key file or held by ssh-agent.
+    pub fn with_ssh_signer(mut self, signer: SshSigner) -> CompositeKey {
+        self.ssh = Some(signer);
+        self
+    }
+
+    /// Add the recovery key reconstructed from shares by [recovery::combine].
+    pub fn with_recovery_key(
+        mut self,
+        key: Secret<[u8; recovery::RECOVERY_KEY_LEN]>,
+    ) -> CompositeKey {
+        self.recovery = Some(key);
+        self
+    }
+
+    /// Return the factors which the key consists of.
+    pub fn factors(&self) -> Vec<Factor> {
+        let mut factors = self.derivation_factors();
+        if self.identity.is_some() {
+            factors.push(Factor::Identity);
+        }
+        if self.ssh.is_some() {
+            factors.push(Factor::SshKey);
+        }
+        if self.recovery.is_some() {
+            factors.push(Factor::Recovery);
+        }
+        factors
+    }
+
+    /// Return the factors from which the key is derived by Argon2id.
+    pub(crate) fn derivation_factors(&self) -> Vec<Factor> {
+        let mut factors = Vec::new();
+        if self.password.is_some() {
+            factors.push(Factor::Password);
+        }
+        if self.keyfile.is_some() {
+            factors.push(Factor::Keyfile);
+        }
+        factors
+    }
+
+    /// Parse the identity file.
+    pub(crate) fn identities(&self) -> Result<Vec<Box<dyn age::Identity>>> {
+        Ok(match &self.identity {
+            Some(content) => {
+                age::IdentityFile::from_buffer(content.expose().as_bytes())?.into_identities()?
+            }
+            None => Vec::new(),
+        })
+    }
+
+    /// Return the SSH signer.
+    pub(crate) fn ssh_signer(&self) -> Option<&SshSigner> {
+        self.ssh.as_ref()
+    }
+
+    /// Return the recovery key.
+    pub(crate) fn recovery_key(&self) -> Option<&[u8; recovery::RECOVERY_KEY_LEN]> {
+        self.recovery.as_ref().map(|k| k.expose())
+    }
+
+    /// Combine the factors into the input of key derivation.
+    pub(crate) fn material(&self) -> Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(match (&self.password, &self.keyfile) {
+            (Some(password), None) => password.expose().as_bytes().to_vec(),
+            (password, Some(keyfile)) => {
+                let mut material = match password {
+                    Some(password) => Sha256::digest(password.expose().as_bytes()).to_vec(),
+                    None => Vec::new(),
+                };
+                material.extend_from_slice(keyfile.expose());
+                material
+            }
+            (None, None) => bail!("Neither password nor keyfile is given."),
+        }))
+    }
+}
+
+/// An entry encrypted separately, with its kind left readable in the index.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SealedEntry {
+    kind: EntryKind,
     nonce: String,
+    data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    protection: Option<EntryProtection>,
+}
+
+/// Salt and parameters of Argon2id to derive the key of a protected entry from its passphrase.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct EntryProtection {
+    kdf: KdfParams,
     salt: String,
+}
+
+/// The database whose entries are decrypted only when they are used.
+///
+/// Each entry is encrypted by XChaCha20Poly1305 under the entry key,
+/// which is derived from the master key by HMAC-SHA256, with its name as associated data.
+/// The index of the entries, i.e. their names and kinds, is encrypted as a whole
+/// together with the revision in the file.
+/// So the secret of an entry stays encrypted in memory until [LazyDatabase::get] is called.
+///
+/// A protected entry is encrypted under the key which is derived from both the entry key
+/// and its own passphrase, so it can be decrypted only by
+/// [LazyDatabase::get_protected] with the passphrase, in addition to the database key.
+///
+/// # Example
+///
+/// ```no_run
+/// # use yatotp::database::*;
+/// let (database, _) =
+///     open_database_lazy(&"database.yatotp", &CompositeKey::from_password("password")).unwrap();
+/// for name in database.names() {
+///     println!("{}", name);
+/// }
+/// let entry = database.get("alice@example.com").unwrap().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct LazyDatabase {
+    entries: HashMap<String, SealedEntry>,
+    entry_key: Secret<[u8; CHACHA20_KEY_LEN]>,
+}
+
+impl LazyDatabase {
+    /// Encrypt each entry of the database under the entry key of the keyring.
+    pub fn seal(database: &TotpDatabase, keyring: &Keyring) -> Result<LazyDatabase> {
+        let mut lazy = LazyDatabase {
+            entries: HashMap::new(),
+            entry_key: entry_key(keyring),
+        };
+        for (name, entry) in database {
+            lazy.insert(name, entry)?;
+        }
+        Ok(lazy)
+    }
+
+    /// Return the names of the entries.
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.keys().map(|n| n.as_str()).collect()
+    }
+
+    /// Return the kind of the entry, without decrypting it.
+    pub fn kind(&self, name: &str) -> Option<EntryKind> {
+        self.entries.get(name).map(|e| e.kind)
+    }
+
+    /// Return whether the entry is protected by its own passphrase.
+    pub fn is_protected(&self, name: &str) -> bool {
+        self.entries
+            .get(name)
+            .is_some_and(|e| e.protection.is_some())
+    }
+
+    /// Decrypt the entry. Returns `None` if there is no entry of the name.
+    ///
+    /// Fails if the entry is protected. Use [LazyDatabase::get_protected] for it.
+    pub fn get(&self, name: &str) -> Result<Option<Entry>> {
+        self.open(name, None)
+    }
+
+    /// Decrypt the entry with its passphrase if it is protected.
+    /// Returns `None` if there is no entry of the name.
+    ///
+    /// The passphrase is ignored if the entry is not protected.
+    pub fn get_protected(&self, name: &str, passphrase: &str) -> Result<Option<Entry>> {
+        self.open(name, Some(passphrase))
+    }
+
+    fn open(&self, name: &str, passphrase: Option<&str>) -> Result<Option<Entry>> {
+        let sealed = match self.entries.get(name) {
+            Some(sealed) => sealed,
+            None => return Ok(None),
+        };
+        let nonce = BASE64.decode(sealed.nonce.as_bytes())?;
+        ensure!(
+            nonce.len() == XCHACHA20_NONCE_LEN,
+            "Invalid nonce of entry."
+        );
+        let data = BASE64.decode(sealed.data.as_bytes())?;
+        let key = self.sealing_key(name, sealed.protection.as_ref(), passphrase)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(key.expose()));
+        let payload = Payload {
+            msg: &data,
+            aad: name.as_bytes(),
+        };
+        let serialized = match cipher.decrypt(XNonce::from_slice(&nonce), payload) {
+            Ok(d) => Zeroizing::new(d),
+            Err(_) if sealed.protection.is_some() => bail!(
+                "Decryption of entry {} failed. The passphrase may be wrong.",
+                name
+            ),
+            Err(e) => bail!("Decryption of entry {} failed: {}", name, e),
+        };
+        let entry = serde_json::from_slice::<Entry>(&serialized)?;
+        ensure!(
+            entry.kind() == sealed.kind,
+            "Kind of entry {} does not match.",
+            name
+        );
+        Ok(Some(entry))
+    }
+
+    /// Encrypt and insert the entry, replacing the one of the same name.
+    pub fn insert(&mut self, name: &str, entry: &Entry) -> Result<()> {
+        self.seal_entry(name, entry, None)
+    }
+
+    /// Encrypt and insert the entry protected by the passphrase,
+    /// replacing the one of the same name.
+    pub fn insert_protected(&mut self, name: &str, entry: &Entry, passphrase: &str) -> Result<()> {
+        let protection = EntryProtection {
+            kdf: KdfParams::default(),
+            salt: SaltString::generate(&mut OsRng).as_str().to_string(),
+        };
+        self.seal_entry(name, entry, Some((protection, passphrase)))
+    }
+
+    fn seal_entry(
+        &mut self,
+        name: &str,
+        entry: &Entry,
+        protection: Option<(EntryProtection, &str)>,
+    ) -> Result<()> {
+        let (protection, passphrase) = match protection {
+            Some((protection, passphrase)) => (Some(protection), Some(passphrase)),
+            None => (None, None),
+        };
+        let key = self.sealing_key(name, protection.as_ref(), passphrase)?;
+        let mut nonce = [0u8; XCHACHA20_NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let serialized = Zeroizing::new(serde_json::to_vec(entry)?);
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(key.expose()));
+        let payload = Payload {
+            msg: &serialized,
+            aad: name.as_bytes(),
+        };
+        let data = match cipher.encrypt(XNonce::from_slice(&nonce), payload) {
+            Ok(c) => c,
+            Err(e) => bail!("Encryption failed: {}", e),
+        };
+        self.entries.insert(
+            name.to_string(),
+            SealedEntry {
+                kind: entry.kind(),
+                nonce: BASE64.encode(&nonce),
+                data: BASE64.encode(&data),
+                protection,
+            },
+        );
+        Ok(())
+    }
+
+    /// Return the key to encrypt the entry.
+    ///
+    /// The key of a protected entry is HMAC-SHA256 of the key derived from the passphrase
+    /// by Argon2id, keyed with the entry key.
+    fn sealing_key(
+        &self,
+        name: &str,
+        protection: Option<&EntryProtection>,
+        passphrase: Option<&str>,
+    ) -> Result<Secret<[u8; CHACHA20_KEY_LEN]>> {
+        let (protection, passphrase) = match (protection, passphrase) {
+            (None, _) => return Ok(Secret::new(*self.entry_key.expose())),
+            (Some(protection), Some(passphrase)) => (protection, passphrase),
+            (Some(_), None) => bail!("Entry {} is protected by a passphrase.", name),
+        };
+        let salt = match SaltString::new(&protection.salt) {
+            Ok(s) => s,
+            Err(e) => bail!("Invalid salt of entry {}: {}", name, e),
+        };
+        let derived = derive_key(passphrase.as_bytes(), &salt, &protection.kdf)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.entry_key.expose())
+            .expect("HMAC accepts keys of any length.");
+        mac.update(derived.as_slice());
+        let mut key = Secret::new([0u8; CHACHA20_KEY_LEN]);
+        key.expose_mut()
+            .copy_from_slice(&mac.finalize().into_bytes());
+        Ok(key)
+    }
+
+    /// Remove the entry. Returns whether it existed.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    /// Decrypt all the entries.
+    ///
+    /// Fails if any entry is protected.
+    pub fn decrypt_all(&self) -> Result<TotpDatabase> {
+        let mut database = TotpDatabase::new();
+        for name in self.entries.keys() {
+            let entry = self.get(name)?.expect("Entry of the name exists.");
+            database.insert(name.clone(), entry);
+        }
+        Ok(database)
+    }
+}
+
+fn entry_key(keyring: &Keyring) -> Secret<[u8; CHACHA20_KEY_LEN]> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(keyring.master_key())
+        .expect("HMAC accepts keys of any length.");
+    mac.update(ENTRY_KEY_CONTEXT);
+    let mut key = Secret::new([0u8; CHACHA20_KEY_LEN]);
+    key.expose_mut()
+        .copy_from_slice(&mac.finalize().into_bytes());
+    key
+}
+
+/// Parameters of Argon2id to derive the key from [CompositeKey].
+///
+/// Files which do not record them use the default parameters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct KdfParams {
+    /// Memory size in KiB.
+    pub m_cost: u32,
+    /// Number of iterations.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> KdfParams {
+        KdfParams {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+fn default_factors() -> Vec<Factor> {
+    vec![Factor::Password]
+}
+
+fn legacy_version() -> u32 {
+    1
+}
+
+/// The data encrypted in the file since version 3.
+#[derive(Serialize, Deserialize)]
+struct Contents<D> {
+    revision: u64,
+    entries: D,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decoy: Option<Decoy>,
+    /// The outer key of the other payload in the file, or random bytes if it has no database.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    peer_key: Option<Secret<[u8; CHACHA20_KEY_LEN]>>,
+    /// The hash of the key slots saved together, so that a revoked slot put back is detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    slots_hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct EncryptedDatabase {
+    #[serde(flatten)]
+    header: PayloadHeader,
+    #[serde(flatten)]
+    keys: KeyHeader,
     encrypted_data: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decoy: Option<DecoyPayload>,
+}
+
+/// The payload of the decoy database and its hidden key slot,
+/// or random bytes of the same size in their place.
+#[derive(Serialize, Deserialize, Debug)]
+struct DecoyPayload {
+    slot: String,
+    nonce: String,
+    data: String,
+}
+
+/// How the data is encrypted, which is authenticated as associated data since version 2.
+///
+/// Key slots are not included. Each of them authenticates its own parameters instead,
+/// and the hash of the list of them is encrypted together with the entries (see [Contents]).
+#[derive(Serialize, Deserialize, Debug)]
+struct PayloadHeader {
+    #[serde(default = "legacy_version")]
+    version: u32,
+    nonce: String,
+}
+
+/// How the key of the encrypted data is given.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum KeyHeader {
+    /// Encrypted with the master key wrapped in key slots.
+    Slots { slots: Vec<KeySlot> },
+    /// Encrypted with the key derived from [CompositeKey] directly,
+    /// as files written before key slots were introduced.
+    Direct {
+        salt: String,
+        #[serde(default = "default_factors")]
+        factors: Vec<Factor>,
+    },
 }
 
 impl EncryptedDatabase {
-    fn new(nonce: &[u8], salt: String, encrypted_data: &[u8]) -> EncryptedDatabase {
-        let nonce = BASE64.encode(nonce);
-        let encrypted_data = BASE64.encode(encrypted_data);
-        EncryptedDatabase {
-            nonce,
-            salt,
-            encrypted_data,
+    fn read(path: &Path) -> Result<EncryptedDatabase> {
+        let mut f = BufReader::new(std::fs::File::open(path)?);
+        let mut enc_db = String::new();
+        f.read_to_string(&mut enc_db)?;
+        Ok(serde_json::from_str::<EncryptedDatabase>(&enc_db)?)
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let mut f = BufWriter::new(std::fs::File::create(path)?);
+        f.write_all(serde_json::to_string(self)?.as_bytes())?;
+        Ok(())
+    }
+
+    /// Encrypt the plaintext with the master key, together with the key slots and the hidden slot.
+    ///
+    /// The plaintext of the decoy is encrypted with its own master key if the keyring has it,
+    /// otherwise random bytes are written in its place. Both are padded to the same size.
+    fn seal(
+        plaintext: &[u8],
+        keyring: &Keyring,
+        hidden_slot: &[u8],
+        decoy_plaintext: Option<&[u8]>,
+    ) -> Result<EncryptedDatabase> {
+        let len =
+            padded_len(plaintext.len()).max(decoy_plaintext.map_or(0, |d| padded_len(d.len())));
+        let (nonce, encrypted) = seal_payload(&pad(plaintext, len), keyring.master_key())?;
+        let (decoy_nonce, decoy_data) = match (keyring.decoy(), decoy_plaintext) {
+            (Some(decoy), Some(decoy_plaintext)) => {
+                seal_payload(&pad(decoy_plaintext, len), decoy.master_key())?
+            }
+            _ => {
+                let mut data = vec![0u8; encrypted.len()];
+                OsRng.fill_bytes(&mut data);
+                (generate_nonce(), data)
+            }
+        };
+        Ok(EncryptedDatabase {
+            header: PayloadHeader {
+                version: FORMAT_VERSION,
+                nonce,
+            },
+            keys: KeyHeader::Slots {
+                slots: keyring.slots().to_vec(),
+            },
+            encrypted_data: BASE64.encode(&encrypted),
+            decoy: Some(DecoyPayload {
+                slot: BASE64.encode(hidden_slot),
+                nonce: decoy_nonce,
+                data: BASE64.encode(&decoy_data),
+            }),
+        })
+    }
+
+    /// Replace the decoy payload with the plaintext encrypted by the keyring of the decoy,
+    /// padded to the size of the other payload, which can not be changed without its key.
+    ///
+    /// The other payload is encrypted again under its outer key with a fresh nonce,
+    /// as it is when the real database is saved.
+    /// If the decoy grows beyond it, the other payload is padded under its outer key
+    /// to the same size, as both grow when the real database is saved.
+    fn seal_decoy(
+        &mut self,
+        plaintext: &[u8],
+        keyring: &Keyring,
+        hidden_slot: &[u8],
+    ) -> Result<()> {
+        let encrypted = BASE64.decode(self.encrypted_data.as_bytes())?;
+        let (nonce, data) = match self.header.version {
+            6 => {
+                let peer_key = keyring
+                    .peer_key()
+                    .context("The key of the other payload is not found.")?;
+                let inner = unwrap_payload(&self.header, &encrypted, peer_key)?;
+                let len = padded_len(plaintext.len())
+                    .max(inner.len().saturating_sub(XCHACHA20_NONCE_LEN + TAG_LEN));
+                let (peer_nonce, peer_data) =
+                    wrap_payload(&inner, peer_key, XCHACHA20_NONCE_LEN + len + TAG_LEN)?;
+                self.header.nonce = peer_nonce;
+                self.encrypted_data = BASE64.encode(&peer_data);
+                seal_payload(&pad(plaintext, len), keyring.master_key())?
+            }
+            // The payload of older versions has no outer layer to be padded under.
+            version => {
+                let len = encrypted.len().saturating_sub(TAG_LEN);
+                ensure!(
+                    plaintext.len() <= len,
+                    "The database is too large to be saved."
+                );
+                let header = PayloadHeader {
+                    version,
+                    nonce: generate_nonce(),
+                };
+                let data = encrypt_payload(&pad(plaintext, len), &header, keyring.master_key())?;
+                (header.nonce, data)
+            }
+        };
+        let decoy = self.decoy.as_mut().context("The database has no room.")?;
+        decoy.slot = BASE64.encode(hidden_slot);
+        decoy.nonce = nonce;
+        decoy.data = BASE64.encode(&data);
+        self.keys = KeyHeader::Slots {
+            slots: keyring.slots().to_vec(),
+        };
+        Ok(())
+    }
+
+    /// Decrypt the decoy payload with the master key of the decoy.
+    fn open_decoy(&self, key: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        let decoy = self.decoy.as_ref().context("The database has no decoy.")?;
+        let header = PayloadHeader {
+            version: self.header.version,
+            nonce: decoy.nonce.clone(),
+        };
+        let encrypted = BASE64.decode(decoy.data.as_bytes())?;
+        match header.version {
+            6 => open_payload(&header, &encrypted, key),
+            _ => decrypt_payload(&encrypted, &header, key),
+        }
+    }
+
+    /// Decrypt the data with the key, checking the header if the format authenticates it.
+    fn open(&self, key: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+        let encrypted = BASE64.decode(self.encrypted_data.as_bytes())?;
+        match self.header.version {
+            1 => {
+                let nonce = BASE64.decode(self.header.nonce.as_bytes())?;
+                ensure!(nonce.len() == CHACHA20_NONCE_LEN, "Invalid nonce.");
+                match ChaCha20Poly1305::new(Key::from_slice(key))
+                    .decrypt(Nonce::from_slice(&nonce), encrypted.as_slice())
+                {
+                    Ok(d) => Ok(Zeroizing::new(d)),
+                    Err(e) => bail!("Decryption failed: {}", e),
+                }
+            }
+            2..=5 => decrypt_payload(&encrypted, &self.header, key),
+            6 => open_payload(&self.header, &encrypted, key),
+            v => bail!(
+                "Database file version {} is not supported. Please update yatotp.",
+                v
+            ),
         }
     }
 }
 
-/// Encrypt and Save database to file.
+fn generate_nonce() -> String {
+    let mut nonce = [0u8; XCHACHA20_NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    BASE64.encode(&nonce)
+}
+
+/// Encrypt the payload by XChaCha20Poly1305, authenticating the header as associated data.
+fn encrypt_payload(plaintext: &[u8], header: &PayloadHeader, key: &[u8]) -> Result<Vec<u8>> {
+    let nonce = BASE64.decode(header.nonce.as_bytes())?;
+    let aad = serde_json::to_vec(header)?;
+    let payload = Payload {
+        msg: plaintext,
+        aad: &aad,
+    };
+    match XChaCha20Poly1305::new(Key::from_slice(key)).encrypt(XNonce::from_slice(&nonce), payload)
+    {
+        Ok(c) => Ok(c),
+        Err(e) => bail!("Encryption failed: {}", e),
+    }
+}
+
+/// Decrypt the payload by XChaCha20Poly1305, authenticating the header as associated data.
+fn decrypt_payload(
+    encrypted: &[u8],
+    header: &PayloadHeader,
+    key: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    let nonce = BASE64.decode(header.nonce.as_bytes())?;
+    ensure!(nonce.len() == XCHACHA20_NONCE_LEN, "Invalid nonce.");
+    let aad = serde_json::to_vec(header)?;
+    let payload = Payload {
+        msg: encrypted,
+        aad: &aad,
+    };
+    match XChaCha20Poly1305::new(Key::from_slice(key)).decrypt(XNonce::from_slice(&nonce), payload)
+    {
+        Ok(d) => Ok(Zeroizing::new(d)),
+        Err(e) => bail!("Decryption failed: {}", e),
+    }
+}
+
+/// Encrypt the payload under the master key, and then under its outer key.
 ///
-/// Given password is hashed with random generated salt by Argon2id to 32 byte,
-/// then passed to ChaCha20Poly1305 with nonce.
-/// The 12 byte nonce is concatnation of Unix millisecond time (8 byte) and random 4 bytes,
-/// because nonce must be Number used ONCE otherwise reused (or conflicted) nonce make attack easier.
+/// The nonce of the inner encryption is put in front of its ciphertext,
+/// and only the nonce of the outer one is left in the file.
+/// The outer encryption can be done again with a fresh nonce by whoever has the outer key,
+/// which the keyring of the decoy has for the real payload, without the master key.
+/// Returns the outer nonce and the encrypted payload.
+fn seal_payload(plaintext: &[u8], master_key: &[u8]) -> Result<(String, Vec<u8>)> {
+    let header = PayloadHeader {
+        version: FORMAT_VERSION,
+        nonce: generate_nonce(),
+    };
+    let mut inner = BASE64.decode(header.nonce.as_bytes())?;
+    inner.extend_from_slice(&encrypt_payload(plaintext, &header, master_key)?);
+    wrap_payload(&inner, outer_key(master_key).expose(), inner.len())
+}
+
+/// Decrypt the payload encrypted by [seal_payload].
+fn open_payload(
+    header: &PayloadHeader,
+    encrypted: &[u8],
+    master_key: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    let inner = unwrap_payload(header, encrypted, outer_key(master_key).expose())?;
+    ensure!(inner.len() >= XCHACHA20_NONCE_LEN, "Invalid payload.");
+    let (nonce, encrypted) = inner.split_at(XCHACHA20_NONCE_LEN);
+    let inner_header = PayloadHeader {
+        version: header.version,
+        nonce: BASE64.encode(nonce),
+    };
+    decrypt_payload(encrypted, &inner_header, master_key)
+}
+
+/// Encrypt the inner payload under the outer key with a fresh nonce.
 ///
-/// Then, JSON-serialized TotpDatabase is encrypted with this ChaCha20,
-/// and then base64-encoded nonce, salt, and encrypted database is saved in JSON file.
+/// The length of the inner payload is put in front of it,
+/// and zeroes are put after it up to `len`, which the outer key alone can pad it to.
+fn wrap_payload(inner: &[u8], outer_key: &[u8], len: usize) -> Result<(String, Vec<u8>)> {
+    let header = PayloadHeader {
+        version: FORMAT_VERSION,
+        nonce: generate_nonce(),
+    };
+    let mut framed = u32::try_from(inner.len())
+        .context("The database is too large to be saved.")?
+        .to_be_bytes()
+        .to_vec();
+    framed.extend_from_slice(inner);
+    framed.resize(LENGTH_PREFIX_LEN + len.max(inner.len()), 0);
+    let encrypted = encrypt_payload(&framed, &header, outer_key)?;
+    Ok((header.nonce, encrypted))
+}
+
+/// Decrypt the inner payload wrapped by [wrap_payload], without its padding.
+fn unwrap_payload(header: &PayloadHeader, encrypted: &[u8], outer_key: &[u8]) -> Result<Vec<u8>> {
+    let framed = decrypt_payload(encrypted, header, outer_key)?;
+    ensure!(framed.len() >= LENGTH_PREFIX_LEN, "Invalid payload.");
+    let (prefix, rest) = framed.split_at(LENGTH_PREFIX_LEN);
+    let len = u32::from_be_bytes(prefix.try_into()?) as usize;
+    ensure!(len <= rest.len(), "Invalid payload.");
+    Ok(rest[..len].to_vec())
+}
+
+/// Return the key of the outer encryption of the payload encrypted under the master key.
+fn outer_key(master_key: &[u8]) -> Secret<[u8; CHACHA20_KEY_LEN]> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(master_key).expect("HMAC accepts keys of any length.");
+    mac.update(OUTER_KEY_CONTEXT);
+    let mut key = Secret::new([0u8; CHACHA20_KEY_LEN]);
+    key.expose_mut()
+        .copy_from_slice(&mac.finalize().into_bytes());
+    key
+}
+
+/// Return the size which the plaintext of the length is padded to.
+fn padded_len(len: usize) -> usize {
+    len.div_ceil(PADDING_BLOCK).max(1) * PADDING_BLOCK
+}
+
+/// Pad the serialized JSON to the length with spaces, which are ignored when it is parsed.
+fn pad(plaintext: &[u8], len: usize) -> Zeroizing<Vec<u8>> {
+    let mut padded = Zeroizing::new(Vec::with_capacity(len));
+    padded.extend_from_slice(plaintext);
+    padded.resize(len.max(plaintext.len()), b' ');
+    padded
+}
+
+/// Encrypt and Save database to file with password.
+///
+/// See [save_database_with_keyring] for details.
 pub fn save_database<P: AsRef<Path>>(
     database: &TotpDatabase,
     path: &P,
     password: &str,
 ) -> Result<()> {
-    let path = path.as_ref();
-    let salt = SaltString::generate(&mut OsRng);
-    let mut argon2param = ParamsBuilder::new();
-    argon2param.output_len(CHACHA20_KEY_LEN).unwrap();
-    let hasher = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        argon2param.params().unwrap(),
+    save_database_with_key(database, path, &CompositeKey::from_password(password))
+}
+
+/// Encrypt and Save database to file with a new keyring which has only one slot for the key.
+///
+/// See [save_database_with_keyring] for details.
+pub fn save_database_with_key<P: AsRef<Path>>(
+    database: &TotpDatabase,
+    path: &P,
+    key: &CompositeKey,
+) -> Result<()> {
+    save_database_with_keyring(database, path, &mut Keyring::new(key)?)
+}
+
+/// Encrypt and Save database to file.
+///
+/// JSON-serialized TotpDatabase is encrypted by XChaCha20Poly1305 with the master key
+/// of the keyring and a random 24 byte nonce, which is long enough not to collide.
+/// The format version and the nonce are authenticated as associated data,
+/// and so is each key slot with its KDF parameters and salt when it unwraps the master key,
+/// so tampering with the header makes decryption fail.
+///
+/// The revision of the keyring is incremented and encrypted together with the entries,
+/// so that an older file substituted for the latest one can be detected
+/// (see [revision](crate::revision)).
+///
+/// Then, base64-encoded nonce and encrypted database is saved in JSON file
+/// together with the key slots, each of which wraps the master key.
+/// The plaintext is zeroized after encryption.
+///
+/// The decoy database in the file is kept if the keyring has it, and padded to the same size.
+/// If the keyring is opened by the duress key, only the decoy database is replaced,
+/// and the other payload is padded to its size if the decoy grows beyond it.
+/// Either way, both payloads are encrypted again under fresh nonces, and the hidden slot is kept.
+pub fn save_database_with_keyring<P: AsRef<Path>>(
+    database: &TotpDatabase,
+    path: &P,
+    keyring: &mut Keyring,
+) -> Result<()> {
+    save_lazy_database_with_keyring(&LazyDatabase::seal(database, keyring)?, path, keyring)
+}
+
+/// Save database whose entries are already encrypted, without decrypting them.
+///
+/// The database must be sealed with the same keyring.
+/// See [save_database_with_keyring] for details.
+pub fn save_lazy_database_with_keyring<P: AsRef<Path>>(
+    database: &LazyDatabase,
+    path: &P,
+    keyring: &mut Keyring,
+) -> Result<()> {
+    ensure!(
+        database.entry_key == entry_key(keyring),
+        "The database is sealed with another keyring."
     );
-    let key = hasher
-        .hash_password(password.as_bytes(), &salt)
-        .unwrap()
-        .hash
-        .unwrap();
-    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
-    let mut nonce = Utc::now().timestamp_millis().to_be_bytes().to_vec();
-    nonce.append(
-        &mut (thread_rng()
-            .sample_iter::<u8, Standard>(Standard)
-            .take(CHACHA20_NONCE_LEN - nonce.len())
-            .collect()),
+    let revision = keyring.revision() + 1;
+    let hidden_slot = keyring.hidden_slot().to_vec();
+    let enc_db = match keyring.is_decoy() {
+        true => {
+            let contents = Contents {
+                revision,
+                entries: &database.entries,
+                decoy: keyring.decoy().cloned(),
+                peer_key: keyring.peer_key().map(|k| Secret::new(*k)),
+                slots_hash: Some(slots_hash(keyring.slots())?),
+            };
+            let serialized = Zeroizing::new(serde_json::to_vec(&contents)?);
+            let mut enc_db = EncryptedDatabase::read(path.as_ref())?;
+            enc_db.seal_decoy(&serialized, keyring, &hidden_slot)?;
+            enc_db
+        }
+        false => {
+            let peer_key = match keyring.decoy() {
+                Some(decoy) => outer_key(decoy.master_key()),
+                None => {
+                    let mut random = Secret::new([0u8; CHACHA20_KEY_LEN]);
+                    OsRng.fill_bytes(random.expose_mut());
+                    random
+                }
+            };
+            let contents = Contents {
+                revision,
+                entries: &database.entries,
+                decoy: keyring.decoy().cloned(),
+                peer_key: Some(peer_key),
+                slots_hash: Some(slots_hash(keyring.slots())?),
+            };
+            let serialized = Zeroizing::new(serde_json::to_vec(&contents)?);
+            let decoy_plaintext = match keyring.decoy() {
+                Some(decoy) => Some(read_decoy(path.as_ref(), decoy, keyring)?),
+                None => None,
+            };
+            EncryptedDatabase::seal(
+                &serialized,
+                keyring,
+                &hidden_slot,
+                decoy_plaintext.as_ref().map(|p| p.as_slice()),
+            )?
+        }
+    };
+    enc_db.write(path.as_ref())?;
+    keyring.set_revision(revision);
+    Ok(())
+}
+
+/// Read the plaintext of the decoy database from the file, and give it the outer key
+/// of the real payload, which it needs to save the file, and the hash of the key slots.
+///
+/// An empty database is returned if the file does not have it yet, e.g. the decoy is just set.
+fn read_decoy(path: &Path, decoy: &Decoy, keyring: &Keyring) -> Result<Zeroizing<Vec<u8>>> {
+    let opened = match EncryptedDatabase::read(path) {
+        Ok(enc_db) => enc_db.open_decoy(decoy.master_key()).ok(),
+        Err(_) => None,
+    };
+    let mut contents = match opened {
+        Some(plaintext) => {
+            serde_json::from_slice::<Contents<HashMap<String, SealedEntry>>>(&plaintext)?
+        }
+        None => Contents {
+            revision: 0,
+            entries: HashMap::new(),
+            decoy: None,
+            peer_key: None,
+            slots_hash: None,
+        },
+    };
+    contents.peer_key = Some(outer_key(keyring.master_key()));
+    contents.slots_hash = Some(slots_hash(keyring.slots())?);
+    Ok(Zeroizing::new(serde_json::to_vec(&contents)?))
+}
+
+/// Save the key slots of the keyring to the database file, keeping the entries in it.
+///
+/// The data is encrypted again with the hash of the slots and the next revision,
+/// as [save_database_with_keyring] does.
+/// The hidden slot is saved too, which is changed by [Keyring::change_key]
+/// if the keyring is opened by the duress key.
+/// The file must be the one the keyring is opened from by [open_database],
+/// and must already have key slots.
+pub fn save_keyring<P: AsRef<Path>>(path: &P, keyring: &mut Keyring) -> Result<()> {
+    let enc_db = EncryptedDatabase::read(path.as_ref())?;
+    ensure!(
+        matches!(enc_db.keys, KeyHeader::Slots { .. }),
+        "The database has no key slots yet. Please save the whole database instead."
     );
-    let nonce = Nonce::from_slice(&nonce);
-    let serialized = serde_json::to_string(database)?;
-    let encrypted = match cipher.encrypt(nonce, serialized.as_bytes()) {
-        Ok(c) => c,
-        Err(e) => bail!("Encryption failed: {}", e),
+    let serialized = match keyring.is_decoy() {
+        true => enc_db.open_decoy(keyring.master_key())?,
+        false => enc_db.open(keyring.master_key())?,
     };
-    let mut f = BufWriter::new(std::fs::File::create(path)?);
-    let enc_db = EncryptedDatabase::new(nonce.as_slice(), salt.as_str().to_string(), &encrypted);
-    f.write_all(serde_json::to_string(&enc_db)?.as_bytes())?;
+    let database = match parse_contents(enc_db.header.version, &serialized)?.entries {
+        Entries::Plain(database) => LazyDatabase::seal(&database, keyring)?,
+        Entries::Sealed(entries) => LazyDatabase {
+            entries,
+            entry_key: entry_key(keyring),
+        },
+    };
+    save_lazy_database_with_keyring(&database, path, keyring)
+}
+
+/// Return the hash of the key slots, which is saved in the encrypted data.
+fn slots_hash(slots: &[KeySlot]) -> Result<String> {
+    Ok(BASE64.encode(&Sha256::digest(serde_json::to_vec(slots)?)))
+}
+
+/// Refuse the key slots other than the ones saved together with the data.
+///
+/// The slots may also be the ones saved with the decoy, whose keyring can change them too.
+fn check_slots(enc_db: &EncryptedDatabase, keyring: &Keyring, saved: Option<&str>) -> Result<()> {
+    let (slots, saved) = match (&enc_db.keys, saved) {
+        (KeyHeader::Slots { slots }, Some(saved)) => (slots, saved),
+        _ => return Ok(()),
+    };
+    let hash = slots_hash(slots)?;
+    if hash == saved {
+        return Ok(());
+    }
+    let saved_with_decoy = match (keyring.is_decoy(), keyring.decoy()) {
+        (false, Some(decoy)) => enc_db
+            .open_decoy(decoy.master_key())
+            .ok()
+            .and_then(|plaintext| {
+                serde_json::from_slice::<Contents<serde::de::IgnoredAny>>(&plaintext).ok()
+            })
+            .and_then(|contents| contents.slots_hash),
+        _ => None,
+    };
+    ensure!(
+        saved_with_decoy == Some(hash),
+        "The key slots are changed without unlocking the database. \
+         A revoked key slot may have been put back."
+    );
     Ok(())
 }
 
-/// Load and Decrypt database from file.
+/// Load and Decrypt database from file with password.
 ///
-/// Nonce and salt used to encrypt database when [save_database] is gained from database file.
+/// See [open_database] for details.
 pub fn load_database<P: AsRef<Path>>(path: &P, password: &str) -> Result<TotpDatabase> {
-    let path = path.as_ref();
-    let mut f = BufReader::new(std::fs::File::open(path)?);
-    let mut enc_db = String::new();
-    f.read_to_string(&mut enc_db)?;
-    let enc_db = serde_json::from_str::<EncryptedDatabase>(&enc_db)?;
-    let nonce = BASE64.decode(enc_db.nonce.as_bytes())?;
-    let nonce = Nonce::from_slice(&nonce);
-    let salt = SaltString::new(&enc_db.salt).unwrap();
-    let encrypted = BASE64.decode(enc_db.encrypted_data.as_bytes())?;
-    let mut argon2param = ParamsBuilder::new();
-    argon2param.output_len(CHACHA20_KEY_LEN).unwrap();
-    let hasher = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        argon2param.params().unwrap(),
-    );
-    let key = hasher
-        .hash_password(password.as_bytes(), &salt)
-        .unwrap()
-        .hash
-        .unwrap();
-    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.as_bytes()));
-    let serialized = match cipher.decrypt(nonce, encrypted.as_slice()) {
-        Ok(c) => c,
-        Err(e) => bail!("Decryption failed: {}", e),
+    load_database_with_key(path, &CompositeKey::from_password(password))
+}
+
+/// Load and Decrypt database from file with age identity file.
+///
+/// See [open_database] for details.
+pub fn load_database_with_identity<P: AsRef<Path>, Q: AsRef<Path>>(
+    path: &P,
+    identity_file: &Q,
+) -> Result<TotpDatabase> {
+    load_database_with_key(
+        path,
+        &CompositeKey::default().with_identity_file(identity_file)?,
+    )
+}
+
+/// Load and Decrypt database from file.
+///
+/// See [open_database] for details.
+pub fn load_database_with_key<P: AsRef<Path>>(
+    path: &P,
+    key: &CompositeKey,
+) -> Result<TotpDatabase> {
+    Ok(open_database(path, key)?.0)
+}
+
+/// Load and Decrypt database from file, and return it with its keyring.
+///
+/// All the entries are decrypted. Use [open_database_lazy] to decrypt only the required ones,
+/// which is also required to open databases with protected entries.
+/// The master key is unwrapped from any key slot which the key opens.
+/// Files without key slots are decrypted with the key directly,
+/// and a new keyring is returned for them, so they are upgraded when saved with it.
+/// The keyring also holds the revision of the file, which is checked against the revision
+/// seen last time by [RevisionStore](crate::revision::RevisionStore).
+/// The decrypted JSON is zeroized as soon as it is parsed.
+pub fn open_database<P: AsRef<Path>>(
+    path: &P,
+    key: &CompositeKey,
+) -> Result<(TotpDatabase, Keyring)> {
+    let (entries, keyring) = open_entries(path.as_ref(), key)?;
+    let database = match entries {
+        Entries::Plain(database) => database,
+        Entries::Sealed(entries) => LazyDatabase {
+            entries,
+            entry_key: entry_key(&keyring),
+        }
+        .decrypt_all()?,
+    };
+    Ok((database, keyring))
+}
+
+/// Load database from file, and return it with its keyring, leaving the entries encrypted.
+///
+/// Files written before entries were encrypted separately are decrypted as a whole,
+/// and sealed again in memory.
+/// See [open_database] for details.
+pub fn open_database_lazy<P: AsRef<Path>>(
+    path: &P,
+    key: &CompositeKey,
+) -> Result<(LazyDatabase, Keyring)> {
+    let (entries, keyring) = open_entries(path.as_ref(), key)?;
+    let database = match entries {
+        Entries::Plain(database) => LazyDatabase::seal(&database, &keyring)?,
+        Entries::Sealed(entries) => LazyDatabase {
+            entries,
+            entry_key: entry_key(&keyring),
+        },
     };
-    let serialized = String::from_utf8(serialized)?;
-    Ok(serde_json::from_str::<TotpDatabase>(&serialized)?)
+    Ok((database, keyring))
+}
+
+/// Entries in the encrypted data, which are encrypted again separately since version 4.
+enum Entries {
+    Plain(TotpDatabase),
+    Sealed(HashMap<String, SealedEntry>),
+}
+
+fn open_entries(path: &Path, key: &CompositeKey) -> Result<(Entries, Keyring)> {
+    let enc_db = EncryptedDatabase::read(path)?;
+    let hidden_slot = match &enc_db.decoy {
+        Some(decoy) => Some(BASE64.decode(decoy.slot.as_bytes())?),
+        None => None,
+    };
+    let (mut keyring, direct_key) = match &enc_db.keys {
+        KeyHeader::Slots { slots } => match Keyring::unlock(slots.clone(), key) {
+            Ok(keyring) => (keyring, None),
+            Err(e) => {
+                let hidden_slot = match &hidden_slot {
+                    Some(hidden_slot) => hidden_slot,
+                    None => return Err(e),
+                };
+                match Keyring::unlock_decoy(slots.clone(), hidden_slot, key)? {
+                    Some(keyring) => (keyring, None),
+                    None => return Err(e),
+                }
+            }
+        },
+        KeyHeader::Direct { salt, factors } => {
+            ensure!(
+                key.derivation_factors() == *factors,
+                "Database requires {:?} to unlock, but {:?} is given.",
+                factors,
+                key.factors()
+            );
+            let salt = match SaltString::new(salt) {
+                Ok(s) => s,
+                Err(e) => bail!("Invalid salt: {}", e),
+            };
+            (
+                Keyring::new(key)?,
+                Some(derive_key(&key.material()?, &salt, &KdfParams::default())?),
+            )
+        }
+    };
+    let serialized = match &direct_key {
+        Some(derived) => enc_db.open(derived.as_slice())?,
+        None if keyring.is_decoy() => enc_db.open_decoy(keyring.master_key())?,
+        None => match enc_db.open(keyring.master_key()) {
+            Ok(serialized) => serialized,
+            // Slots added by the keyring of the decoy open the decoy.
+            Err(e) => match enc_db.open_decoy(keyring.master_key()) {
+                Ok(serialized) => {
+                    keyring.set_is_decoy();
+                    serialized
+                }
+                Err(_) => return Err(e),
+            },
+        },
+    };
+    if let Some(hidden_slot) = hidden_slot {
+        keyring.load_hidden_slot(hidden_slot);
+    }
+    let contents = parse_contents(enc_db.header.version, &serialized)?;
+    keyring.set_revision(contents.revision);
+    if keyring.is_decoy() {
+        keyring.load_peer_key(contents.peer_key);
+    }
+    keyring.load_decoy(contents.decoy);
+    check_slots(&enc_db, &keyring, contents.slots_hash.as_deref())?;
+    Ok((contents.entries, keyring))
+}
+
+/// Parse the decrypted data of the format version.
+///
+/// Files of version 1 and 2 have only the entries, and revision 0 is given to them.
+fn parse_contents(version: u32, serialized: &[u8]) -> Result<Contents<Entries>> {
+    Ok(match version {
+        1 | 2 => Contents {
+            revision: 0,
+            entries: Entries::Plain(serde_json::from_slice::<TotpDatabase>(serialized)?),
+            decoy: None,
+            peer_key: None,
+            slots_hash: None,
+        },
+        3 => {
+            let contents = serde_json::from_slice::<Contents<TotpDatabase>>(serialized)?;
+            Contents {
+                revision: contents.revision,
+                entries: Entries::Plain(contents.entries),
+                decoy: contents.decoy,
+                peer_key: contents.peer_key,
+                slots_hash: contents.slots_hash,
+            }
+        }
+        _ => {
+            let contents =
+                serde_json::from_slice::<Contents<HashMap<String, SealedEntry>>>(serialized)?;
+            Contents {
+                revision: contents.revision,
+                entries: Entries::Sealed(contents.entries),
+                decoy: contents.decoy,
+                peer_key: contents.peer_key,
+                slots_hash: contents.slots_hash,
+            }
+        }
+    })
+}
+
+/// Return the key slots of the database file, which are readable without unlocking.
+///
+/// Files written before key slots were introduced have no slots.
+pub fn key_slots<P: AsRef<Path>>(path: &P) -> Result<Vec<KeySlot>> {
+    Ok(match EncryptedDatabase::read(path.as_ref())?.keys {
+        KeyHeader::Slots { slots } => slots,
+        KeyHeader::Direct { .. } => Vec::new(),
+    })
+}
+
+/// Return the sets of factors, any of which unlocks the database file.
+pub fn accepted_factors<P: AsRef<Path>>(path: &P) -> Result<Vec<Vec<Factor>>> {
+    Ok(match EncryptedDatabase::read(path.as_ref())?.keys {
+        KeyHeader::Slots { slots } => slots.iter().map(|s| s.factors().to_vec()).collect(),
+        KeyHeader::Direct { factors, .. } => vec![factors],
+    })
+}
+
+/// Generate a new keyfile filled with random bytes.
+///
+/// Existing file is never overwritten, and new file is readable only by the owner on Unix.
+pub fn generate_keyfile<P: AsRef<Path>>(path: &P) -> Result<()> {
+    let mut content = Zeroizing::new([0u8; KEYFILE_LEN]);
+    OsRng.fill_bytes(content.as_mut());
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut f = options.open(path)?;
+    f.write_all(content.as_ref())?;
+    Ok(())
+}
+
+/// Derive the ChaCha20Poly1305 key by Argon2id with the parameters.
+pub(crate) fn derive_key(
+    material: &[u8],
+    salt: &SaltString,
+    params: &KdfParams,
+) -> Result<Zeroizing<[u8; CHACHA20_KEY_LEN]>> {
+    let params = match Params::new(
+        params.m_cost,
+        params.t_cost,
+        params.p_cost,
+        Some(CHACHA20_KEY_LEN),
+    ) {
+        Ok(p) => p,
+        Err(e) => bail!("Invalid KDF parameters: {}", e),
+    };
+    let hasher = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+    let mut salt_bytes = [0u8; Salt::MAX_LENGTH];
+    let salt_bytes = match salt.as_salt().b64_decode(&mut salt_bytes) {
+        Ok(s) => s,
+        Err(e) => bail!("Invalid salt: {}", e),
+    };
+    let mut key = Zeroizing::new([0u8; CHACHA20_KEY_LEN]);
+    if let Err(e) = hasher.hash_password_into(material, salt_bytes, key.as_mut()) {
+        bail!("Key derivation failed: {}", e);
+    }
+    Ok(key)
 }
 
 #[cfg(test)]
 mod test {
 
     use super::*;
+    use age::secrecy::ExposeSecret;
     use tempfile::tempdir;
 
     #[test]
@@ -162,7 +1438,8 @@ mod test {
                 0,
                 6,
                 otp::HashType::Sha1,
-            ),
+            )
+            .into(),
         );
         database.insert(
             "test2".to_string(),
@@ -173,7 +1450,41 @@ mod test {
                 6,
                 otp::HashType::Sha256,
             )
-            .unwrap(),
+            .unwrap()
+            .into(),
+        );
+        database.insert(
+            "test3".to_string(),
+            ocra::OcraClient::new(
+                "12345678901234567890".as_bytes().to_vec(),
+                "OCRA-1:HOTP-SHA1-6:QN08".parse().unwrap(),
+                0,
+                None,
+            )
+            .into(),
+        );
+        database.insert(
+            "test4".to_string(),
+            motp::MotpClient::new("e3152afee62599c8".to_string(), "1234".to_string())
+                .unwrap()
+                .into(),
+        );
+        database.insert(
+            "test5".to_string(),
+            yandex::YandexClient::new(vec![0; 16], "1234".to_string())
+                .unwrap()
+                .into(),
+        );
+        database.insert(
+            "test6".to_string(),
+            skey::SkeyClient::new(
+                skey::SkeyAlgorithm::Md5,
+                "This is a test.".to_string(),
+                "TeSt",
+                99,
+            )
+            .unwrap()
+            .into(),
         );
         let save_dir = tempdir().unwrap();
         let save_path = save_dir.path().join("test_database.json");
@@ -182,4 +1493,546 @@ mod test {
         assert_eq!(loaded_database, database);
         save_dir.close().unwrap();
     }
+
+    #[test]
+    fn keyfile() {
+        let mut database = TotpDatabase::new();
+        database.insert(
+            "test".to_string(),
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into(),
+        );
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        let keyfile_path = save_dir.path().join("keyfile");
+        generate_keyfile(&keyfile_path).unwrap();
+        assert!(generate_keyfile(&keyfile_path).is_err());
+        let key = CompositeKey::from_password("Test key")
+            .with_keyfile(&keyfile_path)
+            .unwrap();
+        save_database_with_key(&database, &save_path, &key).unwrap();
+        assert_eq!(
+            accepted_factors(&save_path).unwrap(),
+            vec![vec![Factor::Password, Factor::Keyfile]]
+        );
+        assert!(load_database(&save_path, "Test key").is_err());
+        let keyfile_only = CompositeKey::default().with_keyfile(&keyfile_path).unwrap();
+        assert!(load_database_with_key(&save_path, &keyfile_only).is_err());
+        assert_eq!(load_database_with_key(&save_path, &key).unwrap(), database);
+
+        save_database_with_key(&database, &save_path, &keyfile_only).unwrap();
+        assert_eq!(
+            load_database_with_key(&save_path, &keyfile_only).unwrap(),
+            database
+        );
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn add_and_revoke_slots() {
+        let mut database = TotpDatabase::new();
+        database.insert(
+            "test".to_string(),
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into(),
+        );
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        save_database(&database, &save_path, "alice").unwrap();
+        let (_, mut keyring) =
+            open_database(&save_path, &CompositeKey::from_password("alice")).unwrap();
+        keyring
+            .add_slot("bob", &CompositeKey::from_password("bob"))
+            .unwrap();
+        let encrypted_data = EncryptedDatabase::read(&save_path).unwrap().encrypted_data;
+        save_keyring(&save_path, &mut keyring).unwrap();
+        assert_ne!(
+            EncryptedDatabase::read(&save_path).unwrap().encrypted_data,
+            encrypted_data
+        );
+        assert_eq!(load_database(&save_path, "bob").unwrap(), database);
+        let with_alice = EncryptedDatabase::read(&save_path).unwrap().keys;
+        keyring.remove_slot(0).unwrap();
+        save_keyring(&save_path, &mut keyring).unwrap();
+        assert!(load_database(&save_path, "alice").is_err());
+        assert_eq!(load_database(&save_path, "bob").unwrap(), database);
+
+        // The revoked slot put back from the old file is refused.
+        let mut restored = EncryptedDatabase::read(&save_path).unwrap();
+        restored.keys = with_alice;
+        restored.write(&save_path).unwrap();
+        assert!(load_database(&save_path, "alice").is_err());
+        assert!(load_database(&save_path, "bob").is_err());
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn age_recipient() {
+        let mut database = TotpDatabase::new();
+        database.insert(
+            "test".to_string(),
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into(),
+        );
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        let identity_path = save_dir.path().join("identity.txt");
+        let identity = age::x25519::Identity::generate();
+        std::fs::write(&identity_path, identity.to_string().expose_secret()).unwrap();
+
+        let mut keyring = Keyring::new(&CompositeKey::from_password("Test key")).unwrap();
+        keyring
+            .add_recipient("age", &identity.to_public().to_string())
+            .unwrap();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        assert_eq!(
+            accepted_factors(&save_path).unwrap(),
+            vec![vec![Factor::Password], vec![Factor::Identity]]
+        );
+        assert_eq!(
+            load_database_with_identity(&save_path, &identity_path).unwrap(),
+            database
+        );
+        assert_eq!(load_database(&save_path, "Test key").unwrap(), database);
+
+        let other = age::x25519::Identity::generate();
+        std::fs::write(&identity_path, other.to_string().expose_secret()).unwrap();
+        assert!(load_database_with_identity(&save_path, &identity_path).is_err());
+    }
+
+    #[test]
+    fn authenticate_header() {
+        let mut database = TotpDatabase::new();
+        database.insert(
+            "test".to_string(),
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into(),
+        );
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        save_database(&database, &save_path, "Test key").unwrap();
+        let saved = EncryptedDatabase::read(&save_path).unwrap();
+        assert_eq!(saved.header.version, FORMAT_VERSION);
+        assert_eq!(
+            BASE64.decode(saved.header.nonce.as_bytes()).unwrap().len(),
+            XCHACHA20_NONCE_LEN
+        );
+
+        let mut tampered = EncryptedDatabase::read(&save_path).unwrap();
+        tampered.header.nonce = BASE64.encode(&[0u8; XCHACHA20_NONCE_LEN]);
+        tampered.write(&save_path).unwrap();
+        assert!(load_database(&save_path, "Test key").is_err());
+
+        // Files of version 1 are encrypted by ChaCha20Poly1305 without associated data.
+        let (_, keyring) = {
+            saved.write(&save_path).unwrap();
+            open_database(&save_path, &CompositeKey::from_password("Test key")).unwrap()
+        };
+        let nonce = [0u8; CHACHA20_NONCE_LEN];
+        let encrypted = ChaCha20Poly1305::new(Key::from_slice(keyring.master_key()))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                serde_json::to_vec(&database).unwrap().as_slice(),
+            )
+            .unwrap();
+        let legacy = EncryptedDatabase {
+            header: PayloadHeader {
+                version: 1,
+                nonce: BASE64.encode(&nonce),
+            },
+            keys: saved.keys,
+            encrypted_data: BASE64.encode(&encrypted),
+            decoy: None,
+        };
+        legacy.write(&save_path).unwrap();
+        assert_eq!(load_database(&save_path, "Test key").unwrap(), database);
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn revision() {
+        let mut database = TotpDatabase::new();
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        let key = CompositeKey::from_password("Test key");
+        let mut keyring = Keyring::new(&key).unwrap();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        assert_eq!(keyring.revision(), 1);
+        let old = std::fs::read(&save_path).unwrap();
+
+        database.insert(
+            "test".to_string(),
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into(),
+        );
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        let (loaded, mut keyring) = open_database(&save_path, &key).unwrap();
+        assert_eq!(loaded, database);
+        assert_eq!(keyring.revision(), 2);
+        keyring
+            .add_slot("bob", &CompositeKey::from_password("bob"))
+            .unwrap();
+        save_keyring(&save_path, &mut keyring).unwrap();
+        assert_eq!(keyring.revision(), 3);
+        assert_eq!(open_database(&save_path, &key).unwrap().1.revision(), 3);
+
+        std::fs::write(&save_path, old).unwrap();
+        let (loaded, old_keyring) = open_database(&save_path, &key).unwrap();
+        assert!(loaded.is_empty());
+        assert_eq!(old_keyring.revision(), 1);
+        assert_eq!(old_keyring.database_id(), keyring.database_id());
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn decoy() {
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        let key = CompositeKey::from_password("Test key");
+        let duress = CompositeKey::from_password("Duress key");
+        let mut database = TotpDatabase::new();
+        database.insert(
+            "real".to_string(),
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into(),
+        );
+        let mut keyring = Keyring::new(&key).unwrap();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        let without_decoy = EncryptedDatabase::read(&save_path).unwrap();
+        assert!(open_database(&save_path, &duress).is_err());
+
+        keyring.set_decoy(&duress).unwrap();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        let with_decoy = EncryptedDatabase::read(&save_path).unwrap();
+        let shape = |enc_db: &EncryptedDatabase| {
+            let decoy = enc_db.decoy.as_ref().unwrap();
+            (
+                enc_db.encrypted_data.len(),
+                decoy.slot.len(),
+                decoy.nonce.len(),
+                decoy.data.len(),
+            )
+        };
+        assert_eq!(shape(&with_decoy), shape(&without_decoy));
+        assert_eq!(
+            with_decoy.encrypted_data.len(),
+            with_decoy.decoy.as_ref().unwrap().data.len()
+        );
+
+        // The duress key opens the decoy, which is empty at first.
+        let (mut decoy, mut decoy_keyring) = open_database_lazy(&save_path, &duress).unwrap();
+        assert!(decoy.names().is_empty());
+        assert_ne!(decoy_keyring.database_id(), keyring.database_id());
+        let entry: Entry = otp::TotpClient::new_steam(b"abcdefghijabcdefghij".to_vec()).into();
+        decoy.insert("decoy", &entry).unwrap();
+        save_lazy_database_with_keyring(&decoy, &save_path, &mut decoy_keyring).unwrap();
+
+        // Each key opens its own database, and saving one keeps the other.
+        let (loaded, mut keyring) = open_database(&save_path, &key).unwrap();
+        assert_eq!(loaded, database);
+        assert!(keyring.has_decoy());
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        let (loaded, decoy_keyring) = open_database(&save_path, &duress).unwrap();
+        assert_eq!(loaded.get("decoy"), Some(&entry));
+        assert_eq!(decoy_keyring.revision(), 1);
+
+        // The real payload is padded as the decoy grows beyond it, and both keep the same size.
+        let before = EncryptedDatabase::read(&save_path).unwrap();
+        let mut decoy = LazyDatabase::seal(&loaded, &decoy_keyring).unwrap();
+        for i in 0..200 {
+            decoy.insert(&format!("decoy{}", i), &entry).unwrap();
+        }
+        let mut decoy_keyring = decoy_keyring;
+        save_lazy_database_with_keyring(&decoy, &save_path, &mut decoy_keyring).unwrap();
+        let grown = EncryptedDatabase::read(&save_path).unwrap();
+        assert!(grown.encrypted_data.len() > before.encrypted_data.len());
+        assert_eq!(
+            grown.encrypted_data.len(),
+            grown.decoy.as_ref().unwrap().data.len()
+        );
+        let (loaded, _) = open_database(&save_path, &duress).unwrap();
+        assert_eq!(loaded.len(), 201);
+        let (loaded, mut keyring) = open_database(&save_path, &key).unwrap();
+        assert_eq!(loaded, database);
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        assert_eq!(
+            shape(&EncryptedDatabase::read(&save_path).unwrap()),
+            shape(&grown)
+        );
+
+        keyring.remove_decoy();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        assert!(open_database(&save_path, &duress).is_err());
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn decoy_set_with_duress_key() {
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        let key = CompositeKey::from_password("Test key");
+        let duress = CompositeKey::from_password("Duress key");
+        let mut database = TotpDatabase::new();
+        database.insert(
+            "real".to_string(),
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into(),
+        );
+        let mut keyring = Keyring::new(&key).unwrap();
+        keyring.set_decoy(&duress).unwrap();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+
+        // The keyring of the decoy sets and removes its own decoy as a database without one.
+        let (decoy, mut decoy_keyring) = open_database(&save_path, &duress).unwrap();
+        assert!(!decoy_keyring.has_decoy());
+        decoy_keyring
+            .set_decoy(&CompositeKey::from_password("Other key"))
+            .unwrap();
+        save_database_with_keyring(&decoy, &save_path, &mut decoy_keyring).unwrap();
+        let (decoy, mut decoy_keyring) = open_database(&save_path, &duress).unwrap();
+        assert!(decoy_keyring.has_decoy());
+        let (loaded, keyring) = open_database(&save_path, &key).unwrap();
+        assert_eq!(loaded, database);
+        assert!(keyring.has_decoy());
+
+        decoy_keyring.remove_decoy();
+        save_database_with_keyring(&decoy, &save_path, &mut decoy_keyring).unwrap();
+        let (_, decoy_keyring) = open_database(&save_path, &duress).unwrap();
+        assert!(!decoy_keyring.has_decoy());
+        let (loaded, keyring) = open_database(&save_path, &key).unwrap();
+        assert_eq!(loaded, database);
+        assert!(keyring.has_decoy());
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn decoy_saves_look_alike() {
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        let key = CompositeKey::from_password("Test key");
+        let duress = CompositeKey::from_password("Duress key");
+        let database = TotpDatabase::new();
+        // Every save changes all but the hidden slot, whichever database is saved.
+        let assert_changed = |before: &EncryptedDatabase, after: &EncryptedDatabase| {
+            let (b, a) = (
+                before.decoy.as_ref().unwrap(),
+                after.decoy.as_ref().unwrap(),
+            );
+            assert_eq!(b.slot, a.slot);
+            assert_ne!(before.header.nonce, after.header.nonce);
+            assert_ne!(before.encrypted_data, after.encrypted_data);
+            assert_ne!(b.nonce, a.nonce);
+            assert_ne!(b.data, a.data);
+        };
+
+        let mut keyring = Keyring::new(&key).unwrap();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        let first = EncryptedDatabase::read(&save_path).unwrap();
+        let (_, mut keyring) = open_database(&save_path, &key).unwrap();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        let second = EncryptedDatabase::read(&save_path).unwrap();
+        assert_changed(&first, &second);
+
+        keyring.set_decoy(&duress).unwrap();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        let with_decoy = EncryptedDatabase::read(&save_path).unwrap();
+        let (_, mut keyring) = open_database(&save_path, &key).unwrap();
+        save_database_with_keyring(&database, &save_path, &mut keyring).unwrap();
+        let real_saved = EncryptedDatabase::read(&save_path).unwrap();
+        assert_changed(&with_decoy, &real_saved);
+
+        let (decoy, mut decoy_keyring) = open_database_lazy(&save_path, &duress).unwrap();
+        save_lazy_database_with_keyring(&decoy, &save_path, &mut decoy_keyring).unwrap();
+        let decoy_saved = EncryptedDatabase::read(&save_path).unwrap();
+        assert_changed(&real_saved, &decoy_saved);
+        assert!(open_database(&save_path, &key).is_ok());
+
+        // Key slots are changed with the duress key as with any other key,
+        // and the slots added with it open the decoy.
+        decoy_keyring
+            .add_slot("carol", &CompositeKey::from_password("carol"))
+            .unwrap();
+        let new_duress = CompositeKey::from_password("New duress key");
+        decoy_keyring.change_key(&new_duress).unwrap();
+        save_keyring(&save_path, &mut decoy_keyring).unwrap();
+        assert!(open_database(&save_path, &duress).is_err());
+        let (mut carol, mut carol_keyring) =
+            open_database_lazy(&save_path, &CompositeKey::from_password("carol")).unwrap();
+        assert_eq!(carol_keyring.database_id(), decoy_keyring.database_id());
+        let entry: Entry = otp::TotpClient::new_steam(b"abcdefghijabcdefghij".to_vec()).into();
+        carol.insert("decoy", &entry).unwrap();
+        save_lazy_database_with_keyring(&carol, &save_path, &mut carol_keyring).unwrap();
+        let (loaded, _) = open_database(&save_path, &new_duress).unwrap();
+        assert_eq!(loaded.get("decoy"), Some(&entry));
+        let (loaded, keyring) = open_database(&save_path, &key).unwrap();
+        assert_eq!(loaded, database);
+        assert!(keyring.has_decoy());
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn lazy_entries() {
+        let mut database = TotpDatabase::new();
+        database.insert(
+            "steam".to_string(),
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into(),
+        );
+        database.insert(
+            "skey".to_string(),
+            skey::SkeyClient::new(
+                skey::SkeyAlgorithm::Md5,
+                "This is a test.".to_string(),
+                "TeSt",
+                99,
+            )
+            .unwrap()
+            .into(),
+        );
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        save_database(&database, &save_path, "Test key").unwrap();
+        assert_eq!(
+            EncryptedDatabase::read(&save_path).unwrap().header.version,
+            FORMAT_VERSION
+        );
+
+        let key = CompositeKey::from_password("Test key");
+        let (mut lazy, mut keyring) = open_database_lazy(&save_path, &key).unwrap();
+        let mut names = lazy.names();
+        names.sort_unstable();
+        assert_eq!(names, vec!["skey", "steam"]);
+        assert_eq!(lazy.kind("skey"), Some(EntryKind::Skey));
+        assert_eq!(lazy.get("steam").unwrap().as_ref(), database.get("steam"));
+        assert!(lazy.get("nothing").unwrap().is_none());
+
+        // An entry moved to another name is not decrypted.
+        let moved = lazy.entries["steam"].clone();
+        lazy.entries.insert("skey".to_string(), moved);
+        assert!(lazy.get("skey").is_err());
+
+        assert!(lazy.remove("skey"));
+        save_lazy_database_with_keyring(&lazy, &save_path, &mut keyring).unwrap();
+        database.remove("skey");
+        assert_eq!(load_database(&save_path, "Test key").unwrap(), database);
+        let mut other = Keyring::new(&key).unwrap();
+        assert!(save_lazy_database_with_keyring(&lazy, &save_path, &mut other).is_err());
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn protected_entry() {
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        let key = CompositeKey::from_password("Test key");
+        let mut keyring = Keyring::new(&key).unwrap();
+        let mut lazy = LazyDatabase::seal(&TotpDatabase::new(), &keyring).unwrap();
+        let entry: Entry = otp::TotpClient::new_steam(b"12345678901234567890".to_vec()).into();
+        lazy.insert_protected("root", &entry, "Entry passphrase")
+            .unwrap();
+        save_lazy_database_with_keyring(&lazy, &save_path, &mut keyring).unwrap();
+
+        let (lazy, _) = open_database_lazy(&save_path, &key).unwrap();
+        assert!(lazy.is_protected("root"));
+        assert_eq!(lazy.kind("root"), Some(EntryKind::Totp));
+        assert!(lazy.get("root").is_err());
+        assert!(lazy.get_protected("root", "Wrong passphrase").is_err());
+        assert_eq!(
+            lazy.get_protected("root", "Entry passphrase").unwrap(),
+            Some(entry)
+        );
+        assert!(open_database(&save_path, &key).is_err());
+
+        // The passphrase alone does not decrypt the entry without the database key.
+        let other = Keyring::new(&key).unwrap();
+        let moved = LazyDatabase {
+            entries: lazy.entries.clone(),
+            entry_key: entry_key(&other),
+        };
+        assert!(moved.get_protected("root", "Entry passphrase").is_err());
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn load_without_slots() {
+        let mut database = TotpDatabase::new();
+        database.insert(
+            "test".to_string(),
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into(),
+        );
+        let save_dir = tempdir().unwrap();
+        let save_path = save_dir.path().join("test_database.json");
+        let salt = SaltString::generate(&mut OsRng);
+        let key = derive_key(b"Test key", &salt, &KdfParams::default()).unwrap();
+        let nonce = [0u8; CHACHA20_NONCE_LEN];
+        let encrypted = ChaCha20Poly1305::new(Key::from_slice(key.as_slice()))
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                serde_json::to_vec(&database).unwrap().as_slice(),
+            )
+            .unwrap();
+        let legacy = format!(
+            r#"{{"nonce":"{}","salt":"{}","encrypted_data":"{}"}}"#,
+            BASE64.encode(&nonce),
+            salt.as_str(),
+            BASE64.encode(&encrypted)
+        );
+        std::fs::write(&save_path, legacy).unwrap();
+        assert_eq!(
+            accepted_factors(&save_path).unwrap(),
+            vec![vec![Factor::Password]]
+        );
+        assert!(key_slots(&save_path).unwrap().is_empty());
+        let (loaded, mut keyring) =
+            open_database(&save_path, &CompositeKey::from_password("Test key")).unwrap();
+        assert_eq!(loaded, database);
+        assert_eq!(keyring.revision(), 0);
+        assert!(save_keyring(&save_path, &mut keyring).is_err());
+        save_database_with_keyring(&loaded, &save_path, &mut keyring).unwrap();
+        assert_eq!(load_database(&save_path, "Test key").unwrap(), database);
+        save_dir.close().unwrap();
+    }
+
+    #[test]
+    fn tagged_entries() {
+        let totp: Entry =
+            otp::TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()).into();
+        let serialized = serde_json::to_value(&totp).unwrap();
+        assert_eq!(serialized["type"], "totp");
+        assert_eq!(
+            serde_json::from_value::<Entry>(serialized.clone()).unwrap(),
+            totp
+        );
+        let mut untagged = serialized;
+        untagged.as_object_mut().unwrap().remove("type");
+        assert_eq!(serde_json::from_value::<Entry>(untagged).unwrap(), totp);
+
+        let motp: Entry = motp::MotpClient::new("e3152afee62599c8".to_string(), "1234".to_string())
+            .unwrap()
+            .into();
+        let serialized = serde_json::to_value(&motp).unwrap();
+        assert_eq!(serialized["type"], "motp");
+        assert_eq!(
+            serde_json::from_value::<Entry>(serialized.clone()).unwrap(),
+            motp
+        );
+        let mut invalid = serialized.clone();
+        invalid["pin"] = "12a".into();
+        assert!(serde_json::from_value::<Entry>(invalid).is_err());
+        let mut untagged = serialized;
+        untagged.as_object_mut().unwrap().remove("type");
+        assert!(serde_json::from_value::<Entry>(untagged).is_err());
+    }
+
+    #[test]
+    fn derive_key_compatible() {
+        use argon2::{ParamsBuilder, PasswordHasher};
+        let salt = SaltString::generate(&mut OsRng);
+        let mut argon2param = ParamsBuilder::new();
+        argon2param.output_len(CHACHA20_KEY_LEN).unwrap();
+        let hasher = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            argon2param.params().unwrap(),
+        );
+        let hash = hasher.hash_password(b"Test key", &salt).unwrap();
+        assert_eq!(
+            derive_key(b"Test key", &salt, &KdfParams::default())
+                .unwrap()
+                .as_slice(),
+            hash.hash.unwrap().as_bytes()
+        );
+    }
 }
@@ -0,0 +1,456 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Import accounts from other authenticators.
+//!
+//! `.maFile` of [Steam Desktop Authenticator] (SDA) is supported.
+//! Encrypted maFile is decrypted with the passkey and `manifest.json` in the same directory,
+//! in the same way as SDA does: AES-256-CBC with a key derived by PBKDF2-HMAC-SHA1.
+//!
+//! `otpauth-migration://` URI, which Google Authenticator exports as QR codes, is also supported.
+//! Its data is a protocol buffer message of accounts.
+//!
+//! [Steam Desktop Authenticator]: https://github.com/Jessecar96/SteamDesktopAuthenticator
+
+use crate::otp::{HashType, TotpClient};
+use crate::secret::Zeroizing;
+use aes::Aes256;
+use anyhow::{bail, ensure, Context, Result};
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use data_encoding::{BASE64, BASE64_NOPAD};
+use hmac::Hmac;
+use serde::Deserialize;
+use sha1::Sha1;
+use std::path::Path;
+use url::Url;
+
+/// Metadata key of Steam account name.
+pub const METADATA_ACCOUNT_NAME: &str = "account_name";
+/// Metadata key of Steam Guard revocation code.
+pub const METADATA_REVOCATION_CODE: &str = "revocation_code";
+
+const SDA_MANIFEST: &str = "manifest.json";
+const SDA_PBKDF2_ITERATIONS: u32 = 50000;
+const SDA_KEY_LEN: usize = 32;
+const MIGRATION_SCHEME: &str = "otpauth-migration";
+const MIGRATION_TIMESTEP: u64 = 30;
+
+#[derive(Deserialize)]
+struct SdaMaFile {
+    shared_secret: String,
+    account_name: String,
+    revocation_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SdaManifest {
+    encrypted: bool,
+    entries: Vec<SdaManifestEntry>,
+}
+
+#[derive(Deserialize)]
+struct SdaManifestEntry {
+    encryption_iv: Option<String>,
+    encryption_salt: Option<String>,
+    filename: String,
+}
+
+/// Return whether the maFile is encrypted, according to `manifest.json` next to it.
+///
+/// If there is no manifest, the maFile is treated as plain one.
+pub fn is_sda_encrypted<P: AsRef<Path>>(path: &P) -> Result<bool> {
+    Ok(match read_sda_manifest(path.as_ref())? {
+        Some(manifest) => manifest.encrypted,
+        None => false,
+    })
+}
+
+/// Read a Steam Desktop Authenticator `.maFile` and return its account name and Steam Guard client.
+///
+/// `passkey` is needed only if the maFile is encrypted.
+/// Account name and revocation code are stored in the client's metadata
+/// with key [METADATA_ACCOUNT_NAME] and [METADATA_REVOCATION_CODE].
+pub fn read_sda_mafile<P: AsRef<Path>>(
+    path: &P,
+    passkey: Option<&str>,
+) -> Result<(String, TotpClient)> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)?;
+    let manifest = read_sda_manifest(path)?;
+    let content = Zeroizing::new(match manifest {
+        Some(manifest) if manifest.encrypted => {
+            let filename = path
+                .file_name()
+                .context("maFile path does not have a file name.")?
+                .to_string_lossy();
+            let entry = manifest
+                .entries
+                .iter()
+                .find(|e| e.filename == filename)
+                .context(format!("{} is not listed in SDA manifest.", filename))?;
+            let passkey = passkey.context("maFile is encrypted but passkey is not given.")?;
+            let (iv, salt) = match (&entry.encryption_iv, &entry.encryption_salt) {
+                (Some(iv), Some(salt)) => (iv, salt),
+                _ => bail!("SDA manifest lacks encryption parameters of {}.", filename),
+            };
+            decrypt_sda(&content, passkey, salt, iv)?
+        }
+        _ => content,
+    });
+    let mafile = serde_json::from_str::<SdaMaFile>(&content).context("Failed to parse maFile.")?;
+    let key = BASE64
+        .decode(mafile.shared_secret.as_bytes())
+        .context("Failed to decode base64-encoded shared secret.")?;
+    let mut client = TotpClient::new_steam(key);
+    let metadata = client.metadata_mut();
+    metadata.insert(
+        METADATA_ACCOUNT_NAME.to_string(),
+        mafile.account_name.clone(),
+    );
+    if let Some(code) = mafile.revocation_code {
+        metadata.insert(METADATA_REVOCATION_CODE.to_string(), code);
+    }
+    Ok((mafile.account_name, client))
+}
+
+fn read_sda_manifest(mafile_path: &Path) -> Result<Option<SdaManifest>> {
+    let manifest_path = match mafile_path.parent() {
+        Some(dir) => dir.join(SDA_MANIFEST),
+        None => return Ok(None),
+    };
+    if !manifest_path.is_file() {
+        return Ok(None);
+    }
+    let manifest = std::fs::read_to_string(&manifest_path)?;
+    Ok(Some(
+        serde_json::from_str(&manifest).context("Failed to parse SDA manifest.")?,
+    ))
+}
+
+fn decrypt_sda(content: &str, passkey: &str, salt: &str, iv: &str) -> Result<String> {
+    let salt = BASE64.decode(salt.as_bytes())?;
+    let iv = BASE64.decode(iv.as_bytes())?;
+    let encrypted = BASE64.decode(content.trim().as_bytes())?;
+    let mut key = Zeroizing::new([0u8; SDA_KEY_LEN]);
+    pbkdf2::pbkdf2::<Hmac<Sha1>>(
+        passkey.as_bytes(),
+        &salt,
+        SDA_PBKDF2_ITERATIONS,
+        key.as_mut(),
+    );
+    let decryptor = match cbc::Decryptor::<Aes256>::new_from_slices(key.as_slice(), &iv) {
+        Ok(d) => d,
+        Err(e) => bail!("Invalid SDA encryption parameters: {}", e),
+    };
+    let decrypted = match decryptor.decrypt_padded_vec_mut::<Pkcs7>(&encrypted) {
+        Ok(d) => d,
+        Err(_) => bail!("Decryption failed. Passkey may be wrong."),
+    };
+    Ok(String::from_utf8(decrypted)?)
+}
+
+/// An account in `otpauth-migration://` URI.
+pub struct MigrationAccount {
+    /// Label of the account, `issuer:account name` if the issuer is given.
+    pub label: String,
+    /// TOTP client of the account, or error if the account is not supported, such as HOTP.
+    pub client: Result<TotpClient>,
+}
+
+/// Return whether the text is `otpauth-migration://` URI.
+pub fn is_migration_uri(text: &str) -> bool {
+    text.trim()
+        .get(..MIGRATION_SCHEME.len() + 3)
+        .is_some_and(|head| head.eq_ignore_ascii_case(&format!("{}://", MIGRATION_SCHEME)))
+}
+
+/// Parse `otpauth-migration://offline?data=...` URI exported by Google Authenticator.
+pub fn parse_migration_uri(uri: &str) -> Result<Vec<MigrationAccount>> {
+    let url = Url::parse(uri.trim()).context("Failed to parse URI.")?;
+    ensure!(
+        url.scheme() == MIGRATION_SCHEME,
+        "Unsupported URI scheme: {}",
+        url.scheme()
+    );
+    // `+` in base64 may be left unescaped, and then decoded as space.
+    let data = Zeroizing::new(
+        url.query_pairs()
+            .find(|(k, _)| k == "data")
+            .context("URI does not contain data.")?
+            .1
+            .replace(' ', "+"),
+    );
+    let payload = Zeroizing::new(
+        BASE64_NOPAD
+            .decode(data.trim_end_matches('=').as_bytes())
+            .context("Failed to decode base64-encoded data.")?,
+    );
+    let mut accounts = Vec::new();
+    let mut reader = ProtoReader { data: &payload };
+    while let Some((field, value)) = reader.next_field()? {
+        if let (1, ProtoValue::Bytes(parameters)) = (field, value) {
+            accounts.push(parse_otp_parameters(parameters)?);
+        }
+    }
+    Ok(accounts)
+}
+
+fn parse_otp_parameters(parameters: &[u8]) -> Result<MigrationAccount> {
+    let mut secret = Zeroizing::new(Vec::new());
+    let (mut name, mut issuer) = (String::new(), String::new());
+    let (mut algorithm, mut digits, mut otp_type) = (0, 0, 0);
+    let mut reader = ProtoReader { data: parameters };
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Bytes(b)) => secret = Zeroizing::new(b.to_vec()),
+            (2, ProtoValue::Bytes(b)) => name = String::from_utf8_lossy(b).to_string(),
+            (3, ProtoValue::Bytes(b)) => issuer = String::from_utf8_lossy(b).to_string(),
+            (4, ProtoValue::Varint(v)) => algorithm = v,
+            (5, ProtoValue::Varint(v)) => digits = v,
+            (6, ProtoValue::Varint(v)) => otp_type = v,
+            _ => {}
+        }
+    }
+    let label = match issuer.is_empty() || name.starts_with(&format!("{}:", issuer)) {
+        true => name,
+        false => format!("{}:{}", issuer, name),
+    };
+    Ok(MigrationAccount {
+        label,
+        client: migration_client(&secret, algorithm, digits, otp_type),
+    })
+}
+
+/// Construct the client from the enum values of the migration message.
+fn migration_client(
+    secret: &[u8],
+    algorithm: u64,
+    digits: u64,
+    otp_type: u64,
+) -> Result<TotpClient> {
+    ensure!(otp_type != 1, "HOTP is not supported.");
+    ensure!(!secret.is_empty(), "Account does not contain secret.");
+    let hashtype = match algorithm {
+        0 | 1 => HashType::Sha1,
+        2 => HashType::Sha256,
+        3 => HashType::Sha512,
+        4 => bail!("MD5 is not supported."),
+        _ => bail!("Unsupported algorithm: {}", algorithm),
+    };
+    let digit = match digits {
+        0 | 1 => 6,
+        2 => 8,
+        _ => bail!("Unsupported digits: {}", digits),
+    };
+    Ok(TotpClient::new(
+        secret.to_vec(),
+        MIGRATION_TIMESTEP,
+        0,
+        digit,
+        hashtype,
+    ))
+}
+
+enum ProtoValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Reader of protocol buffer fields, enough to read the migration message.
+struct ProtoReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ProtoReader<'a> {
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Protocol buffer has too long varint.")
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(len <= self.data.len(), "Protocol buffer is truncated.");
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    /// Read the next field number and its value.
+    fn next_field(&mut self) -> Result<Option<(u64, ProtoValue<'a>)>> {
+        if self.data.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => ProtoValue::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                ProtoValue::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                ProtoValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                ProtoValue::Fixed
+            }
+            wire => bail!("Unsupported wire type of protocol buffer: {}", wire),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cbc::cipher::BlockEncryptMut;
+    use tempfile::tempdir;
+
+    const MAFILE: &str = r#"{"shared_secret":"MTIzNDU2Nzg5MDEyMzQ1Njc4OTA=","serial_number":"1","revocation_code":"R12345","account_name":"alice","identity_secret":"AAAA"}"#;
+
+    #[test]
+    fn plain_mafile() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("76561197960287930.maFile");
+        std::fs::write(&path, MAFILE).unwrap();
+        assert!(!is_sda_encrypted(&path).unwrap());
+        let (name, client) = read_sda_mafile(&path, None).unwrap();
+        assert_eq!(name, "alice");
+        assert_eq!(client.metadata()[METADATA_REVOCATION_CODE], "R12345");
+        let mut expected = TotpClient::new_steam("12345678901234567890".as_bytes().to_vec());
+        *expected.metadata_mut() = client.metadata().clone();
+        assert_eq!(client, expected);
+    }
+
+    #[test]
+    fn encrypted_mafile() {
+        let dir = tempdir().unwrap();
+        let salt = [1u8; 8];
+        let iv = [2u8; 16];
+        let mut key = [0u8; SDA_KEY_LEN];
+        pbkdf2::pbkdf2::<Hmac<Sha1>>(b"passkey", &salt, SDA_PBKDF2_ITERATIONS, &mut key);
+        let encrypted = cbc::Encryptor::<Aes256>::new_from_slices(&key, &iv)
+            .unwrap()
+            .encrypt_padded_vec_mut::<Pkcs7>(MAFILE.as_bytes());
+        let path = dir.path().join("76561197960287930.maFile");
+        std::fs::write(&path, BASE64.encode(&encrypted)).unwrap();
+        let manifest = format!(
+            r#"{{"encrypted":true,"entries":[{{"encryption_iv":"{}","encryption_salt":"{}","filename":"76561197960287930.maFile","steamid":76561197960287930}}]}}"#,
+            BASE64.encode(&iv),
+            BASE64.encode(&salt)
+        );
+        std::fs::write(dir.path().join(SDA_MANIFEST), manifest).unwrap();
+        assert!(is_sda_encrypted(&path).unwrap());
+        assert!(read_sda_mafile(&path, None).is_err());
+        assert!(read_sda_mafile(&path, Some("wrong")).is_err());
+        let (name, client) = read_sda_mafile(&path, Some("passkey")).unwrap();
+        assert_eq!(name, "alice");
+        assert_eq!(client.metadata()[METADATA_ACCOUNT_NAME], "alice");
+    }
+
+    fn proto_bytes(field: u8, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![field << 3 | 2, value.len() as u8];
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    fn proto_varint(field: u8, value: u8) -> Vec<u8> {
+        vec![field << 3, value]
+    }
+
+    #[test]
+    fn migration_uri() {
+        let accounts = [
+            [
+                proto_bytes(1, b"12345678901234567890"),
+                proto_bytes(2, b"alice@example.com"),
+                proto_bytes(3, b"Example"),
+                proto_varint(4, 1),
+                proto_varint(5, 1),
+                proto_varint(6, 2),
+            ]
+            .concat(),
+            [
+                proto_bytes(1, b"12345678901234567890123456789012"),
+                proto_bytes(2, b"ACME Co:bob"),
+                proto_bytes(3, b"ACME Co"),
+                proto_varint(4, 2),
+                proto_varint(5, 2),
+                proto_varint(6, 2),
+                // Field 7 is the counter of HOTP.
+                proto_varint(7, 0),
+            ]
+            .concat(),
+            [
+                proto_bytes(1, b"12345678901234567890"),
+                proto_bytes(2, b"carol"),
+                proto_varint(6, 1),
+            ]
+            .concat(),
+        ];
+        let mut payload: Vec<u8> = accounts.iter().flat_map(|a| proto_bytes(1, a)).collect();
+        payload.extend(proto_varint(2, 1));
+        let data = BASE64.encode(&payload);
+        let escaped =
+            percent_encoding::utf8_percent_encode(&data, percent_encoding::NON_ALPHANUMERIC);
+        for uri in [
+            format!("otpauth-migration://offline?data={}", escaped),
+            format!("otpauth-migration://offline?data={}", data),
+        ] {
+            assert!(is_migration_uri(&uri));
+            let parsed = parse_migration_uri(&uri).unwrap();
+            assert_eq!(parsed.len(), 3);
+            assert_eq!(parsed[0].label, "Example:alice@example.com");
+            assert_eq!(
+                parsed[0].client.as_ref().unwrap(),
+                &TotpClient::new(
+                    "12345678901234567890".as_bytes().to_vec(),
+                    30,
+                    0,
+                    6,
+                    HashType::Sha1
+                )
+            );
+            assert_eq!(parsed[1].label, "ACME Co:bob");
+            assert_eq!(
+                parsed[1].client.as_ref().unwrap(),
+                &TotpClient::new(
+                    "12345678901234567890123456789012".as_bytes().to_vec(),
+                    30,
+                    0,
+                    8,
+                    HashType::Sha256
+                )
+            );
+            assert_eq!(parsed[2].label, "carol");
+            assert!(parsed[2].client.is_err());
+        }
+        assert!(!is_migration_uri(
+            "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP"
+        ));
+        assert!(parse_migration_uri("otpauth-migration://offline?data=CgU").is_err());
+    }
+}
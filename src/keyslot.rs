@@ -0,0 +1,1060 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Key slots of database file.
+//!
+//! Like LUKS, the database is encrypted with a random master key,
+//! and each key slot wraps the master key under its own credential:
+//! password and keyfile, an [age] recipient, or an SSH key.
+//! So several credentials can open one database,
+//! and slots can be added or revoked without changing the master key.
+//! The hash of the slots is saved in the encrypted data, so a revoked slot can not be put back.
+//! A recovery slot is unlocked by the key reconstructed from shares (see [recovery]).
+//!
+//! Note that revoking a slot does not change the master key,
+//! so whoever has opened the database with the slot could still decrypt old copies of it.
+
+use crate::database::{derive_key, CompositeKey, Factor, KdfParams};
+use crate::recovery;
+use crate::secret::{Secret, Zeroizing};
+use crate::ssh::{SshPublicKey, SshSigner};
+use anyhow::{bail, ensure, Result};
+use argon2::password_hash::{
+    rand_core::{OsRng, RngCore},
+    SaltString,
+};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, XChaCha20Poly1305, XNonce};
+use data_encoding::BASE64;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+/// Length of the master key in bytes.
+pub const MASTER_KEY_LEN: usize = 32;
+/// Nonce length of ChaCha20Poly1305, used by slots written before the slot header was authenticated.
+const LEGACY_WRAP_NONCE_LEN: usize = 12;
+const WRAP_NONCE_LEN: usize = 24;
+const SSH_CHALLENGE_LEN: usize = 32;
+const DATABASE_ID_CONTEXT: &[u8] = b"yatotp database id\0";
+const DATABASE_ID_LEN: usize = 16;
+/// Prefixed to the challenge, so that the signature is useless for anything else.
+const SSH_CHALLENGE_CONTEXT: &[u8] = b"yatotp key slot challenge\0";
+const HIDDEN_SLOT_CONTEXT: &[u8] = b"yatotp hidden slot\0";
+const HIDDEN_SALT_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+/// Length of the hidden slot: salt, nonce, and the wrapped master key with its tag.
+pub(crate) const HIDDEN_SLOT_LEN: usize =
+    HIDDEN_SALT_LEN + WRAP_NONCE_LEN + MASTER_KEY_LEN + TAG_LEN;
+
+/// A key slot, which wraps the master key under a credential.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeySlot {
+    name: String,
+    #[serde(flatten)]
+    lock: SlotLock,
+}
+
+/// How the master key is wrapped in a slot.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SlotLock {
+    /// Wrapped under the key derived from [CompositeKey] by Argon2id.
+    Composite {
+        factors: Vec<Factor>,
+        #[serde(default)]
+        kdf: KdfParams,
+        salt: String,
+        nonce: String,
+        wrapped_key: String,
+    },
+    /// Encrypted to an age X25519 recipient.
+    Age {
+        recipient: String,
+        wrapped_key: String,
+    },
+    /// Wrapped under SHA-256 hash of the SSH key's signature of the challenge.
+    Ssh {
+        public_key: String,
+        challenge: String,
+        nonce: String,
+        wrapped_key: String,
+    },
+    /// Wrapped under the recovery key, which is split into shares.
+    Recovery { nonce: String, wrapped_key: String },
+}
+
+impl KeySlot {
+    fn composite(
+        name: &str,
+        key: &CompositeKey,
+        kdf: KdfParams,
+        master_key: &[u8],
+    ) -> Result<KeySlot> {
+        let salt = SaltString::generate(&mut OsRng);
+        let kek = derive_key(&key.material()?, &salt, &kdf)?;
+        let mut slot = KeySlot {
+            name: name.to_string(),
+            lock: SlotLock::Composite {
+                factors: key.derivation_factors(),
+                kdf,
+                salt: salt.as_str().to_string(),
+                nonce: generate_wrap_nonce(),
+                wrapped_key: String::new(),
+            },
+        };
+        slot.wrap_master_key(kek.as_slice(), master_key)?;
+        Ok(slot)
+    }
+
+    fn ssh(
+        name: &str,
+        signer: &SshSigner,
+        public_key: &SshPublicKey,
+        master_key: &[u8],
+    ) -> Result<KeySlot> {
+        let mut challenge = [0u8; SSH_CHALLENGE_LEN];
+        OsRng.fill_bytes(&mut challenge);
+        let kek = match ssh_kek(signer, public_key, &challenge)? {
+            Some(kek) => kek,
+            None => bail!("The SSH key {} is not available.", public_key.fingerprint()),
+        };
+        let mut slot = KeySlot {
+            name: name.to_string(),
+            lock: SlotLock::Ssh {
+                public_key: public_key.to_string(),
+                challenge: BASE64.encode(&challenge),
+                nonce: generate_wrap_nonce(),
+                wrapped_key: String::new(),
+            },
+        };
+        slot.wrap_master_key(kek.as_slice(), master_key)?;
+        Ok(slot)
+    }
+
+    fn age(name: &str, recipient: &str, master_key: &[u8]) -> Result<KeySlot> {
+        let parsed = match age::x25519::Recipient::from_str(recipient) {
+            Ok(r) => r,
+            Err(e) => bail!("Invalid age recipient {}: {}", recipient, e),
+        };
+        let wrapped = age::encrypt(&parsed, master_key)?;
+        Ok(KeySlot {
+            name: name.to_string(),
+            lock: SlotLock::Age {
+                recipient: parsed.to_string(),
+                wrapped_key: BASE64.encode(&wrapped),
+            },
+        })
+    }
+
+    /// Return the name of the slot.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the factors needed to unlock the slot.
+    pub fn factors(&self) -> &[Factor] {
+        match &self.lock {
+            SlotLock::Composite { factors, .. } => factors,
+            SlotLock::Age { .. } => &[Factor::Identity],
+            SlotLock::Ssh { .. } => &[Factor::SshKey],
+            SlotLock::Recovery { .. } => &[Factor::Recovery],
+        }
+    }
+
+    /// Return the age recipient which the slot is encrypted to, if it is.
+    pub fn recipient(&self) -> Option<&str> {
+        match &self.lock {
+            SlotLock::Age { recipient, .. } => Some(recipient),
+            _ => None,
+        }
+    }
+
+    /// Return the SSH public key which unlocks the slot, if it does.
+    pub fn ssh_public_key(&self) -> Option<&str> {
+        match &self.lock {
+            SlotLock::Ssh { public_key, .. } => Some(public_key),
+            _ => None,
+        }
+    }
+
+    /// Unwrap the master key if the slot is unlocked by the key.
+    ///
+    /// Returns `None` if the key does not consist of the factors of the slot.
+    fn unwrap_key(&self, key: &CompositeKey) -> Result<Option<Secret<[u8; MASTER_KEY_LEN]>>> {
+        match &self.lock {
+            SlotLock::Composite {
+                factors, kdf, salt, ..
+            } => {
+                if key.derivation_factors() != *factors {
+                    return Ok(None);
+                }
+                let salt = match SaltString::new(salt) {
+                    Ok(s) => s,
+                    Err(e) => bail!("Invalid salt of key slot: {}", e),
+                };
+                let kek = derive_key(&key.material()?, &salt, kdf)?;
+                self.unwrap_master_key(kek.as_slice())
+            }
+            SlotLock::Age { wrapped_key, .. } => {
+                let identities = key.identities()?;
+                if identities.is_empty() {
+                    return Ok(None);
+                }
+                let wrapped = BASE64.decode(wrapped_key.as_bytes())?;
+                let decryptor = age::Decryptor::new_buffered(wrapped.as_slice())?;
+                let mut reader = match decryptor.decrypt(identities.iter().map(|i| i.as_ref() as _))
+                {
+                    Ok(r) => r,
+                    Err(age::DecryptError::NoMatchingKeys) => return Ok(None),
+                    Err(e) => return Err(e.into()),
+                };
+                let mut master_key = Secret::new([0u8; MASTER_KEY_LEN]);
+                reader.read_exact(master_key.expose_mut())?;
+                Ok(Some(master_key))
+            }
+            SlotLock::Ssh {
+                public_key,
+                challenge,
+                ..
+            } => {
+                let signer = match key.ssh_signer() {
+                    Some(signer) => signer,
+                    None => return Ok(None),
+                };
+                let challenge = BASE64.decode(challenge.as_bytes())?;
+                match ssh_kek(signer, &public_key.parse()?, &challenge)? {
+                    Some(kek) => self.unwrap_master_key(kek.as_slice()),
+                    None => Ok(None),
+                }
+            }
+            SlotLock::Recovery { .. } => match key.recovery_key() {
+                Some(recovery_key) => self.unwrap_master_key(recovery_key),
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// Return the slot without the wrapped key, which is authenticated when it is wrapped.
+    fn associated_data(&self) -> Result<Vec<u8>> {
+        let mut header = self.clone();
+        match &mut header.lock {
+            SlotLock::Composite { wrapped_key, .. }
+            | SlotLock::Age { wrapped_key, .. }
+            | SlotLock::Ssh { wrapped_key, .. }
+            | SlotLock::Recovery { wrapped_key, .. } => wrapped_key.clear(),
+        }
+        Ok(serde_json::to_vec(&header)?)
+    }
+
+    fn nonce(&self) -> Result<Vec<u8>> {
+        match &self.lock {
+            SlotLock::Composite { nonce, .. }
+            | SlotLock::Ssh { nonce, .. }
+            | SlotLock::Recovery { nonce, .. } => Ok(BASE64.decode(nonce.as_bytes())?),
+            SlotLock::Age { .. } => bail!("age key slot has no nonce."),
+        }
+    }
+
+    /// Wrap the master key with XChaCha20Poly1305 under the key encryption key.
+    fn wrap_master_key(&mut self, kek: &[u8], master_key: &[u8]) -> Result<()> {
+        let nonce = self.nonce()?;
+        let aad = self.associated_data()?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(kek));
+        let payload = Payload {
+            msg: master_key,
+            aad: &aad,
+        };
+        let wrapped = match cipher.encrypt(XNonce::from_slice(&nonce), payload) {
+            Ok(c) => c,
+            Err(e) => bail!("Failed to wrap master key: {}", e),
+        };
+        match &mut self.lock {
+            SlotLock::Composite { wrapped_key, .. }
+            | SlotLock::Ssh { wrapped_key, .. }
+            | SlotLock::Recovery { wrapped_key, .. } => *wrapped_key = BASE64.encode(&wrapped),
+            SlotLock::Age { .. } => unreachable!("age key slot has no nonce."),
+        }
+        Ok(())
+    }
+
+    /// Unwrap the master key under the key encryption key.
+    ///
+    /// Slots with 12 byte nonce are wrapped with ChaCha20Poly1305 without associated data.
+    /// Returns `None` if the key encryption key is wrong or the slot is tampered.
+    fn unwrap_master_key(&self, kek: &[u8]) -> Result<Option<Secret<[u8; MASTER_KEY_LEN]>>> {
+        let nonce = self.nonce()?;
+        let wrapped = match &self.lock {
+            SlotLock::Composite { wrapped_key, .. }
+            | SlotLock::Ssh { wrapped_key, .. }
+            | SlotLock::Recovery { wrapped_key, .. } => BASE64.decode(wrapped_key.as_bytes())?,
+            SlotLock::Age { .. } => bail!("age key slot is not wrapped with a key."),
+        };
+        let unwrapped = match nonce.len() {
+            LEGACY_WRAP_NONCE_LEN => ChaCha20Poly1305::new(Key::from_slice(kek))
+                .decrypt(Nonce::from_slice(&nonce), wrapped.as_slice()),
+            WRAP_NONCE_LEN => {
+                let aad = self.associated_data()?;
+                let payload = Payload {
+                    msg: &wrapped,
+                    aad: &aad,
+                };
+                XChaCha20Poly1305::new(Key::from_slice(kek))
+                    .decrypt(XNonce::from_slice(&nonce), payload)
+            }
+            _ => bail!("Invalid nonce of key slot."),
+        };
+        let unwrapped = match unwrapped {
+            Ok(k) => Secret::new(k),
+            Err(_) => return Ok(None),
+        };
+        ensure!(
+            unwrapped.expose().len() == MASTER_KEY_LEN,
+            "Invalid length of master key."
+        );
+        let mut master_key = Secret::new([0u8; MASTER_KEY_LEN]);
+        master_key.expose_mut().copy_from_slice(unwrapped.expose());
+        Ok(Some(master_key))
+    }
+}
+
+fn generate_wrap_nonce() -> String {
+    let mut nonce = [0u8; WRAP_NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    BASE64.encode(&nonce)
+}
+
+/// Derive the key encryption key from the signature of the challenge.
+///
+/// Returns `None` if the signer does not have the SSH key.
+fn ssh_kek(
+    signer: &SshSigner,
+    public_key: &SshPublicKey,
+    challenge: &[u8],
+) -> Result<Option<Zeroizing<[u8; MASTER_KEY_LEN]>>> {
+    let mut message = SSH_CHALLENGE_CONTEXT.to_vec();
+    message.extend_from_slice(challenge);
+    Ok(signer.sign(public_key, &message)?.map(|signature| {
+        let mut kek = Zeroizing::new([0u8; MASTER_KEY_LEN]);
+        kek.copy_from_slice(&Sha256::digest(signature.expose()));
+        kek
+    }))
+}
+
+/// Wrap the master key of the decoy database under the duress key, into the hidden slot.
+///
+/// The hidden slot is the concatenation of the salt of Argon2id with the default parameters,
+/// the nonce and the wrapped key, without any name or parameter,
+/// so that it can not be told from the random bytes written in its place when no decoy is set.
+fn hide_master_key(key: &CompositeKey, master_key: &[u8]) -> Result<Vec<u8>> {
+    let mut slot = vec![0u8; HIDDEN_SALT_LEN + WRAP_NONCE_LEN];
+    OsRng.fill_bytes(&mut slot);
+    let salt = match SaltString::b64_encode(&slot[..HIDDEN_SALT_LEN]) {
+        Ok(s) => s,
+        Err(e) => bail!("Invalid salt: {}", e),
+    };
+    let kek = derive_key(&key.material()?, &salt, &KdfParams::default())?;
+    let payload = Payload {
+        msg: master_key,
+        aad: HIDDEN_SLOT_CONTEXT,
+    };
+    let wrapped = match XChaCha20Poly1305::new(Key::from_slice(kek.as_slice()))
+        .encrypt(XNonce::from_slice(&slot[HIDDEN_SALT_LEN..]), payload)
+    {
+        Ok(c) => c,
+        Err(e) => bail!("Failed to wrap master key: {}", e),
+    };
+    slot.extend_from_slice(&wrapped);
+    Ok(slot)
+}
+
+/// Unwrap the master key from the hidden slot. Returns `None` if the key does not open it.
+fn reveal_master_key(
+    slot: &[u8],
+    key: &CompositeKey,
+) -> Result<Option<Secret<[u8; MASTER_KEY_LEN]>>> {
+    ensure!(
+        slot.len() == HIDDEN_SLOT_LEN,
+        "Invalid length of hidden slot."
+    );
+    let material = match key.derivation_factors().is_empty() {
+        true => return Ok(None),
+        false => key.material()?,
+    };
+    let salt = match SaltString::b64_encode(&slot[..HIDDEN_SALT_LEN]) {
+        Ok(s) => s,
+        Err(e) => bail!("Invalid salt: {}", e),
+    };
+    let kek = derive_key(&material, &salt, &KdfParams::default())?;
+    let payload = Payload {
+        msg: &slot[HIDDEN_SALT_LEN + WRAP_NONCE_LEN..],
+        aad: HIDDEN_SLOT_CONTEXT,
+    };
+    let unwrapped = match XChaCha20Poly1305::new(Key::from_slice(kek.as_slice())).decrypt(
+        XNonce::from_slice(&slot[HIDDEN_SALT_LEN..HIDDEN_SALT_LEN + WRAP_NONCE_LEN]),
+        payload,
+    ) {
+        Ok(k) => Zeroizing::new(k),
+        Err(_) => return Ok(None),
+    };
+    let mut master_key = Secret::new([0u8; MASTER_KEY_LEN]);
+    master_key.expose_mut().copy_from_slice(&unwrapped);
+    Ok(Some(master_key))
+}
+
+/// Random bytes written in place of the hidden slot when no decoy is set.
+fn random_hidden_slot() -> Vec<u8> {
+    let mut slot = vec![0u8; HIDDEN_SLOT_LEN];
+    OsRng.fill_bytes(&mut slot);
+    slot
+}
+
+/// The decoy database known to the keyring of the real one,
+/// which is kept in the encrypted data of the real database.
+///
+/// Its hidden slot is read from the file, since the duress key may have been changed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Decoy {
+    master_key: Secret<[u8; MASTER_KEY_LEN]>,
+}
+
+impl Decoy {
+    /// Return the master key of the decoy database.
+    pub(crate) fn master_key(&self) -> &[u8; MASTER_KEY_LEN] {
+        self.master_key.expose()
+    }
+}
+
+impl fmt::Display for KeySlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(recipient) = self.recipient() {
+            return write!(f, "{} (age recipient {})", self.name, recipient);
+        }
+        if let Some(public_key) = self.ssh_public_key() {
+            return match public_key.parse::<SshPublicKey>() {
+                Ok(parsed) => write!(f, "{} (ssh-ed25519 {})", self.name, parsed.fingerprint()),
+                Err(_) => write!(f, "{} ({})", self.name, public_key),
+            };
+        }
+        let factors: Vec<&str> = self
+            .factors()
+            .iter()
+            .map(|factor| match factor {
+                Factor::Password => "password",
+                Factor::Keyfile => "keyfile",
+                Factor::Identity => "identity",
+                Factor::SshKey => "ssh key",
+                Factor::Recovery => "recovery shares",
+            })
+            .collect();
+        write!(f, "{} ({})", self.name, factors.join(" + "))
+    }
+}
+
+/// The master key of a database file and the slots wrapping it,
+/// together with the revision of the database last loaded or saved with it.
+///
+/// It may also have the decoy database, which is opened by the duress key instead
+/// (see [Keyring::set_decoy]). The keyring opened by the duress key has the same slots,
+/// but its master key is the one of the decoy, so the slots it adds open the decoy.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::database::CompositeKey;
+/// # use yatotp::keyslot::Keyring;
+/// let mut keyring = Keyring::new(&CompositeKey::from_password("alice's password")).unwrap();
+/// keyring
+///     .add_slot("bob", &CompositeKey::from_password("bob's password"))
+///     .unwrap();
+/// assert_eq!(keyring.slots().len(), 2);
+/// ```
+#[derive(Debug)]
+pub struct Keyring {
+    master_key: Secret<[u8; MASTER_KEY_LEN]>,
+    slots: Vec<KeySlot>,
+    unlocked: Option<usize>,
+    revision: u64,
+    decoy: Option<Decoy>,
+    is_decoy: bool,
+    /// The hidden slot of the file, which is kept while the decoy or its duress key is not changed.
+    hidden_slot: Option<Vec<u8>>,
+    /// The key of the other payload in the file, which the keyring of the decoy keeps
+    /// to encrypt it again without opening it.
+    peer_key: Option<Secret<[u8; MASTER_KEY_LEN]>>,
+}
+
+impl Keyring {
+    /// Create a keyring with a random master key and a slot named `default` for the key.
+    pub fn new(key: &CompositeKey) -> Result<Keyring> {
+        let mut master_key = Secret::new([0u8; MASTER_KEY_LEN]);
+        OsRng.fill_bytes(master_key.expose_mut());
+        let mut keyring = Keyring {
+            master_key,
+            slots: Vec::new(),
+            unlocked: None,
+            revision: 0,
+            decoy: None,
+            is_decoy: false,
+            hidden_slot: None,
+            peer_key: None,
+        };
+        keyring.add_slot("default", key)?;
+        keyring.unlocked = Some(0);
+        Ok(keyring)
+    }
+
+    /// Unlock the master key with any slot which the key opens.
+    pub(crate) fn unlock(slots: Vec<KeySlot>, key: &CompositeKey) -> Result<Keyring> {
+        for (i, slot) in slots.iter().enumerate() {
+            if let Some(master_key) = slot.unwrap_key(key)? {
+                return Ok(Keyring {
+                    master_key,
+                    slots,
+                    unlocked: Some(i),
+                    revision: 0,
+                    decoy: None,
+                    is_decoy: false,
+                    hidden_slot: None,
+                    peer_key: None,
+                });
+            }
+        }
+        bail!("No key slot is unlocked by the given key.")
+    }
+
+    /// Unlock the master key of the decoy database with the hidden slot.
+    ///
+    /// Returns `None` if the key does not open the hidden slot.
+    pub(crate) fn unlock_decoy(
+        slots: Vec<KeySlot>,
+        hidden_slot: &[u8],
+        key: &CompositeKey,
+    ) -> Result<Option<Keyring>> {
+        Ok(
+            reveal_master_key(hidden_slot, key)?.map(|master_key| Keyring {
+                master_key,
+                slots,
+                unlocked: None,
+                revision: 0,
+                decoy: None,
+                is_decoy: true,
+                hidden_slot: Some(hidden_slot.to_vec()),
+                peer_key: None,
+            }),
+        )
+    }
+
+    /// Return the master key.
+    pub(crate) fn master_key(&self) -> &[u8; MASTER_KEY_LEN] {
+        self.master_key.expose()
+    }
+
+    /// Return the identifier of the database, which is derived from the master key.
+    ///
+    /// It stays the same while the database is saved or its slots are changed,
+    /// and tells nothing about the master key.
+    pub fn database_id(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(DATABASE_ID_CONTEXT);
+        hasher.update(self.master_key.expose());
+        data_encoding::HEXLOWER.encode(&hasher.finalize()[..DATABASE_ID_LEN])
+    }
+
+    /// Return the revision of the database last loaded or saved with the keyring.
+    ///
+    /// It is 0 for a new keyring and files written before revisions were introduced.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub(crate) fn set_revision(&mut self, revision: u64) {
+        self.revision = revision;
+    }
+
+    /// Set the duress key, which opens the decoy database instead of this one.
+    ///
+    /// The decoy database is empty until it is opened by the duress key and entries are added.
+    /// Setting the duress key again replaces the decoy with a new empty one.
+    /// The duress key must be password, keyfile, or both of them,
+    /// and must not unlock any slot of the keyring.
+    ///
+    /// The keyring opened by the duress key behaves as if it had no decoy,
+    /// but the decoy it sets is only kept in its own data, since the file has room for one.
+    /// The hidden slot is kept, so the duress key still opens it.
+    pub fn set_decoy(&mut self, duress: &CompositeKey) -> Result<()> {
+        self.check_duress_key(duress)?;
+        let mut master_key = Secret::new([0u8; MASTER_KEY_LEN]);
+        OsRng.fill_bytes(master_key.expose_mut());
+        if !self.is_decoy {
+            self.hidden_slot = Some(hide_master_key(duress, master_key.expose())?);
+        }
+        self.decoy = Some(Decoy { master_key });
+        Ok(())
+    }
+
+    fn check_duress_key(&self, duress: &CompositeKey) -> Result<()> {
+        ensure!(
+            !duress.derivation_factors().is_empty(),
+            "Duress key requires password or keyfile."
+        );
+        let unlocks_hidden_slot = match (self.is_decoy, &self.hidden_slot) {
+            (true, Some(slot)) => reveal_master_key(slot, duress)?.is_some(),
+            _ => false,
+        };
+        ensure!(
+            !unlocks_hidden_slot,
+            "The duress key unlocks the key slot {}.",
+            self.slots.first().map_or(String::new(), |s| s.to_string())
+        );
+        for slot in &self.slots {
+            ensure!(
+                slot.unwrap_key(duress)?.is_none(),
+                "The duress key unlocks the key slot {}.",
+                slot
+            );
+        }
+        Ok(())
+    }
+
+    /// Remove the decoy database and its duress key.
+    ///
+    /// Random bytes are written in place of the hidden slot from the next save,
+    /// unless the keyring is opened by the duress key, which keeps opening it.
+    pub fn remove_decoy(&mut self) {
+        self.decoy = None;
+        if !self.is_decoy {
+            self.hidden_slot = None;
+        }
+    }
+
+    /// Return whether the keyring has the decoy database.
+    pub fn has_decoy(&self) -> bool {
+        self.decoy.is_some()
+    }
+
+    pub(crate) fn decoy(&self) -> Option<&Decoy> {
+        self.decoy.as_ref()
+    }
+
+    pub(crate) fn load_decoy(&mut self, decoy: Option<Decoy>) {
+        self.decoy = decoy;
+    }
+
+    /// Return whether the keyring is opened by the duress key.
+    pub(crate) fn is_decoy(&self) -> bool {
+        self.is_decoy
+    }
+
+    /// Mark the keyring as the one of the decoy, which is unlocked by a slot added with it.
+    pub(crate) fn set_is_decoy(&mut self) {
+        self.is_decoy = true;
+    }
+
+    /// Return the hidden slot to be written into the file.
+    ///
+    /// Random bytes are generated if the file has none, and kept for the later saves,
+    /// so that the file looks the same whether it has the decoy or not.
+    pub(crate) fn hidden_slot(&mut self) -> &[u8] {
+        self.hidden_slot.get_or_insert_with(random_hidden_slot)
+    }
+
+    /// Keep the hidden slot read from the file.
+    pub(crate) fn load_hidden_slot(&mut self, hidden_slot: Vec<u8>) {
+        if hidden_slot.len() == HIDDEN_SLOT_LEN {
+            self.hidden_slot = Some(hidden_slot);
+        }
+    }
+
+    pub(crate) fn peer_key(&self) -> Option<&[u8; MASTER_KEY_LEN]> {
+        self.peer_key.as_ref().map(|k| k.expose())
+    }
+
+    pub(crate) fn load_peer_key(&mut self, peer_key: Option<Secret<[u8; MASTER_KEY_LEN]>>) {
+        self.peer_key = peer_key;
+    }
+
+    /// Return the key slots.
+    pub fn slots(&self) -> &[KeySlot] {
+        &self.slots
+    }
+
+    /// Return the index of the slot used to unlock the keyring, if it is still there.
+    pub fn unlocked_slot(&self) -> Option<usize> {
+        self.unlocked
+    }
+
+    /// Add a slot which wraps the master key under the key,
+    /// derived with the default parameters of Argon2id.
+    ///
+    /// Slot names must be unique in the keyring.
+    pub fn add_slot(&mut self, name: &str, key: &CompositeKey) -> Result<()> {
+        ensure!(
+            self.slots.iter().all(|s| s.name != name),
+            "Key slot named {} does already exist.",
+            name
+        );
+        let slot = KeySlot::composite(name, key, KdfParams::default(), self.master_key.expose())?;
+        self.slots.push(slot);
+        Ok(())
+    }
+
+    /// Add a slot which encrypts the master key to the age X25519 recipient (`age1...`).
+    ///
+    /// Slot names must be unique in the keyring.
+    pub fn add_recipient(&mut self, name: &str, recipient: &str) -> Result<()> {
+        ensure!(
+            self.slots.iter().all(|s| s.name != name),
+            "Key slot named {} does already exist.",
+            name
+        );
+        let slot = KeySlot::age(name, recipient, self.master_key.expose())?;
+        ensure!(
+            self.slots.iter().all(|s| s.recipient() != slot.recipient()),
+            "Recipient {} does already exist.",
+            recipient
+        );
+        self.slots.push(slot);
+        Ok(())
+    }
+
+    /// Add a slot which is unlocked by the ed25519 SSH key, signing with the signer.
+    ///
+    /// Slot names must be unique in the keyring.
+    pub fn add_ssh_key(
+        &mut self,
+        name: &str,
+        signer: &SshSigner,
+        public_key: &SshPublicKey,
+    ) -> Result<()> {
+        ensure!(
+            self.slots.iter().all(|s| s.name != name),
+            "Key slot named {} does already exist.",
+            name
+        );
+        let slot = KeySlot::ssh(name, signer, public_key, self.master_key.expose())?;
+        ensure!(
+            self.slots
+                .iter()
+                .all(|s| s.ssh_public_key() != slot.ssh_public_key()),
+            "Key slot of SSH key {} does already exist.",
+            public_key.fingerprint()
+        );
+        self.slots.push(slot);
+        Ok(())
+    }
+
+    /// Add a slot which is unlocked by a new random recovery key, and return the key
+    /// to be split into shares by [recovery::split].
+    ///
+    /// Slot names must be unique in the keyring.
+    pub fn add_recovery_key(
+        &mut self,
+        name: &str,
+    ) -> Result<Secret<[u8; recovery::RECOVERY_KEY_LEN]>> {
+        ensure!(
+            self.slots.iter().all(|s| s.name != name),
+            "Key slot named {} does already exist.",
+            name
+        );
+        let mut recovery_key = Secret::new([0u8; recovery::RECOVERY_KEY_LEN]);
+        OsRng.fill_bytes(recovery_key.expose_mut());
+        let mut slot = KeySlot {
+            name: name.to_string(),
+            lock: SlotLock::Recovery {
+                nonce: generate_wrap_nonce(),
+                wrapped_key: String::new(),
+            },
+        };
+        slot.wrap_master_key(recovery_key.expose(), self.master_key.expose())?;
+        self.slots.push(slot);
+        Ok(recovery_key)
+    }
+
+    /// Replace the password and keyfile which unlocked the keyring.
+    ///
+    /// The slot unlocked is replaced keeping its name, or the hidden slot is
+    /// if the keyring is unlocked by the duress key.
+    pub fn change_key(&mut self, key: &CompositeKey) -> Result<()> {
+        match (self.unlocked, self.is_decoy) {
+            (Some(index), _) => self.replace_slot(index, key),
+            (None, true) => {
+                self.check_duress_key(key)?;
+                self.hidden_slot = Some(hide_master_key(key, self.master_key.expose())?);
+                Ok(())
+            }
+            (None, false) => bail!("The key slot unlocked the keyring is already revoked."),
+        }
+    }
+
+    /// Return the parameters of Argon2id which [Keyring::change_key] derives the new key with.
+    pub fn change_key_kdf(&self) -> KdfParams {
+        match self.unlocked.and_then(|index| self.slots.get(index)) {
+            Some(KeySlot {
+                lock: SlotLock::Composite { kdf, .. },
+                ..
+            }) => *kdf,
+            _ => KdfParams::default(),
+        }
+    }
+
+    /// Replace the password and keyfile of the slot, keeping its name and parameters of Argon2id.
+    pub fn replace_slot(&mut self, index: usize, key: &CompositeKey) -> Result<()> {
+        let (name, kdf) = match self.slots.get(index) {
+            Some(KeySlot {
+                name,
+                lock: SlotLock::Composite { kdf, .. },
+            }) => (name.clone(), *kdf),
+            Some(slot) => bail!("Key slot {} is not unlocked by password or keyfile.", slot),
+            None => bail!("Key slot {} does not exist.", index),
+        };
+        self.slots[index] = KeySlot::composite(&name, key, kdf, self.master_key.expose())?;
+        Ok(())
+    }
+
+    /// Revoke the slot. The last slot can not be revoked.
+    pub fn remove_slot(&mut self, index: usize) -> Result<KeySlot> {
+        ensure!(
+            index < self.slots.len(),
+            "Key slot {} does not exist.",
+            index
+        );
+        ensure!(
+            self.slots.len() > 1,
+            "The last key slot can not be revoked."
+        );
+        self.unlocked = match self.unlocked {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            unlocked => unlocked,
+        };
+        Ok(self.slots.remove(index))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+
+    #[test]
+    fn unlock_with_each_slot() {
+        let alice = CompositeKey::from_password("alice");
+        let bob = CompositeKey::from_password("bob");
+        let mut keyring = Keyring::new(&alice).unwrap();
+        keyring.add_slot("bob", &bob).unwrap();
+        assert!(keyring.add_slot("bob", &bob).is_err());
+        let slots = keyring.slots().to_vec();
+        let unlocked = Keyring::unlock(slots.clone(), &bob).unwrap();
+        assert_eq!(unlocked.master_key(), keyring.master_key());
+        assert_eq!(unlocked.unlocked_slot(), Some(1));
+        assert!(Keyring::unlock(slots, &CompositeKey::from_password("eve")).is_err());
+    }
+
+    #[test]
+    fn authenticate_slot_header() {
+        let alice = CompositeKey::from_password("alice");
+        let keyring = Keyring::new(&alice).unwrap();
+        let mut tampered = keyring.slots().to_vec();
+        tampered[0].name = "mallory".to_string();
+        assert!(Keyring::unlock(tampered, &alice).is_err());
+
+        // Slots wrapped with ChaCha20Poly1305 and 12 byte nonce are still unlocked.
+        let salt = SaltString::generate(&mut OsRng);
+        let kek = derive_key(b"alice", &salt, &KdfParams::default()).unwrap();
+        let nonce = [0u8; LEGACY_WRAP_NONCE_LEN];
+        let wrapped = ChaCha20Poly1305::new(Key::from_slice(kek.as_slice()))
+            .encrypt(Nonce::from_slice(&nonce), keyring.master_key().as_slice())
+            .unwrap();
+        let legacy: KeySlot = serde_json::from_str(&format!(
+            r#"{{"name":"default","type":"composite","factors":["password"],"salt":"{}","nonce":"{}","wrapped_key":"{}"}}"#,
+            salt.as_str(),
+            BASE64.encode(&nonce),
+            BASE64.encode(&wrapped)
+        ))
+        .unwrap();
+        let unlocked = Keyring::unlock(vec![legacy], &alice).unwrap();
+        assert_eq!(unlocked.master_key(), keyring.master_key());
+    }
+
+    #[test]
+    fn unlock_with_age_identity() {
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+        let mut keyring = Keyring::new(&CompositeKey::from_password("alice")).unwrap();
+        keyring.add_recipient("bob", &recipient).unwrap();
+        assert!(keyring.add_recipient("bob2", &recipient).is_err());
+        assert!(keyring.add_recipient("eve", "age1invalid").is_err());
+        assert_eq!(keyring.slots()[1].recipient(), Some(recipient.as_str()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let identity_path = dir.path().join("identity.txt");
+        std::fs::write(&identity_path, identity.to_string().expose_secret()).unwrap();
+        let key = CompositeKey::default()
+            .with_identity_file(&identity_path)
+            .unwrap();
+        let unlocked = Keyring::unlock(keyring.slots().to_vec(), &key).unwrap();
+        assert_eq!(unlocked.master_key(), keyring.master_key());
+        assert_eq!(unlocked.unlocked_slot(), Some(1));
+
+        let other = age::x25519::Identity::generate();
+        std::fs::write(&identity_path, other.to_string().expose_secret()).unwrap();
+        let key = CompositeKey::default()
+            .with_identity_file(&identity_path)
+            .unwrap();
+        assert!(Keyring::unlock(keyring.slots().to_vec(), &key).is_err());
+    }
+
+    #[test]
+    fn unlock_with_ssh_key() {
+        let seeded = |seed| {
+            let pair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(seed));
+            SshSigner::PrivateKey(Secret::new(*pair.sk))
+        };
+        let signer = seeded([1u8; 32]);
+        let public_key = signer.public_keys().unwrap().remove(0);
+        let mut keyring = Keyring::new(&CompositeKey::from_password("alice")).unwrap();
+        keyring.add_ssh_key("ssh", &signer, &public_key).unwrap();
+        assert!(keyring.add_ssh_key("ssh2", &signer, &public_key).is_err());
+        let other = seeded([2u8; 32]);
+        assert!(keyring
+            .add_ssh_key("other", &signer, &other.public_keys().unwrap()[0])
+            .is_err());
+        assert_eq!(keyring.slots()[1].factors(), &[Factor::SshKey]);
+
+        let key = CompositeKey::default().with_ssh_signer(signer);
+        let unlocked = Keyring::unlock(keyring.slots().to_vec(), &key).unwrap();
+        assert_eq!(unlocked.master_key(), keyring.master_key());
+        assert_eq!(unlocked.unlocked_slot(), Some(1));
+        let key = CompositeKey::default().with_ssh_signer(other);
+        assert!(Keyring::unlock(keyring.slots().to_vec(), &key).is_err());
+    }
+
+    #[test]
+    fn revoke_slot() {
+        let alice = CompositeKey::from_password("alice");
+        let bob = CompositeKey::from_password("bob");
+        let mut keyring = Keyring::new(&alice).unwrap();
+        keyring.add_slot("bob", &bob).unwrap();
+        assert_eq!(keyring.remove_slot(0).unwrap().name(), "default");
+        assert_eq!(keyring.unlocked_slot(), None);
+        assert!(keyring.remove_slot(0).is_err());
+        assert!(Keyring::unlock(keyring.slots().to_vec(), &alice).is_err());
+        assert!(Keyring::unlock(keyring.slots().to_vec(), &bob).is_ok());
+    }
+
+    #[test]
+    fn replace_slot_keeping_kdf() {
+        let alice = CompositeKey::from_password("alice");
+        let bob = CompositeKey::from_password("bob");
+        let kdf = KdfParams {
+            m_cost: 1024,
+            t_cost: 3,
+            p_cost: 2,
+        };
+        let mut keyring = Keyring::new(&alice).unwrap();
+        keyring.slots[0] =
+            KeySlot::composite("default", &alice, kdf, keyring.master_key.expose()).unwrap();
+        let mut unlocked = Keyring::unlock(keyring.slots().to_vec(), &alice).unwrap();
+        assert_eq!(unlocked.change_key_kdf(), kdf);
+        unlocked.change_key(&bob).unwrap();
+        let unlocked = Keyring::unlock(unlocked.slots().to_vec(), &bob).unwrap();
+        assert_eq!(unlocked.change_key_kdf(), kdf);
+        assert_eq!(unlocked.slots()[0].name(), "default");
+
+        let mut keyring = Keyring::new(&alice).unwrap();
+        keyring.add_slot("bob", &bob).unwrap();
+        keyring.remove_slot(0).unwrap();
+        assert_eq!(keyring.change_key_kdf(), KdfParams::default());
+    }
+
+    #[test]
+    fn unlock_with_recovery_key() {
+        let mut keyring = Keyring::new(&CompositeKey::from_password("alice")).unwrap();
+        let recovery_key = keyring.add_recovery_key("recovery").unwrap();
+        assert!(keyring.add_recovery_key("recovery").is_err());
+        assert_eq!(keyring.slots()[1].factors(), &[Factor::Recovery]);
+        assert_eq!(keyring.slots()[1].to_string(), "recovery (recovery shares)");
+
+        let shares = recovery::split(recovery_key.expose(), 2, 3).unwrap();
+        let combined = recovery::combine(&shares[1..]).unwrap();
+        let key = CompositeKey::default().with_recovery_key(combined);
+        let unlocked = Keyring::unlock(keyring.slots().to_vec(), &key).unwrap();
+        assert_eq!(unlocked.master_key(), keyring.master_key());
+        assert_eq!(unlocked.unlocked_slot(), Some(1));
+        let key = CompositeKey::default()
+            .with_recovery_key(Secret::new([0u8; recovery::RECOVERY_KEY_LEN]));
+        assert!(Keyring::unlock(keyring.slots().to_vec(), &key).is_err());
+    }
+
+    #[test]
+    fn hidden_slot() {
+        let alice = CompositeKey::from_password("alice");
+        let duress = CompositeKey::from_password("duress");
+        let mut keyring = Keyring::new(&alice).unwrap();
+        assert!(keyring.set_decoy(&alice).is_err());
+        keyring.set_decoy(&duress).unwrap();
+        let slot = keyring.hidden_slot().to_vec();
+        assert_eq!(slot.len(), HIDDEN_SLOT_LEN);
+        assert_eq!(random_hidden_slot().len(), HIDDEN_SLOT_LEN);
+        let decoy = keyring.decoy().unwrap();
+
+        let slots = keyring.slots().to_vec();
+        let unlocked = Keyring::unlock_decoy(slots.clone(), &slot, &duress)
+            .unwrap()
+            .unwrap();
+        assert_eq!(unlocked.master_key(), decoy.master_key());
+        assert!(unlocked.is_decoy());
+        assert_eq!(unlocked.unlocked_slot(), None);
+        assert!(unlocked.slots() == keyring.slots());
+        let mut unlocked = unlocked;
+        assert!(Keyring::unlock_decoy(slots.clone(), &slot, &alice)
+            .unwrap()
+            .is_none());
+
+        // The keyring of the decoy sets its own decoy as if it had none, keeping the hidden slot.
+        assert!(!unlocked.has_decoy());
+        assert!(unlocked.set_decoy(&duress).is_err());
+        unlocked
+            .set_decoy(&CompositeKey::from_password("mallory"))
+            .unwrap();
+        assert!(unlocked.has_decoy());
+        assert_eq!(unlocked.hidden_slot(), slot.as_slice());
+        unlocked.remove_decoy();
+        assert!(!unlocked.has_decoy());
+        assert_eq!(unlocked.hidden_slot(), slot.as_slice());
+
+        // Changing the duress key replaces the hidden slot.
+        let eve = CompositeKey::from_password("eve");
+        assert!(unlocked.change_key(&alice).is_err());
+        unlocked.change_key(&eve).unwrap();
+        let changed = unlocked.hidden_slot().to_vec();
+        assert!(Keyring::unlock_decoy(slots.clone(), &changed, &duress)
+            .unwrap()
+            .is_none());
+        let unlocked = Keyring::unlock_decoy(slots, &changed, &eve)
+            .unwrap()
+            .unwrap();
+        assert_eq!(unlocked.master_key(), decoy.master_key());
+
+        keyring.remove_decoy();
+        assert_ne!(keyring.hidden_slot(), slot.as_slice());
+    }
+}
@@ -28,4 +28,19 @@
 //! [Keepass]: https://keepass.info
 
 pub mod database;
+pub mod import;
+pub mod keyslot;
+pub mod motp;
+pub mod ocra;
 pub mod otp;
+pub mod paper;
+pub mod qr;
+pub mod recovery;
+pub mod revision;
+pub mod scan;
+pub mod secret;
+pub mod skey;
+pub mod ssh;
+pub mod strength;
+pub mod uri;
+pub mod yandex;
@@ -0,0 +1,124 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Mobile-OTP calculation.
+//!
+//! [Mobile-OTP] (mOTP) is a legacy time-based OTP used by some VPNs.
+//! The code is the first 6 characters of hex-encoded MD5 hash of
+//! the concatenation of Unix time divided by 10, the secret, and the PIN.
+//!
+//! [Mobile-OTP]: http://motp.sourceforge.net
+
+use crate::secret::{Secret, Zeroizing};
+use anyhow::{ensure, Result};
+use chrono::prelude::*;
+use data_encoding::HEXLOWER;
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+
+/// Time step of mOTP in seconds.
+pub const MOTP_TIMESTEP: u64 = 10;
+
+/// Length of mOTP code.
+pub const MOTP_DIGIT: usize = 6;
+
+/// A mOTP client for each account.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::motp::*;
+/// # use chrono::prelude::*;
+/// let motp = MotpClient::new("e3152afee62599c8".to_string(), "1234".to_string()).unwrap();
+/// assert_eq!(motp.code(&Utc.timestamp(165892298, 0)).unwrap(), "e7d8b6");
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(try_from = "MotpClientData")]
+pub struct MotpClient {
+    secret: Secret<String>,
+    pin: Secret<String>,
+}
+
+/// Fields of [MotpClient] to be checked when it is deserialized.
+#[derive(Deserialize)]
+struct MotpClientData {
+    secret: Secret<String>,
+    pin: Secret<String>,
+}
+
+impl TryFrom<MotpClientData> for MotpClient {
+    type Error = anyhow::Error;
+
+    fn try_from(data: MotpClientData) -> Result<MotpClient> {
+        MotpClient::new(data.secret.expose().clone(), data.pin.expose().clone())
+    }
+}
+
+impl MotpClient {
+    /// Create a new mOTP client with hex-encoded secret and PIN.
+    pub fn new(secret: String, pin: String) -> Result<MotpClient> {
+        let mut secret = Secret::new(secret);
+        let pin = Secret::new(pin);
+        ensure!(
+            !secret.expose().is_empty() && secret.expose().chars().all(|c| c.is_ascii_hexdigit()),
+            "mOTP secret must be hexadecimal."
+        );
+        ensure!(
+            !pin.expose().is_empty() && pin.expose().chars().all(|c| c.is_ascii_digit()),
+            "mOTP PIN must be decimal."
+        );
+        secret.expose_mut().make_ascii_lowercase();
+        Ok(MotpClient { secret, pin })
+    }
+
+    /// Calculate the mOTP code of given datetime.
+    ///
+    /// Returns `None` if the datetime is before the Unix epoch.
+    pub fn code(&self, datetime: &DateTime<Utc>) -> Option<String> {
+        let counter = u64::try_from(datetime.timestamp()).ok()? / MOTP_TIMESTEP;
+        let message = Zeroizing::new(format!(
+            "{}{}{}",
+            counter,
+            self.secret.expose(),
+            self.pin.expose()
+        ));
+        let hash = Md5::digest(message.as_bytes());
+        let mut code = HEXLOWER.encode(&hash);
+        code.truncate(MOTP_DIGIT);
+        Some(code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn motp_example() {
+        let motp = MotpClient::new("E3152AFEE62599C8".to_string(), "1234".to_string()).unwrap();
+        assert_eq!(motp.code(&Utc.timestamp(165892298, 0)).unwrap(), "e7d8b6");
+        assert_eq!(motp.code(&Utc.timestamp(123456789, 0)).unwrap(), "4ebfb2");
+        assert_eq!(motp.code(&Utc.timestamp(-1, 0)), None);
+        assert!(MotpClient::new("not hex".to_string(), "1234".to_string()).is_err());
+        assert!(MotpClient::new("e3152afee62599c8".to_string(), "".to_string()).is_err());
+        assert!(
+            serde_json::from_str::<MotpClient>(r#"{"secret":"e3152afee62599c8","pin":"12a"}"#)
+                .is_err()
+        );
+    }
+}
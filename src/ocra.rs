@@ -0,0 +1,633 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! OATH Challenge-Response Algorithm calculation.
+//!
+//! Provide OCRA calculation, which conforms to [RFC 6287].
+//! The suite such as `OCRA-1:HOTP-SHA1-6:QN08` decides which inputs are used.
+//!
+//! [RFC 6287]: https://datatracker.ietf.org/doc/html/rfc6287
+
+use crate::otp::{self, Encoding, HashType};
+use crate::secret::Secret;
+use anyhow::{bail, ensure, Context, Result};
+use chrono::prelude::*;
+use data_encoding::{HEXUPPER, HEXUPPER_PERMISSIVE};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+const QUESTION_LEN: usize = 128;
+
+/// Format of the challenge question.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum QuestionFormat {
+    /// Alphanumeric characters (`QA`).
+    Alphanumeric,
+    /// Decimal digits (`QN`).
+    Numeric,
+    /// Hexadecimal digits (`QH`).
+    Hex,
+}
+
+/// Parsed OCRA suite string.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::ocra::*;
+/// # use yatotp::otp::HashType;
+/// let suite: OcraSuite = "OCRA-1:HOTP-SHA512-8:C-QN08-PSHA1".parse().unwrap();
+/// assert_eq!(suite.hashtype(), HashType::Sha512);
+/// assert_eq!(suite.digit(), 8);
+/// assert!(suite.uses_counter());
+/// assert_eq!(suite.pin_hashtype(), Some(HashType::Sha1));
+/// assert_eq!(suite.to_string(), "OCRA-1:HOTP-SHA512-8:C-QN08-PSHA1");
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct OcraSuite {
+    suite: String,
+    hashtype: HashType,
+    digit: u32,
+    counter: bool,
+    question_format: QuestionFormat,
+    question_len: usize,
+    pin: Option<HashType>,
+    session_len: Option<usize>,
+    timestep: Option<u64>,
+}
+
+impl OcraSuite {
+    /// Return hash function used in HMAC calculation.
+    pub fn hashtype(&self) -> HashType {
+        self.hashtype
+    }
+
+    /// Return digit of the response.
+    pub fn digit(&self) -> u32 {
+        self.digit
+    }
+
+    /// Return whether the suite uses counter (`C`).
+    pub fn uses_counter(&self) -> bool {
+        self.counter
+    }
+
+    /// Return format and maximum length of the question.
+    pub fn question(&self) -> (QuestionFormat, usize) {
+        (self.question_format, self.question_len)
+    }
+
+    /// Return hash function for PIN, if the suite uses PIN hash (`P`).
+    pub fn pin_hashtype(&self) -> Option<HashType> {
+        self.pin
+    }
+
+    /// Return length of session information in bytes, if the suite uses it (`S`).
+    pub fn session_len(&self) -> Option<usize> {
+        self.session_len
+    }
+
+    /// Return time step in seconds, if the suite uses timestamp (`T`).
+    pub fn timestep(&self) -> Option<u64> {
+        self.timestep
+    }
+
+    /// Hash PIN with the hash function of the suite.
+    ///
+    /// Returns `None` if the suite does not use PIN hash.
+    pub fn hash_pin(&self, pin: &[u8]) -> Option<Vec<u8>> {
+        Some(self.pin?.digest(pin))
+    }
+
+    /// Calculate OCRA response with given key and inputs.
+    ///
+    /// Inputs required by the suite must be given, and other inputs are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use yatotp::ocra::*;
+    /// let suite: OcraSuite = "OCRA-1:HOTP-SHA1-6:QN08".parse().unwrap();
+    /// let input = OcraInput {
+    ///     question: "00000000",
+    ///     ..Default::default()
+    /// };
+    /// // The first one-way challenge-response test vector in RFC 6287 Appendix C.
+    /// assert_eq!(suite.respond(b"12345678901234567890", &input).unwrap(), "237653");
+    /// ```
+    pub fn respond(&self, key: &[u8], input: &OcraInput) -> Result<String> {
+        let mut message = self.suite.as_bytes().to_vec();
+        message.push(0);
+        if self.counter {
+            let counter = input.counter.context("OCRA suite requires counter.")?;
+            message.extend_from_slice(&counter.to_be_bytes());
+        }
+        message.extend_from_slice(&self.encode_question(input.question)?);
+        if let Some(hashtype) = self.pin {
+            let pin_hash = input.pin_hash.context("OCRA suite requires PIN hash.")?;
+            ensure!(
+                pin_hash.len() == hashtype.output_len(),
+                "Length of PIN hash does not match the OCRA suite."
+            );
+            message.extend_from_slice(pin_hash);
+        }
+        if let Some(len) = self.session_len {
+            let session = input
+                .session
+                .context("OCRA suite requires session information.")?;
+            ensure!(
+                session.len() <= len,
+                "Session information must be at most {} bytes.",
+                len
+            );
+            message.resize(message.len() + len - session.len(), 0);
+            message.extend_from_slice(session);
+        }
+        if self.timestep.is_some() {
+            let timestamp = input.timestamp.context("OCRA suite requires timestamp.")?;
+            message.extend_from_slice(&timestamp.to_be_bytes());
+        }
+        let hs = self.hashtype.hmac(key, &message);
+        let bin_code = u32::from_be_bytes(otp::dynamic_truncate(&hs));
+        Ok(Encoding::Decimal.encode(bin_code, self.digit))
+    }
+
+    /// Return the timestamp input (`T`) of given datetime, if the suite uses timestamp.
+    ///
+    /// `None` is also returned for datetime before the Unix epoch.
+    pub fn timestamp(&self, datetime: &DateTime<Utc>) -> Option<u64> {
+        Some(u64::try_from(datetime.timestamp()).ok()? / self.timestep?)
+    }
+
+    fn encode_question(&self, question: &str) -> Result<Vec<u8>> {
+        ensure!(
+            question.len() >= 4 && question.len() <= self.question_len,
+            "Question must be 4 to {} characters.",
+            self.question_len
+        );
+        let hex = match self.question_format {
+            QuestionFormat::Alphanumeric => {
+                ensure!(
+                    question.chars().all(|c| c.is_ascii_alphanumeric()),
+                    "Question must be alphanumeric."
+                );
+                HEXUPPER.encode(question.as_bytes())
+            }
+            QuestionFormat::Numeric => {
+                ensure!(
+                    question.chars().all(|c| c.is_ascii_digit()),
+                    "Question must be numeric."
+                );
+                decimal_to_hex(question)
+            }
+            QuestionFormat::Hex => {
+                ensure!(
+                    question.chars().all(|c| c.is_ascii_hexdigit()),
+                    "Question must be hexadecimal."
+                );
+                question.to_ascii_uppercase()
+            }
+        };
+        let hex = format!("{:0<width$}", hex, width = QUESTION_LEN * 2);
+        Ok(HEXUPPER_PERMISSIVE.decode(hex.as_bytes())?)
+    }
+}
+
+impl FromStr for OcraSuite {
+    type Err = anyhow::Error;
+
+    fn from_str(suite: &str) -> Result<OcraSuite> {
+        ensure!(suite.is_ascii(), "OCRA suite must be ASCII: {}", suite);
+        let parts: Vec<&str> = suite.split(':').collect();
+        ensure!(
+            parts.len() == 3,
+            "OCRA suite must have three parts: {}",
+            suite
+        );
+        ensure!(
+            parts[0] == "OCRA-1",
+            "Unsupported OCRA version: {}",
+            parts[0]
+        );
+
+        let function: Vec<&str> = parts[1].split('-').collect();
+        ensure!(
+            function.len() == 3 && function[0] == "HOTP",
+            "Unsupported crypto function: {}",
+            parts[1]
+        );
+        let hashtype = parse_hashtype(function[1])?;
+        let digit: u32 = function[2]
+            .parse()
+            .context("Invalid digit of OCRA suite.")?;
+        ensure!((4..=10).contains(&digit), "Digit must be 4 to 10.");
+
+        let mut counter = false;
+        let mut question = None;
+        let mut pin = None;
+        let mut session_len = None;
+        let mut timestep = None;
+        for input in parts[2].split('-') {
+            match input.get(..1) {
+                Some("C") if input == "C" => counter = true,
+                Some("Q") if input.len() == 4 => {
+                    let format = match &input[1..2] {
+                        "A" => QuestionFormat::Alphanumeric,
+                        "N" => QuestionFormat::Numeric,
+                        "H" => QuestionFormat::Hex,
+                        _ => bail!("Invalid question format: {}", input),
+                    };
+                    let len: usize = input[2..].parse().context("Invalid question length.")?;
+                    ensure!((4..=64).contains(&len), "Question length must be 4 to 64.");
+                    question = Some((format, len));
+                }
+                Some("P") => pin = Some(parse_hashtype(&input[1..])?),
+                Some("S") => {
+                    let len = match &input[1..] {
+                        "" | "064" => 64,
+                        "128" => 128,
+                        "256" => 256,
+                        "512" => 512,
+                        _ => bail!("Invalid session information length: {}", input),
+                    };
+                    session_len = Some(len);
+                }
+                Some("T") if input.len() >= 3 => {
+                    let (step, unit) = input[1..].split_at(input.len() - 2);
+                    let step: u64 = step.parse().context("Invalid time step.")?;
+                    let (unit, max) = match unit {
+                        "S" => (1, 59),
+                        "M" => (60, 59),
+                        "H" => (3600, 48),
+                        _ => bail!("Invalid time step unit: {}", input),
+                    };
+                    ensure!((1..=max).contains(&step), "Invalid time step: {}", input);
+                    timestep = Some(step * unit);
+                }
+                _ => bail!("Invalid data input: {}", input),
+            }
+        }
+        let (question_format, question_len) =
+            question.context("OCRA suite must have question input.")?;
+        Ok(OcraSuite {
+            suite: suite.to_string(),
+            hashtype,
+            digit,
+            counter,
+            question_format,
+            question_len,
+            pin,
+            session_len,
+            timestep,
+        })
+    }
+}
+
+impl TryFrom<String> for OcraSuite {
+    type Error = anyhow::Error;
+
+    fn try_from(suite: String) -> Result<OcraSuite> {
+        suite.parse()
+    }
+}
+
+impl From<OcraSuite> for String {
+    fn from(suite: OcraSuite) -> String {
+        suite.suite
+    }
+}
+
+impl fmt::Display for OcraSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.suite)
+    }
+}
+
+/// Inputs of OCRA calculation.
+///
+/// Only the inputs which the suite requires are used.
+#[derive(Debug, Default)]
+pub struct OcraInput<'a> {
+    /// Challenge question (`Q`).
+    pub question: &'a str,
+    /// Counter (`C`).
+    pub counter: Option<u64>,
+    /// Hash of PIN (`P`). See [OcraSuite::hash_pin].
+    pub pin_hash: Option<&'a [u8]>,
+    /// Session information (`S`), which is left-padded with zero to the length in the suite.
+    pub session: Option<&'a [u8]>,
+    /// Timestamp (`T`) in time steps. See [OcraSuite::timestamp].
+    pub timestamp: Option<u64>,
+}
+
+/// An OCRA client for each account.
+///
+/// It holds the counter and PIN in addition to the key and suite,
+/// so it only needs the challenge question (and session information if required) to respond.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct OcraClient {
+    key: Secret<Vec<u8>>,
+    suite: OcraSuite,
+    #[serde(default)]
+    counter: u64,
+    #[serde(default)]
+    pin: Option<Secret<String>>,
+}
+
+impl OcraClient {
+    /// Create a new OCRA client.
+    ///
+    /// `counter` is the next counter value, and `pin` is needed if the suite uses PIN hash.
+    pub fn new(key: Vec<u8>, suite: OcraSuite, counter: u64, pin: Option<String>) -> OcraClient {
+        OcraClient {
+            key: Secret::new(key),
+            suite,
+            counter,
+            pin: pin.map(Secret::new),
+        }
+    }
+
+    /// Return the suite of the client.
+    pub fn suite(&self) -> &OcraSuite {
+        &self.suite
+    }
+
+    /// Return the counter value used by the next response.
+    pub fn counter(&self) -> &u64 {
+        &self.counter
+    }
+
+    /// Calculate the response to the question at given datetime.
+    ///
+    /// If the suite uses counter, it is incremented after calculation,
+    /// so the database must be saved afterwards.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use yatotp::ocra::*;
+    /// # use chrono::prelude::*;
+    /// let suite = "OCRA-1:HOTP-SHA256-8:C-QN08-PSHA1".parse().unwrap();
+    /// let key = "12345678901234567890123456789012".as_bytes().to_vec();
+    /// let mut client = OcraClient::new(key, suite, 0, Some("1234".to_string()));
+    /// assert_eq!(client.respond("12345678", None, &Utc::now()).unwrap(), "65347737");
+    /// assert_eq!(client.respond("12345678", None, &Utc::now()).unwrap(), "86775851");
+    /// ```
+    pub fn respond(
+        &mut self,
+        question: &str,
+        session: Option<&[u8]>,
+        datetime: &DateTime<Utc>,
+    ) -> Result<String> {
+        let pin_hash = match &self.pin {
+            Some(pin) => self.suite.hash_pin(pin.expose().as_bytes()),
+            None => None,
+        };
+        let input = OcraInput {
+            question,
+            counter: Some(self.counter),
+            pin_hash: pin_hash.as_deref(),
+            session,
+            timestamp: self.suite.timestamp(datetime),
+        };
+        let response = self.suite.respond(self.key.expose(), &input)?;
+        if self.suite.counter {
+            self.counter = self.counter.checked_add(1).context("Counter overflowed.")?;
+        }
+        Ok(response)
+    }
+}
+
+fn parse_hashtype(name: &str) -> Result<HashType> {
+    Ok(match name {
+        "SHA1" => HashType::Sha1,
+        "SHA256" => HashType::Sha256,
+        "SHA512" => HashType::Sha512,
+        _ => bail!("Unsupported hash function: {}", name),
+    })
+}
+
+/// Convert decimal string to hexadecimal string without leading zeros.
+fn decimal_to_hex(decimal: &str) -> String {
+    let mut bytes: Vec<u8> = Vec::new();
+    for d in decimal.bytes() {
+        let mut carry = (d - b'0') as u32;
+        for b in bytes.iter_mut().rev() {
+            let v = (*b as u32) * 10 + carry;
+            *b = v as u8;
+            carry = v >> 8;
+        }
+        if carry > 0 {
+            bytes.insert(0, carry as u8);
+        }
+    }
+    let hex = HEXUPPER.encode(&bytes);
+    match hex.trim_start_matches('0') {
+        "" => "0".to_string(),
+        hex => hex.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SEED: &[u8] = b"12345678901234567890";
+    const SEED32: &[u8] = b"12345678901234567890123456789012";
+    const SEED64: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    fn questions(n: usize) -> Vec<String> {
+        (0..n).map(|i| i.to_string().repeat(8)).collect()
+    }
+
+    #[test]
+    fn parse_suite() {
+        let suite: OcraSuite = "OCRA-1:HOTP-SHA512-8:QA10-S128-T1M".parse().unwrap();
+        assert_eq!(suite.question(), (QuestionFormat::Alphanumeric, 10));
+        assert_eq!(suite.session_len(), Some(128));
+        assert_eq!(suite.timestep(), Some(60));
+        assert!(!suite.uses_counter());
+        assert!("OCRA-2:HOTP-SHA1-6:QN08".parse::<OcraSuite>().is_err());
+        assert!("OCRA-1:HOTP-MD5-6:QN08".parse::<OcraSuite>().is_err());
+        assert!("OCRA-1:HOTP-SHA1-6:C".parse::<OcraSuite>().is_err());
+        assert!("OCRA-1:HOTP-SHA1-6:QN08-T60M".parse::<OcraSuite>().is_err());
+    }
+
+    #[test]
+    fn reject_non_ascii_suite() {
+        for suite in [
+            "OCRA-1:HOTP-SHA1-6:Qé08",
+            "OCRA-1:HOTP-SHA1-6:QN08-Té",
+            "OCRA-1:HOTP-SHA1-6:QN08-T1é",
+            "OCRA-1:HOTP-SHA1-6:QN08-Pé",
+            "OCRA-1:HOTP-SHA1-6:QN08-Sé",
+        ] {
+            assert!(suite.parse::<OcraSuite>().is_err());
+        }
+    }
+
+    #[test]
+    fn session_lengths() {
+        for (suite, len) in [
+            ("OCRA-1:HOTP-SHA1-6:QN08-S", 64),
+            ("OCRA-1:HOTP-SHA1-6:QN08-S064", 64),
+            ("OCRA-1:HOTP-SHA1-6:QN08-S128", 128),
+            ("OCRA-1:HOTP-SHA1-6:QN08-S256", 256),
+            ("OCRA-1:HOTP-SHA1-6:QN08-S512", 512),
+        ] {
+            assert_eq!(suite.parse::<OcraSuite>().unwrap().session_len(), Some(len));
+        }
+        for suite in [
+            "OCRA-1:HOTP-SHA1-6:QN08-S0",
+            "OCRA-1:HOTP-SHA1-6:QN08-S64",
+            "OCRA-1:HOTP-SHA1-6:QN08-S100",
+            "OCRA-1:HOTP-SHA1-6:QN08-S1024",
+            "OCRA-1:HOTP-SHA1-6:QN08-S+128",
+        ] {
+            assert!(suite.parse::<OcraSuite>().is_err());
+        }
+    }
+
+    #[test]
+    fn decimal_question() {
+        assert_eq!(decimal_to_hex("00000000"), "0");
+        assert_eq!(decimal_to_hex("12345678"), "BC614E");
+        assert_eq!(
+            decimal_to_hex("340282366920938463463374607431768211456"),
+            "100000000000000000000000000000000"
+        );
+    }
+
+    #[test]
+    fn rfc6287_one_way() {
+        let suite: OcraSuite = "OCRA-1:HOTP-SHA1-6:QN08".parse().unwrap();
+        let result = [
+            "237653", "243178", "653583", "740991", "608993", "388898", "816933", "224598",
+            "750600", "294470",
+        ];
+        for (q, r) in questions(10).iter().zip(result.iter()) {
+            let input = OcraInput {
+                question: q,
+                ..Default::default()
+            };
+            assert_eq!(suite.respond(SEED, &input).unwrap(), *r);
+        }
+
+        let suite: OcraSuite = "OCRA-1:HOTP-SHA256-8:C-QN08-PSHA1".parse().unwrap();
+        let pin_hash = suite.hash_pin(b"1234").unwrap();
+        assert_eq!(
+            HEXUPPER.encode(&pin_hash),
+            "7110EDA4D09E062AA5E4A390B0A572AC0D2C0220"
+        );
+        let result = [
+            "65347737", "86775851", "78192410", "71565254", "10104329", "65983500", "70069104",
+            "91771096", "75011558", "08522129",
+        ];
+        for (c, r) in result.iter().enumerate() {
+            let input = OcraInput {
+                question: "12345678",
+                counter: Some(c as u64),
+                pin_hash: Some(&pin_hash),
+                ..Default::default()
+            };
+            assert_eq!(suite.respond(SEED32, &input).unwrap(), *r);
+        }
+
+        let suite: OcraSuite = "OCRA-1:HOTP-SHA256-8:QN08-PSHA1".parse().unwrap();
+        let result = ["83238735", "01501458", "17957585", "86776967", "86807031"];
+        for (q, r) in questions(5).iter().zip(result.iter()) {
+            let input = OcraInput {
+                question: q,
+                pin_hash: Some(&pin_hash),
+                ..Default::default()
+            };
+            assert_eq!(suite.respond(SEED32, &input).unwrap(), *r);
+        }
+
+        let suite: OcraSuite = "OCRA-1:HOTP-SHA512-8:C-QN08".parse().unwrap();
+        let result = [
+            "07016083", "63947962", "70123924", "25341727", "33203315", "34205738", "44343969",
+            "51946085", "20403879", "31409299",
+        ];
+        for (c, (q, r)) in questions(10).iter().zip(result.iter()).enumerate() {
+            let input = OcraInput {
+                question: q,
+                counter: Some(c as u64),
+                ..Default::default()
+            };
+            assert_eq!(suite.respond(SEED64, &input).unwrap(), *r);
+        }
+
+        let suite: OcraSuite = "OCRA-1:HOTP-SHA512-8:QN08-T1M".parse().unwrap();
+        let timestamp = suite.timestamp(&Utc.timestamp(0x132d0b6 * 60 + 30, 0));
+        assert_eq!(timestamp, Some(0x132d0b6));
+        assert_eq!(suite.timestamp(&Utc.timestamp(-1, 0)), None);
+        let result = ["95209754", "55907591", "22048402", "24218844", "36209546"];
+        for (q, r) in questions(5).iter().zip(result.iter()) {
+            let input = OcraInput {
+                question: q,
+                timestamp,
+                ..Default::default()
+            };
+            assert_eq!(suite.respond(SEED64, &input).unwrap(), *r);
+        }
+    }
+
+    #[test]
+    fn rfc6287_signature() {
+        let suite: OcraSuite = "OCRA-1:HOTP-SHA256-8:QA08".parse().unwrap();
+        let cases = [("SIG10000", "53095496"), ("SIG11000", "04110475")];
+        for (q, r) in cases.iter() {
+            let input = OcraInput {
+                question: q,
+                ..Default::default()
+            };
+            assert_eq!(suite.respond(SEED32, &input).unwrap(), *r);
+        }
+
+        let suite: OcraSuite = "OCRA-1:HOTP-SHA512-8:QA10-T1M".parse().unwrap();
+        let input = OcraInput {
+            question: "SIG1000000",
+            timestamp: Some(0x132d0b6),
+            ..Default::default()
+        };
+        assert_eq!(suite.respond(SEED64, &input).unwrap(), "77537423");
+    }
+
+    #[test]
+    fn client_counter() {
+        let suite = "OCRA-1:HOTP-SHA256-8:C-QN08-PSHA1".parse().unwrap();
+        let mut client = OcraClient::new(SEED32.to_vec(), suite, 0, Some("1234".to_string()));
+        assert_eq!(
+            client.respond("12345678", None, &Utc::now()).unwrap(),
+            "65347737"
+        );
+        assert_eq!(
+            client.respond("12345678", None, &Utc::now()).unwrap(),
+            "86775851"
+        );
+        assert_eq!(*client.counter(), 2);
+        let serialized = serde_json::to_string(&client).unwrap();
+        assert_eq!(
+            serde_json::from_str::<OcraClient>(&serialized).unwrap(),
+            client
+        );
+    }
+}
@@ -23,22 +23,29 @@
 //!
 //! [RFC 6238]: https://datatracker.ietf.org/doc/html/rfc6238
 
-use anyhow::{Context, Result};
+use crate::secret::Secret;
+use anyhow::{ensure, Context, Result};
 use chrono::prelude::*;
 use data_encoding::BASE32;
+use hmac::digest::{Digest, KeyInit};
 use hmac::{Hmac, Mac};
 use serde;
 use sha1::Sha1;
 use sha2::{Sha256, Sha512};
+use sha3::{Sha3_256, Sha3_512};
+use std::collections::{BTreeMap, BTreeSet};
+use std::marker::PhantomData;
+use std::sync::OnceLock;
 
 /// Hash function used in HMAC calculation.
 ///
 /// Basically, [RFC 6238] uses SHA-1 hash function like [RFC 4226],
 /// but it also suggest that implementations MAY use SHA-256 and SHA-512.
+/// SHA-3 is not mentioned in RFC, but some services use it in the same way.
 ///
 /// [RFC 6238]: https://datatracker.ietf.org/doc/html/rfc6238
 /// [RFC 4226]: https://datatracker.ietf.org/doc/html/rfc4226
-#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Clone, Copy)]
 pub enum HashType {
     /// Use SHA-1 as a hash function.
     Sha1,
@@ -46,53 +53,281 @@ pub enum HashType {
     Sha256,
     /// Use SHA-2-512 as a hash function.
     Sha512,
+    /// Use SHA-3-256 as a hash function.
+    Sha3_256,
+    /// Use SHA-3-512 as a hash function.
+    Sha3_512,
+}
+
+impl HashType {
+    /// Calculate HMAC of the message with this hash function.
+    ///
+    /// # Example
+    /// ```
+    /// # use yatotp::otp::*;
+    /// assert_eq!(HashType::Sha3_256.hmac(b"key", b"message").len(), 32);
+    /// ```
+    pub fn hmac(&self, key: &[u8], message: &[u8]) -> Vec<u8> {
+        self.keyed(key).hmac(message)
+    }
+
+    /// Calculate hash of the data with this hash function.
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        self.function().digest(data)
+    }
+
+    /// Return output size of this hash function in bytes.
+    pub fn output_len(&self) -> usize {
+        self.function().output_len()
+    }
+
+    fn keyed(&self, key: &[u8]) -> Box<dyn KeyedHmac> {
+        self.function().keyed(key)
+    }
+
+    /// Return the implementation of the hash function.
+    ///
+    /// This is the only place to be changed when a new hash function is added,
+    /// other than the variant of [HashType] itself.
+    fn function(&self) -> &'static dyn HashFunction {
+        match self {
+            HashType::Sha1 => &Hasher::<Sha1, Hmac<Sha1>>(PhantomData),
+            HashType::Sha256 => &Hasher::<Sha256, Hmac<Sha256>>(PhantomData),
+            HashType::Sha512 => &Hasher::<Sha512, Hmac<Sha512>>(PhantomData),
+            HashType::Sha3_256 => &Hasher::<Sha3_256, Hmac<Sha3_256>>(PhantomData),
+            HashType::Sha3_512 => &Hasher::<Sha3_512, Hmac<Sha3_512>>(PhantomData),
+        }
+    }
+}
+
+/// Object-safe interface of the hash function, to be selected by [HashType] at runtime.
+trait HashFunction: Sync {
+    fn keyed(&self, key: &[u8]) -> Box<dyn KeyedHmac>;
+    fn digest(&self, data: &[u8]) -> Vec<u8>;
+    fn output_len(&self) -> usize;
+}
+
+/// [HashFunction] implemented on any hash function `D` of [digest] crate, with its HMAC `M`.
+struct Hasher<D, M>(PhantomData<(D, M)>);
+
+impl<D, M> HashFunction for Hasher<D, M>
+where
+    D: Digest + Sync,
+    M: KeyInit + Mac + Clone + Send + Sync + 'static,
+{
+    fn keyed(&self, key: &[u8]) -> Box<dyn KeyedHmac> {
+        Box::new(<M as KeyInit>::new_from_slice(key).expect("HMAC can take key of any size"))
+    }
+
+    fn digest(&self, data: &[u8]) -> Vec<u8> {
+        D::digest(data).to_vec()
+    }
+
+    fn output_len(&self) -> usize {
+        <D as Digest>::output_size()
+    }
 }
 
+/// HMAC whose key is already absorbed, so it can be calculated for many messages cheaply.
+trait KeyedHmac: Send + Sync {
+    /// Calculate HMAC of the message and pass it to `f`, without heap allocation.
+    fn with_hmac(&self, message: &[u8], f: &mut dyn FnMut(&[u8]));
+
+    fn hmac(&self, message: &[u8]) -> Vec<u8> {
+        let mut hs = Vec::new();
+        self.with_hmac(message, &mut |h| hs.extend_from_slice(h));
+        hs
+    }
+
+    fn truncate(&self, message: &[u8]) -> u32 {
+        let mut bin_code = 0;
+        self.with_hmac(message, &mut |h| {
+            bin_code = u32::from_be_bytes(dynamic_truncate(h))
+        });
+        bin_code
+    }
+}
+
+// The key is absorbed once, and the keyed state is cloned for each message.
+impl<M: Mac + Clone + Send + Sync> KeyedHmac for M {
+    fn with_hmac(&self, message: &[u8], f: &mut dyn FnMut(&[u8])) {
+        let mut mac = self.clone();
+        mac.update(message);
+        f(&mac.finalize().into_bytes());
+    }
+}
+
+/// Lazily initialized [KeyedHmac] of the client.
+///
+/// It is not serialized, and ignored in comparison.
+#[derive(Default)]
+struct KeyedHmacCache(OnceLock<Box<dyn KeyedHmac>>);
+
+impl std::fmt::Debug for KeyedHmacCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KeyedHmacCache")
+    }
+}
+
+impl PartialEq for KeyedHmacCache {
+    fn eq(&self, _: &KeyedHmacCache) -> bool {
+        true
+    }
+}
+
+/// Output encoding of the OTP value.
+///
+/// [RFC 4226] encodes the truncated HMAC value as decimal digits,
+/// but some services use their own encoding on the same truncated value.
+/// In any encoding, the length of the code is the digit of the client.
+///
+/// [RFC 4226]: https://datatracker.ietf.org/doc/html/rfc4226
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Clone, Default)]
+pub enum Encoding {
+    /// Decimal digits, as defined in RFC 4226.
+    #[default]
+    Decimal,
+    /// Lowercase hexadecimal digits, zero-padded like [Encoding::Decimal].
+    Hex,
+    /// Steam Guard code, which consists of characters in [STEAM_ALPHABET].
+    Steam,
+    /// Characters in the custom alphabet.
+    ///
+    /// The truncated value is repeatedly divided by the size of the alphabet,
+    /// and the remainder of each division selects the next character, as Steam Guard does.
+    Alphabet(Alphabet),
+}
+
+impl Encoding {
+    /// Encode the truncated value into the code of given length.
+    ///
+    /// # Example
+    /// ```
+    /// # use yatotp::otp::*;
+    /// assert_eq!(Encoding::Decimal.encode(1284755224, 6), "755224");
+    /// assert_eq!(Encoding::Hex.encode(0x4c93cf18, 6), "93cf18");
+    /// let binary = Encoding::Alphabet(Alphabet::new("01").unwrap());
+    /// assert_eq!(binary.encode(6, 4), "0110");
+    /// ```
+    pub fn encode(&self, bin_code: u32, digit: u32) -> String {
+        let digit = digit as usize;
+        match self {
+            Encoding::Decimal => format!(
+                "{:0>digit$}",
+                match 10u64.checked_pow(digit as u32) {
+                    Some(modulus) => bin_code as u64 % modulus,
+                    None => bin_code as u64,
+                },
+                digit = digit
+            ),
+            Encoding::Hex => {
+                let code = format!("{:08x}", bin_code);
+                format!(
+                    "{:0>digit$}",
+                    &code[code.len().saturating_sub(digit)..],
+                    digit = digit
+                )
+            }
+            Encoding::Steam => encode_alphabet(STEAM_ALPHABET.chars().collect(), bin_code, digit),
+            Encoding::Alphabet(alphabet) => {
+                encode_alphabet(alphabet.0.chars().collect(), bin_code, digit)
+            }
+        }
+    }
+}
+
+fn encode_alphabet(alphabet: Vec<char>, mut bin_code: u32, digit: usize) -> String {
+    let len = alphabet.len() as u32;
+    let mut code = String::with_capacity(digit);
+    for _ in 0..digit {
+        code.push(alphabet[(bin_code % len) as usize]);
+        bin_code /= len;
+    }
+    code
+}
+
+/// Alphabet of [Encoding::Alphabet], which has at least two distinct characters.
+#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Alphabet(String);
+
+impl Alphabet {
+    /// Create a new alphabet from its characters.
+    ///
+    /// Returns error if it has less than two characters or duplicated characters.
+    pub fn new(alphabet: &str) -> Result<Alphabet> {
+        let chars: Vec<char> = alphabet.chars().collect();
+        ensure!(
+            chars.len() >= 2,
+            "Alphabet must have at least two characters."
+        );
+        ensure!(
+            chars.iter().collect::<BTreeSet<_>>().len() == chars.len(),
+            "Alphabet must not have duplicated characters."
+        );
+        Ok(Alphabet(alphabet.to_string()))
+    }
+}
+
+impl TryFrom<String> for Alphabet {
+    type Error = anyhow::Error;
+
+    fn try_from(alphabet: String) -> Result<Alphabet> {
+        Alphabet::new(&alphabet)
+    }
+}
+
+impl From<Alphabet> for String {
+    fn from(alphabet: Alphabet) -> String {
+        alphabet.0
+    }
+}
+
+/// Characters used in Steam Guard codes.
+pub const STEAM_ALPHABET: &str = "23456789BCDFGHJKMNPQRTVWXY";
+
+/// Length of Steam Guard codes.
+pub const STEAM_DIGIT: u32 = 5;
+
 #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
 struct HotpClient {
-    key: Vec<u8>,
+    key: Secret<Vec<u8>>,
     digit: u32,
     hashtype: HashType,
+    #[serde(default)]
+    encoding: Encoding,
+    #[serde(skip)]
+    keyed: KeyedHmacCache,
 }
 
 impl HotpClient {
     fn new(key: Vec<u8>, digit: u32, hashtype: HashType) -> HotpClient {
         HotpClient {
-            key,
+            key: Secret::new(key),
             digit,
             hashtype,
+            encoding: Encoding::Decimal,
+            keyed: KeyedHmacCache::default(),
         }
     }
 
     fn hotp(&self, counter: &u64) -> u32 {
-        let hs = match self.hashtype {
-            HashType::Sha1 => self.hmac_sha1(counter),
-            HashType::Sha256 => self.hmac_sha256(counter),
-            HashType::Sha512 => self.hmac_sha512(counter),
-        };
-        let bin_code = u32::from_be_bytes(dynamic_truncate(&hs));
-        bin_code % 10u32.pow(self.digit)
-    }
-
-    fn hmac_sha1(&self, counter: &u64) -> Vec<u8> {
-        let mut hasher =
-            Hmac::<Sha1>::new_from_slice(&self.key).expect("HMAC can take key of any size");
-        hasher.update(&counter.to_be_bytes());
-        hasher.finalize().into_bytes().to_vec()
+        let bin_code = self.truncate(counter);
+        match 10u32.checked_pow(self.digit) {
+            Some(modulus) => bin_code % modulus,
+            None => bin_code,
+        }
     }
 
-    fn hmac_sha256(&self, counter: &u64) -> Vec<u8> {
-        let mut hasher =
-            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC can take key of any size");
-        hasher.update(&counter.to_be_bytes());
-        hasher.finalize().into_bytes().to_vec()
+    fn code(&self, counter: &u64) -> String {
+        self.encoding.encode(self.truncate(counter), self.digit)
     }
 
-    fn hmac_sha512(&self, counter: &u64) -> Vec<u8> {
-        let mut hasher =
-            Hmac::<Sha512>::new_from_slice(&self.key).expect("HMAC can take key of any size");
-        hasher.update(&counter.to_be_bytes());
-        hasher.finalize().into_bytes().to_vec()
+    fn truncate(&self, counter: &u64) -> u32 {
+        self.keyed
+            .0
+            .get_or_init(|| self.hashtype.keyed(self.key.expose()))
+            .truncate(&counter.to_be_bytes())
     }
 }
 
@@ -109,19 +344,21 @@ impl HotpClient {
 /// let totp = TotpClient::new("12345678901234567890".as_bytes().to_vec(), 30, 0, 8, HashType::Sha1);
 /// let datetime = Utc.datetime_from_str("1970-01-01 00:00:59", "%Y-%m-%d %H:%M:%S").unwrap();
 /// // The first test vector in RFC 6238 Appendix B.
-/// assert_eq!(totp.totp(&datetime), 94287082);
+/// assert_eq!(totp.totp(&datetime).unwrap(), 94287082);
 ///
 /// // Construct TotpClient with base32-encoded secret key.
 /// let totp = TotpClient::from_base32key(
 ///    "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA====".to_string(),
 ///    30, 0, 8, HashType::Sha256).unwrap();
 /// // The second test vector.
-/// assert_eq!(totp.totp(&datetime), 46119246);
+/// assert_eq!(totp.totp(&datetime).unwrap(), 46119246);
 /// ```
 pub struct TotpClient {
     hotp: HotpClient,
     timestep: u64,
     t0: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
 }
 
 impl TotpClient {
@@ -129,7 +366,12 @@ impl TotpClient {
     /// See examples in [TotpClient].
     pub fn new(key: Vec<u8>, timestep: u64, t0: u64, digit: u32, hashtype: HashType) -> TotpClient {
         let hotp = HotpClient::new(key, digit, hashtype);
-        TotpClient { hotp, timestep, t0 }
+        TotpClient {
+            hotp,
+            timestep,
+            t0,
+            metadata: BTreeMap::new(),
+        }
     }
 
     /// Create a new TOTP client with base32-encoded key.
@@ -141,15 +383,48 @@ impl TotpClient {
         digit: u32,
         hashtype: HashType,
     ) -> Result<TotpClient> {
+        let key = Secret::new(key);
         let key = BASE32
-            .decode(key.as_bytes())
+            .decode(key.expose().as_bytes())
             .context("Failed to decode base32-encoded key.")?;
         let hotp = HotpClient::new(key, digit, hashtype);
-        Ok(TotpClient { hotp, timestep, t0 })
+        Ok(TotpClient {
+            hotp,
+            timestep,
+            t0,
+            metadata: BTreeMap::new(),
+        })
+    }
+
+    /// Create a new Steam Guard client.
+    ///
+    /// Steam Guard uses SHA-1, 30 seconds time step
+    /// and 5 characters code encoded by [Encoding::Steam].
+    ///
+    /// # Example
+    /// ```
+    /// # use yatotp::otp::*;
+    /// # use chrono::prelude::*;
+    ///
+    /// let steam = TotpClient::new_steam("12345678901234567890".as_bytes().to_vec());
+    /// assert_eq!(steam.code(&Utc.timestamp(59, 0)).unwrap().len(), 5);
+    /// ```
+    pub fn new_steam(key: Vec<u8>) -> TotpClient {
+        TotpClient::new(key, 30, 0, STEAM_DIGIT, HashType::Sha1).with_encoding(Encoding::Steam)
+    }
+
+    /// Replace output encoding of the client.
+    pub fn with_encoding(mut self, encoding: Encoding) -> TotpClient {
+        self.hotp.encoding = encoding;
+        self
     }
 
     /// Calculate the TOTP value of given datetime.
     ///
+    /// The value is the decimal one defined in RFC 6238 regardless of [Encoding].
+    /// Use [TotpClient::code] to get the value encoded for display.
+    /// Returns `None` if the datetime is before T0.
+    ///
     /// # Examples
     /// ```
     /// # use yatotp::otp::*;
@@ -158,18 +433,196 @@ impl TotpClient {
     /// # let totp = TotpClient::new("12345678901234567890".as_bytes().to_vec(), 30, 0, 8, HashType::Sha1);
     /// // Get present TOTP value
     /// totp.totp(&Utc::now());
-    pub fn totp(&self, datetime: &DateTime<Utc>) -> u32 {
-        let t = ((datetime.timestamp() as u64) - self.t0) / self.timestep;
-        self.hotp.hotp(&t)
+    /// // No value before T0
+    /// assert_eq!(totp.totp(&Utc.timestamp(-1, 0)), None);
+    /// ```
+    pub fn totp(&self, datetime: &DateTime<Utc>) -> Option<u32> {
+        Some(self.hotp.hotp(&self.counter(datetime)?))
+    }
+
+    /// Calculate the OTP code of given datetime, encoded by the client's [Encoding].
+    ///
+    /// Returns `None` if the datetime is before T0.
+    ///
+    /// # Examples
+    /// ```
+    /// # use yatotp::otp::*;
+    /// # use chrono::prelude::*;
+    ///
+    /// # let totp = TotpClient::new("12345678901234567890".as_bytes().to_vec(), 30, 0, 8, HashType::Sha1);
+    /// let datetime = Utc.datetime_from_str("2005-03-18 01:58:29", "%Y-%m-%d %H:%M:%S").unwrap();
+    /// // Decimal code is zero-padded to the digit.
+    /// assert_eq!(totp.code(&datetime).unwrap(), "07081804");
+    /// ```
+    pub fn code(&self, datetime: &DateTime<Utc>) -> Option<String> {
+        Some(self.hotp.code(&self.counter(datetime)?))
+    }
+
+    /// Calculate TOTP values of many time-step counters at once.
+    ///
+    /// The HMAC key is processed only once per client and no memory is allocated,
+    /// so it is suitable to verify codes of wide time windows for many accounts.
+    ///
+    /// # Panics
+    /// Panics if `counters` and `results` have different length.
+    ///
+    /// # Examples
+    /// ```
+    /// # use yatotp::otp::*;
+    /// # use chrono::prelude::*;
+    ///
+    /// # let totp = TotpClient::new("12345678901234567890".as_bytes().to_vec(), 30, 0, 8, HashType::Sha1);
+    /// let datetime = Utc.timestamp(59, 0);
+    /// let counter = totp.counter(&datetime).unwrap();
+    /// let mut results = [0; 3];
+    /// totp.totp_batch(&[counter - 1, counter, counter + 1], &mut results);
+    /// assert_eq!(results[1], 94287082);
+    /// ```
+    pub fn totp_batch(&self, counters: &[u64], results: &mut [u32]) {
+        assert_eq!(
+            counters.len(),
+            results.len(),
+            "counters and results must have the same length"
+        );
+        for (counter, result) in counters.iter().zip(results.iter_mut()) {
+            *result = self.hotp.hotp(counter);
+        }
+    }
+
+    /// Return the time-step counter `T` of given datetime.
+    ///
+    /// Returns `None` if the datetime is before T0.
+    ///
+    /// # Examples
+    /// ```
+    /// # use yatotp::otp::*;
+    /// # use chrono::prelude::*;
+    ///
+    /// # let totp = TotpClient::new("12345678901234567890".as_bytes().to_vec(), 30, 0, 8, HashType::Sha1);
+    /// assert_eq!(totp.counter(&Utc.timestamp(59, 0)), Some(1));
+    /// assert_eq!(totp.counter(&Utc.timestamp(-1, 0)), None);
+    /// ```
+    pub fn counter(&self, datetime: &DateTime<Utc>) -> Option<u64> {
+        let elapsed = u64::try_from(datetime.timestamp())
+            .ok()?
+            .checked_sub(self.t0)?;
+        Some(elapsed / self.timestep)
+    }
+
+    /// Return the first instant of the time window which contains given datetime.
+    ///
+    /// Returns `None` if the datetime is before T0.
+    pub fn window_start(&self, datetime: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.window_at(self.counter(datetime)?)
+    }
+
+    /// Return the first instant of the time window next to the one containing given datetime.
+    ///
+    /// Returns `None` if the datetime is before T0 or the window is out of range.
+    pub fn window_end(&self, datetime: &DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.window_at(self.counter(datetime)?.checked_add(1)?)
+    }
+
+    fn window_at(&self, counter: u64) -> Option<DateTime<Utc>> {
+        let start = counter.checked_mul(self.timestep)?.checked_add(self.t0)?;
+        Utc.timestamp_opt(i64::try_from(start).ok()?, 0).single()
+    }
+
+    /// Return seconds remaining until the TOTP value of given datetime expires.
+    ///
+    /// # Examples
+    /// ```
+    /// # use yatotp::otp::*;
+    /// # use chrono::prelude::*;
+    ///
+    /// # let totp = TotpClient::new("12345678901234567890".as_bytes().to_vec(), 30, 0, 8, HashType::Sha1);
+    /// assert_eq!(totp.remaining_seconds(&Utc.timestamp(59, 0)), Some(1));
+    /// ```
+    pub fn remaining_seconds(&self, datetime: &DateTime<Utc>) -> Option<u64> {
+        Some((self.window_end(datetime)?.timestamp() - datetime.timestamp()) as u64)
+    }
+
+    /// Iterate TOTP values of consecutive time windows,
+    /// starting from the window which contains given datetime.
+    ///
+    /// # Examples
+    /// ```
+    /// # use yatotp::otp::*;
+    /// # use chrono::prelude::*;
+    ///
+    /// # let totp = TotpClient::new("12345678901234567890".as_bytes().to_vec(), 30, 0, 8, HashType::Sha1);
+    /// let datetime = Utc.timestamp(59, 0);
+    /// let codes: Vec<u32> = totp.codes(&datetime).unwrap().take(2).collect();
+    /// assert_eq!(codes, vec![94287082, totp.totp(&Utc.timestamp(60, 0)).unwrap()]);
+    /// ```
+    pub fn codes(&self, datetime: &DateTime<Utc>) -> Option<TotpCodes<'_>> {
+        Some(TotpCodes {
+            hotp: &self.hotp,
+            counter: Some(self.counter(datetime)?),
+        })
     }
 
     /// Return digit of the TOTP.
     pub fn digit(&self) -> &u32 {
         &self.hotp.digit
     }
+
+    /// Return time step of the TOTP in seconds.
+    pub fn timestep(&self) -> &u64 {
+        &self.timestep
+    }
+
+    /// Return time to start counting time steps from, in Unix time.
+    pub fn t0(&self) -> &u64 {
+        &self.t0
+    }
+
+    /// Return hash function of the TOTP.
+    pub fn hashtype(&self) -> &HashType {
+        &self.hotp.hashtype
+    }
+
+    /// Return output encoding of the TOTP.
+    pub fn encoding(&self) -> &Encoding {
+        &self.hotp.encoding
+    }
+
+    /// Return the secret key.
+    pub(crate) fn key(&self) -> &[u8] {
+        self.hotp.key.expose()
+    }
+
+    /// Return additional information of the account, such as account name or recovery code.
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    /// Return mutable reference to additional information of the account.
+    pub fn metadata_mut(&mut self) -> &mut BTreeMap<String, String> {
+        &mut self.metadata
+    }
+}
+
+/// Iterator over TOTP values of consecutive time windows.
+///
+/// Created by [TotpClient::codes].
+pub struct TotpCodes<'a> {
+    hotp: &'a HotpClient,
+    /// The counter of the next code, which is `None` after the code of `u64::MAX`.
+    counter: Option<u64>,
+}
+
+impl<'a> Iterator for TotpCodes<'a> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let counter = self.counter?;
+        self.counter = counter.checked_add(1);
+        Some(self.hotp.hotp(&counter))
+    }
 }
 
-fn dynamic_truncate(hs: &[u8]) -> [u8; 4] {
+pub(crate) fn dynamic_truncate(hs: &[u8]) -> [u8; 4] {
     let offset = (hs.last().unwrap() & 0xf) as usize;
     [
         hs[offset] & 0x7f,
@@ -198,6 +651,91 @@ mod test {
         }
     }
 
+    #[test]
+    fn sha3_hotp() {
+        // Calculated with HMAC-SHA3 of Python's hmac module, as RFC does not give test vectors.
+        let cases = [
+            (HashType::Sha3_256, [170828, 902588, 810314]),
+            (HashType::Sha3_512, [342230, 625483, 819892]),
+        ];
+        for (hashtype, result) in cases.iter() {
+            let hotp = HotpClient::new("12345678901234567890".as_bytes().to_vec(), 6, *hashtype);
+            for (c, r) in result.iter().enumerate() {
+                assert_eq!(hotp.hotp(&(c as u64)), *r);
+            }
+        }
+    }
+
+    #[test]
+    fn precomputed_hmac_long_key() {
+        // Test case 6 of RFC 2202 and RFC 4231, whose key is longer than block size.
+        let message = b"Test Using Larger Than Block-Size Key - Hash Key First";
+        let cases = [
+            (
+                HashType::Sha1,
+                80,
+                "aa4ae5e15272d00e95705637ce8a3b55ed402112",
+            ),
+            (
+                HashType::Sha256,
+                131,
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                HashType::Sha512,
+                131,
+                "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f352\
+                 6b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598",
+            ),
+        ];
+        for (hashtype, key_len, expected) in cases.iter() {
+            let hs = hashtype.hmac(&vec![0xaa; *key_len], message);
+            assert_eq!(data_encoding::HEXLOWER.encode(&hs), *expected);
+        }
+    }
+
+    #[test]
+    fn totp_batch() {
+        let totp = TotpClient::new(
+            "12345678901234567890".as_bytes().to_vec(),
+            30,
+            0,
+            6,
+            HashType::Sha1,
+        );
+        let counters: Vec<u64> = (0..10).collect();
+        let mut results = vec![0; 10];
+        totp.totp_batch(&counters, &mut results);
+        assert_eq!(
+            results,
+            vec![755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489]
+        );
+    }
+
+    #[test]
+    fn debug_redacts_key() {
+        let totp = TotpClient::new(vec![0xab; 20], 30, 0, 6, HashType::Sha1);
+        let debug = format!("{:?}", totp);
+        assert!(debug.contains("[REDACTED]"));
+        assert!(!debug.contains("171"));
+    }
+
+    #[test]
+    fn hashtype_names() {
+        // Names of hash functions are stored in database files, so they must not be changed.
+        let names = [
+            (HashType::Sha1, r#""Sha1""#),
+            (HashType::Sha256, r#""Sha256""#),
+            (HashType::Sha512, r#""Sha512""#),
+            (HashType::Sha3_256, r#""Sha3_256""#),
+            (HashType::Sha3_512, r#""Sha3_512""#),
+        ];
+        for (hashtype, name) in names.iter() {
+            assert_eq!(serde_json::to_string(hashtype).unwrap(), *name);
+            assert_eq!(hashtype.digest(b"").len(), hashtype.output_len());
+        }
+    }
+
     #[test]
     fn rfc6238_example_sha1() {
         let totp = TotpClient::new(
@@ -212,23 +750,23 @@ mod test {
         let datetime = Utc
             .datetime_from_str("1970-01-01 00:00:59", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 94287082);
+        assert_eq!(totp.totp(&datetime).unwrap(), 94287082);
         let datetime = Utc
             .datetime_from_str("2005-03-18 01:58:29", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 7081804);
+        assert_eq!(totp.totp(&datetime).unwrap(), 7081804);
         let datetime = Utc
             .datetime_from_str("2009-02-13 23:31:30", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 89005924);
+        assert_eq!(totp.totp(&datetime).unwrap(), 89005924);
         let datetime = Utc
             .datetime_from_str("2033-05-18 03:33:20", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 69279037);
+        assert_eq!(totp.totp(&datetime).unwrap(), 69279037);
         let datetime = Utc
             .datetime_from_str("2603-10-11 11:33:20", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 65353130);
+        assert_eq!(totp.totp(&datetime).unwrap(), 65353130);
     }
 
     #[test]
@@ -245,23 +783,23 @@ mod test {
         let datetime = Utc
             .datetime_from_str("1970-01-01 00:00:59", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 46119246);
+        assert_eq!(totp.totp(&datetime).unwrap(), 46119246);
         let datetime = Utc
             .datetime_from_str("2005-03-18 01:58:29", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 68084774);
+        assert_eq!(totp.totp(&datetime).unwrap(), 68084774);
         let datetime = Utc
             .datetime_from_str("2009-02-13 23:31:30", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 91819424);
+        assert_eq!(totp.totp(&datetime).unwrap(), 91819424);
         let datetime = Utc
             .datetime_from_str("2033-05-18 03:33:20", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 90698825);
+        assert_eq!(totp.totp(&datetime).unwrap(), 90698825);
         let datetime = Utc
             .datetime_from_str("2603-10-11 11:33:20", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 77737706);
+        assert_eq!(totp.totp(&datetime).unwrap(), 77737706);
     }
 
     #[test]
@@ -280,22 +818,127 @@ mod test {
         let datetime = Utc
             .datetime_from_str("1970-01-01 00:00:59", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 90693936);
+        assert_eq!(totp.totp(&datetime).unwrap(), 90693936);
         let datetime = Utc
             .datetime_from_str("2005-03-18 01:58:29", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 25091201);
+        assert_eq!(totp.totp(&datetime).unwrap(), 25091201);
         let datetime = Utc
             .datetime_from_str("2009-02-13 23:31:30", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 93441116);
+        assert_eq!(totp.totp(&datetime).unwrap(), 93441116);
         let datetime = Utc
             .datetime_from_str("2033-05-18 03:33:20", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 38618901);
+        assert_eq!(totp.totp(&datetime).unwrap(), 38618901);
         let datetime = Utc
             .datetime_from_str("2603-10-11 11:33:20", datetime_format)
             .unwrap();
-        assert_eq!(totp.totp(&datetime), 47863826);
+        assert_eq!(totp.totp(&datetime).unwrap(), 47863826);
+    }
+
+    #[test]
+    fn steam_code() {
+        let steam = TotpClient::new_steam("12345678901234567890".as_bytes().to_vec());
+        let datetime = Utc.timestamp(59, 0);
+        // Truncated value of counter 1 is 0x41397eea in RFC 4226 Appendix D.
+        assert_eq!(steam.code(&datetime).unwrap(), "PV9M4");
+    }
+
+    #[test]
+    fn custom_encoding() {
+        let hotp = HotpClient::new(
+            "12345678901234567890".as_bytes().to_vec(),
+            6,
+            HashType::Sha1,
+        );
+        // Truncated values are 0x4c93cf18 and 0x41397eea in RFC 4226 Appendix D.
+        assert_eq!(hotp.code(&0), "755224");
+        let hotp = HotpClient {
+            encoding: Encoding::Hex,
+            ..hotp
+        };
+        assert_eq!(hotp.code(&0), "93cf18");
+        let hotp = HotpClient {
+            encoding: Encoding::Alphabet(Alphabet::new(STEAM_ALPHABET).unwrap()),
+            digit: 5,
+            ..hotp
+        };
+        assert_eq!(hotp.code(&1), "PV9M4");
+
+        assert!(Alphabet::new("0").is_err());
+        assert!(Alphabet::new("0120").is_err());
+        let encoding = Encoding::Alphabet(Alphabet::new("abc").unwrap());
+        let serialized = serde_json::to_string(&encoding).unwrap();
+        assert_eq!(serialized, r#"{"Alphabet":"abc"}"#);
+        assert_eq!(
+            serde_json::from_str::<Encoding>(&serialized).unwrap(),
+            encoding
+        );
+        assert!(serde_json::from_str::<Encoding>(r#"{"Alphabet":"aa"}"#).is_err());
+    }
+
+    #[test]
+    fn time_window() {
+        let totp = TotpClient::new(
+            "12345678901234567890".as_bytes().to_vec(),
+            30,
+            15,
+            8,
+            HashType::Sha1,
+        );
+        let datetime = Utc.timestamp(100, 0);
+        assert_eq!(totp.counter(&datetime), Some(2));
+        assert_eq!(totp.window_start(&datetime), Some(Utc.timestamp(75, 0)));
+        assert_eq!(totp.window_end(&datetime), Some(Utc.timestamp(105, 0)));
+        assert_eq!(totp.remaining_seconds(&datetime), Some(5));
+        let codes: Vec<u32> = totp.codes(&datetime).unwrap().take(3).collect();
+        assert_eq!(
+            codes,
+            vec![
+                totp.totp(&Utc.timestamp(75, 0)).unwrap(),
+                totp.totp(&Utc.timestamp(105, 0)).unwrap(),
+                totp.totp(&Utc.timestamp(135, 0)).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn before_t0() {
+        let totp = TotpClient::new(
+            "12345678901234567890".as_bytes().to_vec(),
+            30,
+            15,
+            8,
+            HashType::Sha1,
+        );
+        for timestamp in [14, 0, -1, -315619200] {
+            let datetime = Utc.timestamp(timestamp, 0);
+            assert_eq!(totp.counter(&datetime), None);
+            assert_eq!(totp.code(&datetime), None);
+            assert_eq!(totp.window_start(&datetime), None);
+            assert_eq!(totp.remaining_seconds(&datetime), None);
+            assert!(totp.codes(&datetime).is_none());
+        }
+        assert_eq!(totp.counter(&Utc.timestamp(15, 0)), Some(0));
+
+        let totp = TotpClient::new(b"key".to_vec(), u64::MAX, 0, 6, HashType::Sha1);
+        let datetime = Utc.timestamp(100, 0);
+        assert_eq!(totp.counter(&datetime), Some(0));
+        assert_eq!(totp.window_start(&datetime), Some(Utc.timestamp(0, 0)));
+        assert_eq!(totp.window_end(&datetime), None);
+    }
+
+    #[test]
+    fn last_counter() {
+        let totp = TotpClient::new(b"key".to_vec(), 30, 0, 6, HashType::Sha1);
+        let mut codes = TotpCodes {
+            hotp: &totp.hotp,
+            counter: Some(u64::MAX - 1),
+        };
+        assert_eq!(codes.next(), Some(totp.hotp.hotp(&(u64::MAX - 1))));
+        assert_eq!(codes.next(), Some(totp.hotp.hotp(&u64::MAX)));
+        assert_eq!(codes.next(), None);
+        assert_eq!(codes.next(), None);
     }
 }
@@ -0,0 +1,572 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Paper backup of entries.
+//!
+//! Each entry is printed into a sheet with a QR code. A TOTP entry which otpauth URI can represent
+//! is printed as the URI in the QR code and its secret key in plain base32, so that any
+//! authenticator app can restore it. Other entries are encoded into numbered lines of base32 text.
+//! Every line ends with its own check characters, so a typo is found at the line where it is
+//! typed in, and the whole sheet has a checksum.
+//! [render_html] renders the sheets into a self-contained HTML document with inline SVG,
+//! which can be printed or saved as PDF from a browser.
+//!
+//! # Example
+//!
+//! ```
+//! # use yatotp::{database::Entry, otp::*, paper::*, skey::*};
+//! let entry: Entry = TotpClient::from_base32key("JBSWY3DPEHPK3PXP".to_string(), 30, 0, 6, HashType::Sha1)
+//!     .unwrap()
+//!     .into();
+//! let sheet = Sheet::new("alice", &entry).unwrap();
+//! assert!(sheet.qr_text().starts_with("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP"));
+//! assert_eq!(sheet.lines()[1].as_str(), "JBSW Y3DP EHPK 3PXP");
+//! assert_eq!(decode(&sheet.qr_text()).unwrap(), ("alice".to_string(), entry));
+//!
+//! let entry: Entry = SkeyClient::new(SkeyAlgorithm::Md5, "This is a test.".to_string(), "TeSt", 99)
+//!     .unwrap()
+//!     .into();
+//! let sheet = Sheet::new("server", &entry).unwrap();
+//! let mut reader = SheetReader::default();
+//! for line in sheet.lines() {
+//!     reader.push_line(&line).unwrap();
+//! }
+//! assert_eq!(reader.finish().unwrap(), ("server".to_string(), entry));
+//! ```
+
+use crate::database::Entry;
+use crate::otp::{Encoding, TotpClient};
+use crate::qr::QrImage;
+use crate::secret::Zeroizing;
+use crate::uri;
+use anyhow::{bail, ensure, Context, Result};
+use data_encoding::BASE32_NOPAD;
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+
+const SHEET_VERSION: u8 = 1;
+/// Prefix of the text in the QR code. It and base32 are in the alphanumeric mode of QR code.
+const SHEET_PREFIX: &str = "YATOTP:";
+const HEADER_LEN: usize = 3;
+const CHECKSUM_LEN: usize = 4;
+const LINE_LEN: usize = 40;
+const GROUP_LEN: usize = 5;
+const LINE_CHECK_LEN: usize = 2;
+/// Length of the groups of the plain secret key, as most apps show.
+const SECRET_GROUP_LEN: usize = 4;
+/// Number of the groups of the plain secret key in a line.
+const SECRET_GROUPS_PER_LINE: usize = 8;
+
+/// An entry encoded to be printed.
+pub struct Sheet {
+    name: String,
+    content: Content,
+}
+
+enum Content {
+    /// otpauth URI of a TOTP entry, and its secret key and parameters to be printed in plain.
+    Otpauth {
+        uri: Zeroizing<String>,
+        secret: Zeroizing<String>,
+        parameters: String,
+    },
+    /// Check-coded base32 text of an entry which otpauth URI can not represent.
+    Encoded(Zeroizing<String>),
+}
+
+impl Sheet {
+    /// Encode the entry with its name.
+    ///
+    /// A TOTP entry is printed as otpauth URI if it can be represented in the URI.
+    pub fn new(name: &str, entry: &Entry) -> Result<Sheet> {
+        let content = match entry {
+            Entry::Totp(client) => match uri::format(name, client) {
+                Ok(uri) => otpauth_content(uri, client),
+                Err(_) => encode(name, entry)?,
+            },
+            _ => encode(name, entry)?,
+        };
+        Ok(Sheet {
+            name: name.to_string(),
+            content,
+        })
+    }
+
+    /// Return the name of the entry.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return whether the entry is printed as otpauth URI, which other apps can restore.
+    pub fn is_otpauth(&self) -> bool {
+        matches!(self.content, Content::Otpauth { .. })
+    }
+
+    /// Return the text encoded into the QR code.
+    pub fn qr_text(&self) -> Zeroizing<String> {
+        match &self.content {
+            Content::Otpauth { uri, .. } => uri.clone(),
+            Content::Encoded(encoded) => {
+                Zeroizing::new(format!("{}{}", SHEET_PREFIX, encoded.as_str()))
+            }
+        }
+    }
+
+    /// Return the lines to be printed.
+    ///
+    /// They are the secret key in groups such as `JBSW Y3DP` and the parameters of otpauth URI,
+    /// or the numbered lines such as `01 AEAGW ... 4Q` of other entries.
+    pub fn lines(&self) -> Vec<Zeroizing<String>> {
+        match &self.content {
+            Content::Otpauth {
+                secret, parameters, ..
+            } => {
+                let mut lines = vec![Zeroizing::new("Secret key:".to_string())];
+                for chunk in secret
+                    .as_bytes()
+                    .chunks(SECRET_GROUP_LEN * SECRET_GROUPS_PER_LINE)
+                {
+                    let groups: Vec<&str> = chunk
+                        .chunks(SECRET_GROUP_LEN)
+                        .map(|group| std::str::from_utf8(group).expect("Base32 is ASCII."))
+                        .collect();
+                    lines.push(Zeroizing::new(groups.join(" ")));
+                }
+                lines.push(Zeroizing::new(parameters.clone()));
+                lines
+            }
+            Content::Encoded(encoded) => encoded
+                .as_bytes()
+                .chunks(LINE_LEN)
+                .enumerate()
+                .map(|(i, chunk)| {
+                    let chunk = std::str::from_utf8(chunk).expect("Base32 is ASCII.");
+                    let mut line = Zeroizing::new(format!("{:02}", i + 1));
+                    for group in chunk.as_bytes().chunks(GROUP_LEN) {
+                        line.push(' ');
+                        line.push_str(std::str::from_utf8(group).expect("Base32 is ASCII."));
+                    }
+                    line.push(' ');
+                    line.push_str(&line_check(i + 1, chunk));
+                    line
+                })
+                .collect(),
+        }
+    }
+}
+
+fn otpauth_content(uri: Zeroizing<String>, client: &TotpClient) -> Content {
+    let algorithm = format!("{:?}", client.hashtype()).to_uppercase();
+    let parameters = match client.encoding() {
+        Encoding::Steam => "Steam Guard".to_string(),
+        _ => format!(
+            "TOTP, {}, {} digits, {} seconds",
+            algorithm,
+            client.digit(),
+            client.timestep()
+        ),
+    };
+    Content::Otpauth {
+        uri,
+        secret: Zeroizing::new(BASE32_NOPAD.encode(client.key())),
+        parameters,
+    }
+}
+
+fn encode(name: &str, entry: &Entry) -> Result<Content> {
+    let payload = Zeroizing::new(serde_json::to_vec(&(name, entry))?);
+    let len = u16::try_from(payload.len())
+        .ok()
+        .context(format!("Entry {} is too large to be printed.", name))?;
+    let mut bytes = Zeroizing::new(vec![SHEET_VERSION]);
+    bytes.extend_from_slice(&len.to_be_bytes());
+    bytes.extend_from_slice(&payload);
+    let checksum = Sha256::digest(&bytes[..]);
+    bytes.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+    Ok(Content::Encoded(Zeroizing::new(
+        BASE32_NOPAD.encode(&bytes),
+    )))
+}
+
+/// Reader of the lines of a sheet typed in one by one.
+#[derive(Default)]
+pub struct SheetReader {
+    encoded: Zeroizing<String>,
+    read: usize,
+    total_len: Option<usize>,
+}
+
+impl SheetReader {
+    /// Return the number of the line to be read next, from 1.
+    pub fn next_line(&self) -> usize {
+        self.read + 1
+    }
+
+    /// Return the number of lines of the sheet, which is known after the first line is read.
+    pub fn total_lines(&self) -> Option<usize> {
+        self.total_len.map(|len| len.div_ceil(LINE_LEN))
+    }
+
+    /// Return whether all the lines are read.
+    pub fn is_complete(&self) -> bool {
+        self.total_lines() == Some(self.read)
+    }
+
+    /// Read the next line, ignoring case and spaces in groups.
+    ///
+    /// The line is refused, and the reader is left unchanged, if it is not correctly typed.
+    pub fn push_line(&mut self, line: &str) -> Result<()> {
+        ensure!(!self.is_complete(), "All the lines are already read.");
+        let mut tokens: Vec<&str> = line.split_whitespace().collect();
+        ensure!(
+            tokens.len() >= 3,
+            "Line must be its number, base32 text and check characters separated by spaces."
+        );
+        let number: usize = tokens
+            .remove(0)
+            .parse()
+            .context("Line must begin with its number.")?;
+        ensure!(
+            number == self.next_line(),
+            "Line {} is given instead of line {}.",
+            number,
+            self.next_line()
+        );
+        let check = tokens
+            .pop()
+            .expect("Line has 3 tokens or more.")
+            .to_ascii_uppercase();
+        let chunk = Zeroizing::new(tokens.concat().to_ascii_uppercase());
+        ensure!(
+            check == line_check(number, &chunk),
+            "Line {} is not correctly typed.",
+            number
+        );
+        let total_len = match self.total_len {
+            Some(len) => len,
+            None => encoded_len(&chunk)?,
+        };
+        let expected = (total_len - self.encoded.len()).min(LINE_LEN);
+        ensure!(
+            chunk.len() == expected,
+            "Line {} must have {} characters besides its number and check characters.",
+            number,
+            expected
+        );
+        self.encoded.push_str(&chunk);
+        self.total_len = Some(total_len);
+        self.read += 1;
+        Ok(())
+    }
+
+    /// Decode the entry with its name from all the lines.
+    pub fn finish(&self) -> Result<(String, Entry)> {
+        ensure!(self.is_complete(), "Some lines are not read yet.");
+        decode_base32(&self.encoded)
+    }
+}
+
+/// Decode the entry with its name from the text of the QR code, which is otpauth URI or
+/// begins with `YATOTP:`.
+pub fn decode(text: &str) -> Result<(String, Entry)> {
+    let text = text.trim();
+    let encoded = match text.get(..SHEET_PREFIX.len()) {
+        Some(head) if head.eq_ignore_ascii_case(SHEET_PREFIX) => &text[SHEET_PREFIX.len()..],
+        _ if text.contains("://") => {
+            let parsed = uri::parse(text)?;
+            return Ok((parsed.label, parsed.client.into()));
+        }
+        _ => bail!(
+            "Text of the QR code must be otpauth URI or begin with {}.",
+            SHEET_PREFIX
+        ),
+    };
+    decode_base32(&Zeroizing::new(encoded.to_ascii_uppercase()))
+}
+
+/// Render the sheets into a self-contained HTML document to be printed.
+pub fn render_html(title: &str, sheets: &[Sheet]) -> Result<Zeroizing<String>> {
+    let mut html = Zeroizing::new(String::new());
+    write!(
+        html,
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n\
+         <style>\n\
+         body {{ font-family: sans-serif; margin: 1cm; }}\n\
+         section {{ display: flex; align-items: center; gap: 1cm; padding: 0.5cm; \
+         border: 1px solid black; margin-bottom: 0.5cm; break-inside: avoid; }}\n\
+         section svg {{ width: 5cm; height: 5cm; flex: none; }}\n\
+         h2 {{ margin-top: 0; overflow-wrap: anywhere; }}\n\
+         pre {{ font-size: 11pt; line-height: 1.5; }}\n\
+         </style>\n\
+         </head>\n\
+         <body>\n\
+         <h1>{title}</h1>\n\
+         <p>This paper contains the secret keys in plain. Keep it as safe as the keys themselves.\n\
+         Restore a TOTP entry with a secret key by scanning its QR code with an authenticator app. \
+         Restore an entry by typing in its lines, the text of its QR code or its secret key \
+         with <code>yatotp-cli paper restore</code>.</p>\n",
+        title = escape_html(title)
+    )?;
+    for sheet in sheets {
+        write!(
+            html,
+            "<section>\n{}\n<div>\n<h2>{}</h2>\n<pre>",
+            QrImage::new(&sheet.qr_text())
+                .context(format!("Entry {} is too large to be printed.", sheet.name))?
+                .to_svg(),
+            escape_html(&sheet.name)
+        )?;
+        for line in sheet.lines() {
+            writeln!(html, "{}", line.as_str())?;
+        }
+        html.push_str("</pre>\n</div>\n</section>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    Ok(html)
+}
+
+/// Render the sheets into a new HTML file, which only the owner can read.
+pub fn save_html<P: AsRef<Path>>(path: &P, title: &str, sheets: &[Sheet]) -> Result<()> {
+    let html = render_html(title, sheets)?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut f = options.open(path)?;
+    f.write_all(html.as_bytes())?;
+    Ok(())
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Check characters of the line, which are base32 of the hash of its number and text.
+fn line_check(number: usize, chunk: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(number.to_string().as_bytes());
+    hasher.update(b":");
+    hasher.update(chunk.as_bytes());
+    let mut check = BASE32_NOPAD.encode(&hasher.finalize()[..1]);
+    check.truncate(LINE_CHECK_LEN);
+    check
+}
+
+/// Length of the whole base32 text, read from the header in the first line.
+fn encoded_len(first_line: &str) -> Result<usize> {
+    let header = match first_line
+        .get(..8)
+        .and_then(|h| BASE32_NOPAD.decode(h.as_bytes()).ok())
+    {
+        Some(header) => header,
+        None => bail!("Line 1 is not correctly typed."),
+    };
+    ensure!(
+        header[0] == SHEET_VERSION,
+        "Sheet version {} is not supported.",
+        header[0]
+    );
+    let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+    Ok(((HEADER_LEN + len + CHECKSUM_LEN) * 8).div_ceil(5))
+}
+
+fn decode_base32(encoded: &str) -> Result<(String, Entry)> {
+    let bytes = match BASE32_NOPAD.decode(encoded.as_bytes()) {
+        Ok(bytes) => Zeroizing::new(bytes),
+        Err(_) => bail!("Sheet is not correctly typed."),
+    };
+    ensure!(
+        bytes.len() >= HEADER_LEN + CHECKSUM_LEN,
+        "Sheet is not correctly typed."
+    );
+    let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    ensure!(
+        Sha256::digest(body)[..CHECKSUM_LEN] == *checksum,
+        "Sheet is not correctly typed."
+    );
+    ensure!(
+        body[0] == SHEET_VERSION,
+        "Sheet version {} is not supported.",
+        body[0]
+    );
+    ensure!(
+        u16::from_be_bytes([body[1], body[2]]) as usize == body.len() - HEADER_LEN,
+        "Sheet is not correctly typed."
+    );
+    serde_json::from_slice(&body[HEADER_LEN..]).context("Failed to decode the entry.")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{otp, skey};
+
+    fn totp(hashtype: otp::HashType) -> Entry {
+        otp::TotpClient::from_base32key(
+            "HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ".to_string(),
+            60,
+            0,
+            8,
+            hashtype,
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn skey() -> Entry {
+        skey::SkeyClient::new(
+            skey::SkeyAlgorithm::Md5,
+            "This is a test.".to_string(),
+            "TeSt",
+            99,
+        )
+        .unwrap()
+        .into()
+    }
+
+    fn read_lines(lines: &[Zeroizing<String>]) -> Result<(String, Entry)> {
+        let mut reader = SheetReader::default();
+        for line in lines {
+            reader.push_line(line)?;
+        }
+        reader.finish()
+    }
+
+    #[test]
+    fn print_otpauth() {
+        let name = "ACME Co:john.doe@email.com";
+        let entry = totp(otp::HashType::Sha256);
+        let sheet = Sheet::new(name, &entry).unwrap();
+        assert!(sheet.is_otpauth());
+        let uri = sheet.qr_text();
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert_eq!(decode(&uri).unwrap(), (name.to_string(), entry));
+        let lines: Vec<String> = sheet.lines().iter().map(|l| l.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "Secret key:",
+                "HXDM VJEC JJWS RB3H WIZR 4IFU GFTM XBOZ",
+                "TOTP, SHA256, 8 digits, 60 seconds"
+            ]
+        );
+        assert_eq!(
+            uri::decode_secret(&lines[1]).unwrap(),
+            BASE32_NOPAD
+                .decode(b"HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ")
+                .unwrap()
+        );
+
+        let steam: Entry = otp::TotpClient::new_steam(b"secret".to_vec()).into();
+        let sheet = Sheet::new("steam", &steam).unwrap();
+        assert!(sheet.is_otpauth());
+        assert_eq!(sheet.lines().last().unwrap().as_str(), "Steam Guard");
+        assert_eq!(
+            decode(&sheet.qr_text()).unwrap(),
+            ("steam".to_string(), steam)
+        );
+    }
+
+    #[test]
+    fn print_and_restore() {
+        let entries = vec![
+            ("ACME Co:john.doe@email.com", totp(otp::HashType::Sha3_256)),
+            ("server", skey()),
+        ];
+        for (name, entry) in entries {
+            let sheet = Sheet::new(name, &entry).unwrap();
+            assert!(!sheet.is_otpauth());
+            let expected = (name.to_string(), entry);
+            assert_eq!(read_lines(&sheet.lines()).unwrap(), expected);
+            assert_eq!(decode(&sheet.qr_text()).unwrap(), expected);
+            let lowercase: Vec<Zeroizing<String>> = sheet
+                .lines()
+                .iter()
+                .map(|l| Zeroizing::new(l.to_ascii_lowercase()))
+                .collect();
+            assert_eq!(read_lines(&lowercase).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn detect_typos() {
+        let sheet = Sheet::new("alice", &totp(otp::HashType::Sha3_512)).unwrap();
+        let lines = sheet.lines();
+        assert!(lines.len() >= 3);
+        let mut reader = SheetReader::default();
+        assert!(reader.push_line(&lines[1]).is_err());
+        reader.push_line(&lines[0]).unwrap();
+        assert_eq!(reader.next_line(), 2);
+        assert_eq!(reader.total_lines(), Some(lines.len()));
+        let mut typo = lines[1].to_string();
+        let i = typo.find(|c: char| c.is_ascii_alphabetic()).unwrap();
+        let replaced = if &typo[i..i + 1] == "A" { "B" } else { "A" };
+        typo.replace_range(i..i + 1, replaced);
+        assert!(reader.push_line(&typo).is_err());
+        assert!(reader.push_line(&lines[1][..lines[1].len() - 9]).is_err());
+        assert!(reader.finish().is_err());
+        for line in &lines[1..] {
+            reader.push_line(line).unwrap();
+        }
+        assert!(reader.is_complete());
+        assert!(reader.push_line(&lines[0]).is_err());
+        assert!(reader.finish().is_ok());
+
+        let mut text = sheet.qr_text().to_string();
+        text.pop();
+        assert!(decode(&text).is_err());
+    }
+
+    #[test]
+    fn render() {
+        let sheets = vec![
+            Sheet::new("<alice>", &totp(otp::HashType::Sha1)).unwrap(),
+            Sheet::new("bob", &skey()).unwrap(),
+        ];
+        let html = render_html("Backup & restore", &sheets).unwrap();
+        assert!(html.contains("<h1>Backup &amp; restore</h1>"));
+        assert!(html.contains("<h2>&lt;alice&gt;</h2>"));
+        assert_eq!(html.matches("<svg").count(), 2);
+        assert!(html.contains(sheets[1].lines()[0].as_str()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.html");
+        save_html(&path, "Backup", &sheets).unwrap();
+        assert!(save_html(&path, "Backup", &sheets).is_err());
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .ends_with("</html>\n"));
+    }
+}
@@ -0,0 +1,200 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Render QR codes in the terminal, SVG and PNG.
+//!
+//! QR codes have the quiet zone of 4 modules around them, as scanners expect.
+//! In the terminal, each character shows two modules stacked vertically with half blocks.
+//!
+//! # Example
+//!
+//! ```
+//! # use yatotp::qr::QrImage;
+//! let qr = QrImage::new("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP").unwrap();
+//! let text = qr.to_terminal(false);
+//! assert_eq!(text.lines().count(), (qr.size() + 8).div_ceil(2));
+//! ```
+
+use anyhow::{bail, Result};
+use qrcodegen::{QrCode, QrCodeEcc};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::Path;
+
+/// Width of the quiet zone in modules.
+const BORDER: i32 = 4;
+/// Pixels on a side of each module in PNG.
+const PNG_SCALE: usize = 8;
+
+/// A QR code to be rendered.
+pub struct QrImage(QrCode);
+
+impl QrImage {
+    /// Encode the text with the medium error correction level.
+    pub fn new(text: &str) -> Result<QrImage> {
+        match QrCode::encode_text(text, QrCodeEcc::Medium) {
+            Ok(qr) => Ok(QrImage(qr)),
+            Err(_) => bail!("Text is too long to be encoded into a QR code."),
+        }
+    }
+
+    /// Return the number of modules on a side, without the quiet zone.
+    pub fn size(&self) -> usize {
+        self.0.size() as usize
+    }
+
+    /// Render with Unicode half blocks.
+    ///
+    /// Light modules are drawn with blocks for terminals of light text on dark background,
+    /// and dark modules are drawn instead if `invert` is true.
+    pub fn to_terminal(&self, invert: bool) -> String {
+        let drawn = |x: i32, y: i32| self.is_dark(x, y) == invert;
+        let mut text = String::new();
+        for y in (-BORDER..self.0.size() + BORDER).step_by(2) {
+            for x in -BORDER..self.0.size() + BORDER {
+                let bottom = y + 1 < self.0.size() + BORDER && drawn(x, y + 1);
+                text.push(match (drawn(x, y), bottom) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Render in SVG, which scales to the size of its container.
+    pub fn to_svg(&self) -> String {
+        let size = self.0.size() + BORDER * 2;
+        let mut path = String::new();
+        for y in 0..self.0.size() {
+            for x in 0..self.0.size() {
+                if self.is_dark(x, y) {
+                    write!(path, "M{},{}h1v1h-1z", x + BORDER, y + BORDER)
+                        .expect("Writing to String does not fail.");
+                }
+            }
+        }
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {size} {size}\" \
+             shape-rendering=\"crispEdges\">\
+             <rect width=\"{size}\" height=\"{size}\" fill=\"white\"/>\
+             <path d=\"{path}\" fill=\"black\"/></svg>",
+            size = size,
+            path = path
+        )
+    }
+
+    /// Render in grayscale PNG.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let side = (self.size() + BORDER as usize * 2) * PNG_SCALE;
+        let mut pixels = Vec::with_capacity(side * side);
+        for py in 0..side {
+            for px in 0..side {
+                let x = (px / PNG_SCALE) as i32 - BORDER;
+                let y = (py / PNG_SCALE) as i32 - BORDER;
+                pixels.push(if self.is_dark(x, y) { 0 } else { 255 });
+            }
+        }
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, side as u32, side as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+        writer.finish()?;
+        Ok(png)
+    }
+
+    /// Render into a new file, which only the owner can read.
+    ///
+    /// The format is PNG or SVG by the extension of the path.
+    pub fn save<P: AsRef<Path>>(&self, path: &P) -> Result<()> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let content = match extension.as_deref() {
+            Some("png") => self.to_png()?,
+            Some("svg") => self.to_svg().into_bytes(),
+            _ => bail!("QR code can be saved only as .png or .svg file."),
+        };
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut f = options.open(path)?;
+        f.write_all(&content)?;
+        Ok(())
+    }
+
+    /// Return whether the module is dark. Modules in the quiet zone are light.
+    fn is_dark(&self, x: i32, y: i32) -> bool {
+        self.0.get_module(x, y)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEXT: &str = "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP";
+
+    #[test]
+    fn terminal() {
+        let qr = QrImage::new(TEXT).unwrap();
+        let width = qr.size() + 8;
+        let text = qr.to_terminal(false);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), width.div_ceil(2));
+        assert!(lines.iter().all(|l| l.chars().count() == width));
+        // The quiet zone is drawn, and the finder pattern starts after it.
+        assert!(lines[0].chars().all(|c| c == '█'));
+        assert!(lines[2].starts_with("████ ▄▄▄▄▄ █"));
+        let inverted = qr.to_terminal(true);
+        assert!(inverted.lines().next().unwrap().chars().all(|c| c == ' '));
+        assert!(inverted.lines().nth(2).unwrap().starts_with("    █▀▀▀▀▀█ "));
+    }
+
+    #[test]
+    fn images() {
+        let qr = QrImage::new(TEXT).unwrap();
+        let svg = qr.to_svg();
+        let side = qr.size() + 8;
+        assert!(svg.contains(&format!("viewBox=\"0 0 {} {}\"", side, side)));
+
+        let png = qr.to_png().unwrap();
+        let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(info.width as usize, side * PNG_SCALE);
+        let pixel =
+            |x: usize, y: usize| pixels[y * PNG_SCALE * info.width as usize + x * PNG_SCALE];
+        assert_eq!(pixel(0, 0), 255);
+        assert_eq!(pixel(4, 4), 0);
+        assert_eq!(pixel(5, 5), 255);
+
+        let dir = tempfile::tempdir().unwrap();
+        qr.save(&dir.path().join("qr.png")).unwrap();
+        qr.save(&dir.path().join("qr.SVG")).unwrap();
+        assert!(qr.save(&dir.path().join("qr.png")).is_err());
+        assert!(qr.save(&dir.path().join("qr.jpg")).is_err());
+    }
+}
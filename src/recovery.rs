@@ -0,0 +1,317 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Recover access to the database with Shamir's secret sharing.
+//!
+//! A random recovery key unlocks the database through its own key slot
+//! (see [Keyring::add_recovery_key](crate::keyslot::Keyring::add_recovery_key)),
+//! and is split into shares over GF(2^8), any `threshold` of which reconstruct it.
+//! Fewer shares tell nothing about the key.
+//! Revoking the slot invalidates all the shares.
+//!
+//! # Example
+//!
+//! ```
+//! # use yatotp::recovery::*;
+//! let key = [7u8; RECOVERY_KEY_LEN];
+//! let shares = split(&key, 3, 5).unwrap();
+//! let printed: Vec<String> = shares.iter().map(|s| s.to_string()).collect();
+//! let read: Vec<Share> = printed[2..].iter().map(|s| s.parse().unwrap()).collect();
+//! assert_eq!(combine(&read).unwrap().expose(), &key);
+//! ```
+
+use crate::secret::Secret;
+use anyhow::{bail, ensure, Result};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use data_encoding::BASE32_NOPAD;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+/// Length of the recovery key in bytes.
+pub const RECOVERY_KEY_LEN: usize = 32;
+const SHARE_VERSION: u8 = 1;
+const SHARE_PREFIX: &str = "YATOTP";
+const SPLIT_ID_CONTEXT: &[u8] = b"yatotp recovery split\0";
+const SPLIT_ID_LEN: usize = 4;
+const CHECKSUM_LEN: usize = 4;
+const GROUP_LEN: usize = 5;
+
+/// A share of the recovery key.
+///
+/// It is printed as base32 in groups, such as `YATOTP-AEBQE-...`, with a checksum
+/// to detect typos, and the identifier of the split to detect shares of different splits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    threshold: u8,
+    index: u8,
+    split_id: [u8; SPLIT_ID_LEN],
+    value: Secret<[u8; RECOVERY_KEY_LEN]>,
+}
+
+impl Share {
+    /// Return the number of shares required to reconstruct the key.
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// Return the index of the share, from 1.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![SHARE_VERSION, self.threshold, self.index];
+        bytes.extend_from_slice(&self.split_id);
+        bytes.extend_from_slice(self.value.expose());
+        let checksum = Sha256::digest(&bytes);
+        bytes.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+        bytes
+    }
+}
+
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoded = BASE32_NOPAD.encode(&self.to_bytes());
+        write!(f, "{}", SHARE_PREFIX)?;
+        for group in encoded.as_bytes().chunks(GROUP_LEN) {
+            write!(f, "-{}", String::from_utf8_lossy(group))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Share {
+    type Err = anyhow::Error;
+
+    /// Parse the printed share, ignoring case, spaces and hyphens.
+    fn from_str(s: &str) -> Result<Share> {
+        let normalized: String = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .collect::<String>()
+            .to_ascii_uppercase();
+        let encoded = match normalized.strip_prefix(SHARE_PREFIX) {
+            Some(encoded) => encoded,
+            None => bail!("Share must begin with {}.", SHARE_PREFIX),
+        };
+        let bytes = Secret::new(match BASE32_NOPAD.decode(encoded.as_bytes()) {
+            Ok(b) => b,
+            Err(_) => bail!("Share is not correctly typed."),
+        });
+        let bytes = bytes.expose();
+        ensure!(
+            bytes.len() == 3 + SPLIT_ID_LEN + RECOVERY_KEY_LEN + CHECKSUM_LEN,
+            "Share is not correctly typed."
+        );
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        ensure!(
+            Sha256::digest(body)[..CHECKSUM_LEN] == *checksum,
+            "Share is not correctly typed."
+        );
+        ensure!(
+            body[0] == SHARE_VERSION,
+            "Share version {} is not supported.",
+            body[0]
+        );
+        let mut split_id = [0u8; SPLIT_ID_LEN];
+        split_id.copy_from_slice(&body[3..3 + SPLIT_ID_LEN]);
+        let mut value = Secret::new([0u8; RECOVERY_KEY_LEN]);
+        value
+            .expose_mut()
+            .copy_from_slice(&body[3 + SPLIT_ID_LEN..]);
+        ensure!(
+            body[1] >= 2 && body[2] >= 1,
+            "Share is not correctly typed."
+        );
+        Ok(Share {
+            threshold: body[1],
+            index: body[2],
+            split_id,
+            value,
+        })
+    }
+}
+
+/// Check that `count` shares can be split with the threshold.
+pub fn check_threshold(threshold: u8, count: u8) -> Result<()> {
+    ensure!(
+        threshold >= 2,
+        "Threshold must be 2 or more. Keep the key itself for threshold 1."
+    );
+    ensure!(
+        threshold <= count,
+        "Threshold must not be more than the number of shares."
+    );
+    Ok(())
+}
+
+/// Split the key into `count` shares, any `threshold` of which reconstruct it.
+///
+/// Each byte of the key is the constant term of a random polynomial of degree `threshold - 1`
+/// over GF(2^8), and share `i` holds the values of the polynomials at `i`.
+pub fn split(key: &[u8; RECOVERY_KEY_LEN], threshold: u8, count: u8) -> Result<Vec<Share>> {
+    check_threshold(threshold, count)?;
+    let split_id = split_id(key);
+    let mut shares: Vec<Share> = (1..=count)
+        .map(|index| Share {
+            threshold,
+            index,
+            split_id,
+            value: Secret::new([0u8; RECOVERY_KEY_LEN]),
+        })
+        .collect();
+    let mut coefficients = Secret::new(vec![0u8; threshold as usize]);
+    for (i, byte) in key.iter().enumerate() {
+        OsRng.fill_bytes(coefficients.expose_mut());
+        coefficients.expose_mut()[0] = *byte;
+        for share in shares.iter_mut() {
+            // Horner's method.
+            share.value.expose_mut()[i] = coefficients
+                .expose()
+                .iter()
+                .rev()
+                .fold(0, |acc, c| gf_mul(acc, share.index) ^ c);
+        }
+    }
+    Ok(shares)
+}
+
+/// Reconstruct the key from the shares by Lagrange interpolation at 0.
+pub fn combine(shares: &[Share]) -> Result<Secret<[u8; RECOVERY_KEY_LEN]>> {
+    let first = match shares.first() {
+        Some(s) => s,
+        None => bail!("No share is given."),
+    };
+    ensure!(
+        shares
+            .iter()
+            .all(|s| s.split_id == first.split_id && s.threshold == first.threshold),
+        "Shares are not of the same split."
+    );
+    let mut indices: Vec<u8> = shares.iter().map(|s| s.index).collect();
+    indices.sort_unstable();
+    indices.dedup();
+    ensure!(
+        indices.len() == shares.len(),
+        "The same share is given twice."
+    );
+    ensure!(
+        shares.len() >= first.threshold as usize,
+        "{} shares are required, but {} are given.",
+        first.threshold,
+        shares.len()
+    );
+    let shares = &shares[..first.threshold as usize];
+    let mut key = Secret::new([0u8; RECOVERY_KEY_LEN]);
+    for share in shares {
+        let mut basis = 1u8;
+        for other in shares.iter().filter(|o| o.index != share.index) {
+            basis = gf_mul(basis, gf_div(other.index, other.index ^ share.index));
+        }
+        for (k, v) in key.expose_mut().iter_mut().zip(share.value.expose()) {
+            *k ^= gf_mul(basis, *v);
+        }
+    }
+    ensure!(
+        split_id(key.expose()) == first.split_id,
+        "Failed to reconstruct the key. Some share may be wrong."
+    );
+    Ok(key)
+}
+
+fn split_id(key: &[u8; RECOVERY_KEY_LEN]) -> [u8; SPLIT_ID_LEN] {
+    let mut hasher = Sha256::new();
+    hasher.update(SPLIT_ID_CONTEXT);
+    hasher.update(key);
+    let mut id = [0u8; SPLIT_ID_LEN];
+    id.copy_from_slice(&hasher.finalize()[..SPLIT_ID_LEN]);
+    id
+}
+
+/// Multiply in GF(2^8) with the polynomial of AES, in constant time.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (carry & 0x1b);
+        b >>= 1;
+    }
+    product
+}
+
+/// Divide in GF(2^8), where `b` is not 0. The inverse is `b^254`.
+fn gf_div(a: u8, b: u8) -> u8 {
+    let mut inverse = 1u8;
+    for _ in 0..254 {
+        inverse = gf_mul(inverse, b);
+    }
+    gf_mul(a, inverse)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn galois_field() {
+        // Examples in FIPS 197.
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for b in 1..=255u8 {
+            assert_eq!(gf_mul(gf_div(1, b), b), 1);
+        }
+    }
+
+    #[test]
+    fn split_and_combine() {
+        let mut key = [0u8; RECOVERY_KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        let shares = split(&key, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert_eq!(shares[4].index(), 5);
+        for combination in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let chosen: Vec<Share> = combination.iter().map(|i| shares[*i].clone()).collect();
+            assert_eq!(combine(&chosen).unwrap().expose(), &key);
+        }
+        assert!(combine(&shares[..2]).is_err());
+        assert!(combine(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err());
+        let other = split(&[1u8; RECOVERY_KEY_LEN], 3, 5).unwrap();
+        assert!(combine(&[shares[0].clone(), shares[1].clone(), other[2].clone()]).is_err());
+        assert!(split(&key, 1, 5).is_err());
+        assert!(split(&key, 6, 5).is_err());
+    }
+
+    #[test]
+    fn print_and_parse() {
+        let shares = split(&[42u8; RECOVERY_KEY_LEN], 2, 3).unwrap();
+        let printed = shares[1].to_string();
+        assert!(printed.starts_with("YATOTP-"));
+        assert_eq!(printed.parse::<Share>().unwrap(), shares[1]);
+        let retyped = printed.to_lowercase().replace('-', " ");
+        assert_eq!(retyped.parse::<Share>().unwrap(), shares[1]);
+
+        // A typo is detected by the checksum.
+        let mut typo = printed.into_bytes();
+        let last = typo.len() - 3;
+        typo[last] = if typo[last] == b'A' { b'B' } else { b'A' };
+        assert!(String::from_utf8(typo).unwrap().parse::<Share>().is_err());
+        assert!("AEBQE".parse::<Share>().is_err());
+    }
+}
@@ -0,0 +1,170 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Detect rollback of database files.
+//!
+//! Database files are often synced through storage which is not trusted,
+//! so an older valid file could be substituted for the latest one, resurrecting removed entries.
+//! Every save increments the revision encrypted in the file
+//! (see [save_database_with_keyring](crate::database::save_database_with_keyring)),
+//! and [RevisionStore] remembers the latest revision of each database seen on this machine,
+//! so that loading an older revision is detected.
+
+use crate::keyslot::Keyring;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Result of comparing the revision of a loaded database with the one seen last time.
+#[derive(Debug, PartialEq)]
+pub enum RevisionCheck {
+    /// The database has never been seen with this store.
+    New,
+    /// The revision is the same as or newer than the one seen last time.
+    UpToDate,
+    /// The revision is older than the one seen last time.
+    Rollback {
+        /// The revision seen last time.
+        seen: u64,
+    },
+}
+
+/// Local record of the latest revision of each database, keyed by [Keyring::database_id].
+///
+/// # Example
+///
+/// ```no_run
+/// # use yatotp::database::*;
+/// # use yatotp::revision::*;
+/// let store = RevisionStore::new(&RevisionStore::default_path().unwrap());
+/// let (database, keyring) =
+///     open_database(&"database.yatotp", &CompositeKey::from_password("password")).unwrap();
+/// if let RevisionCheck::Rollback { seen } = store.check(&keyring).unwrap() {
+///     panic!("Revision {} is older than {}.", keyring.revision(), seen);
+/// }
+/// store.update(&keyring).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RevisionStore {
+    path: PathBuf,
+}
+
+impl RevisionStore {
+    /// Use the file as the store. It is created when a revision is recorded first.
+    pub fn new<P: AsRef<Path>>(path: &P) -> RevisionStore {
+        RevisionStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Return the default location of the store, `yatotp/revisions.json`
+    /// in the local state directory (`~/.local/state` on Linux) or the local data directory.
+    pub fn default_path() -> Result<PathBuf> {
+        let dir = dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .context("Failed to find the directory to store the revisions of databases.")?;
+        Ok(dir.join("yatotp").join("revisions.json"))
+    }
+
+    /// Compare the revision of the keyring with the one seen last time.
+    pub fn check(&self, keyring: &Keyring) -> Result<RevisionCheck> {
+        Ok(match self.read()?.get(&keyring.database_id()) {
+            None => RevisionCheck::New,
+            Some(&seen) if keyring.revision() < seen => RevisionCheck::Rollback { seen },
+            Some(_) => RevisionCheck::UpToDate,
+        })
+    }
+
+    /// Record the revision of the keyring, unless a newer one has been seen.
+    pub fn update(&self, keyring: &Keyring) -> Result<()> {
+        self.record(keyring, false)
+    }
+
+    /// Record the revision of the keyring even if it is older than the one seen last time,
+    /// which means the rollback is accepted.
+    pub fn accept_rollback(&self, keyring: &Keyring) -> Result<()> {
+        self.record(keyring, true)
+    }
+
+    fn record(&self, keyring: &Keyring, force: bool) -> Result<()> {
+        // Keyrings which have never been saved have nothing to protect.
+        if keyring.revision() == 0 {
+            return Ok(());
+        }
+        let mut revisions = self.read()?;
+        let seen = revisions.entry(keyring.database_id()).or_insert(0);
+        if !force && *seen >= keyring.revision() {
+            return Ok(());
+        }
+        *seen = keyring.revision();
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string(&revisions)?).context(format!(
+            "Failed to write revisions to {}.",
+            self.path.display()
+        ))
+    }
+
+    fn read(&self) -> Result<HashMap<String, u64>> {
+        if !self.path.is_file() {
+            return Ok(HashMap::new());
+        }
+        let content = std::fs::read_to_string(&self.path)?;
+        serde_json::from_str(&content).context(format!(
+            "Failed to parse revisions in {}.",
+            self.path.display()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::CompositeKey;
+    use tempfile::tempdir;
+
+    #[test]
+    fn detect_rollback() {
+        let dir = tempdir().unwrap();
+        let store = RevisionStore::new(&dir.path().join("state").join("revisions.json"));
+        let mut keyring = Keyring::new(&CompositeKey::from_password("alice")).unwrap();
+        assert_eq!(store.check(&keyring).unwrap(), RevisionCheck::New);
+        store.update(&keyring).unwrap();
+        assert_eq!(store.check(&keyring).unwrap(), RevisionCheck::New);
+
+        keyring.set_revision(3);
+        store.update(&keyring).unwrap();
+        assert_eq!(store.check(&keyring).unwrap(), RevisionCheck::UpToDate);
+        keyring.set_revision(2);
+        assert_eq!(
+            store.check(&keyring).unwrap(),
+            RevisionCheck::Rollback { seen: 3 }
+        );
+        store.update(&keyring).unwrap();
+        assert_eq!(
+            store.check(&keyring).unwrap(),
+            RevisionCheck::Rollback { seen: 3 }
+        );
+        store.accept_rollback(&keyring).unwrap();
+        assert_eq!(store.check(&keyring).unwrap(), RevisionCheck::UpToDate);
+
+        let other = Keyring::new(&CompositeKey::from_password("alice")).unwrap();
+        assert_eq!(store.check(&other).unwrap(), RevisionCheck::New);
+    }
+}
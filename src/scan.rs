@@ -0,0 +1,375 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Decode QR codes in images.
+//!
+//! PNG and JPEG images are supported, and all the QR codes in an image are decoded
+//! by [rqrr], so they may be scaled, rotated or inverted.
+//! Errors in codewords are corrected by Reed-Solomon codes.
+//!
+//! # Example
+//!
+//! ```
+//! # use yatotp::{qr::QrImage, scan};
+//! let png = QrImage::new("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP")
+//!     .unwrap()
+//!     .to_png()
+//!     .unwrap();
+//! let texts = scan::scan_image(&png).unwrap();
+//! assert_eq!(texts[0].as_str(), "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP");
+//! ```
+
+use crate::secret::Zeroizing;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_SIGNATURE: &[u8] = b"\xff\xd8";
+
+/// Decode all the QR codes in the PNG or JPEG file.
+pub fn scan_file<P: AsRef<Path>>(path: &P) -> Result<Vec<Zeroizing<String>>> {
+    scan_image(&Zeroizing::new(std::fs::read(path)?))
+}
+
+/// Decode all the QR codes in the PNG or JPEG image.
+///
+/// Returns empty if no QR code is found.
+pub fn scan_image(image: &[u8]) -> Result<Vec<Zeroizing<String>>> {
+    let gray = if image.starts_with(PNG_SIGNATURE) {
+        load_png(image)?
+    } else if image.starts_with(JPEG_SIGNATURE) {
+        load_jpeg(image)?
+    } else {
+        bail!("Image must be PNG or JPEG.")
+    };
+    Ok(scan_gray(&gray))
+}
+
+/// Grayscale image, 8 bits per pixel.
+struct Gray {
+    width: usize,
+    height: usize,
+    pixels: Zeroizing<Vec<u8>>,
+}
+
+fn luma(r: u8, g: u8, b: u8) -> u8 {
+    ((299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000) as u8
+}
+
+/// Blend the pixel over white background.
+fn over_white(value: u8, alpha: u8) -> u8 {
+    ((value as u32 * alpha as u32 + 255 * (255 - alpha as u32)) / 255) as u8
+}
+
+fn load_png(image: &[u8]) -> Result<Gray> {
+    let mut decoder = png::Decoder::new(image);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().context("Failed to decode PNG image.")?;
+    let mut buffer = Zeroizing::new(vec![0; reader.output_buffer_size()]);
+    let info = reader
+        .next_frame(&mut buffer)
+        .context("Failed to decode PNG image.")?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let mut pixels = Zeroizing::new(Vec::with_capacity(width * height));
+    for row in buffer.chunks(info.line_size).take(height) {
+        let row = &row[..width * info.color_type.samples()];
+        match info.color_type {
+            png::ColorType::Grayscale => pixels.extend_from_slice(row),
+            png::ColorType::GrayscaleAlpha => {
+                pixels.extend(row.chunks(2).map(|p| over_white(p[0], p[1])))
+            }
+            png::ColorType::Rgb => pixels.extend(row.chunks(3).map(|p| luma(p[0], p[1], p[2]))),
+            png::ColorType::Rgba => pixels.extend(
+                row.chunks(4)
+                    .map(|p| over_white(luma(p[0], p[1], p[2]), p[3])),
+            ),
+            png::ColorType::Indexed => bail!("Failed to expand PNG palette."),
+        }
+    }
+    Ok(Gray {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn load_jpeg(image: &[u8]) -> Result<Gray> {
+    let mut decoder = jpeg_decoder::Decoder::new(image);
+    let decoded = Zeroizing::new(decoder.decode().context("Failed to decode JPEG image.")?);
+    let info = decoder.info().context("Failed to decode JPEG image.")?;
+    let pixels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => Zeroizing::new(decoded.to_vec()),
+        jpeg_decoder::PixelFormat::RGB24 => {
+            Zeroizing::new(decoded.chunks(3).map(|p| luma(p[0], p[1], p[2])).collect())
+        }
+        format => bail!("{:?} JPEG image is not supported.", format),
+    };
+    Ok(Gray {
+        width: info.width as usize,
+        height: info.height as usize,
+        pixels,
+    })
+}
+
+/// Decode the codes located in the image, which are also looked for in the inverted image
+/// if none is found, as light codes on dark background.
+fn scan_gray(gray: &Gray) -> Vec<Zeroizing<String>> {
+    for inverted in [false, true] {
+        let mut prepared =
+            rqrr::PreparedImage::prepare_from_greyscale(gray.width, gray.height, |x, y| {
+                let pixel = gray.pixels[y * gray.width + x];
+                if inverted {
+                    255 - pixel
+                } else {
+                    pixel
+                }
+            });
+        let texts: Vec<Zeroizing<String>> = prepared
+            .detect_grids()
+            .iter()
+            .filter_map(|grid| {
+                let mut decoded = Zeroizing::new(Vec::new());
+                grid.decode_to(&mut *decoded).ok()?;
+                String::from_utf8(std::mem::take(&mut *decoded))
+                    .ok()
+                    .map(Zeroizing::new)
+            })
+            .collect();
+        if !texts.is_empty() {
+            return texts;
+        }
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use qrcodegen::{Mask, QrCode, QrCodeEcc, QrSegment, Version};
+
+    const URI: &str = "otpauth://totp/ACME%20Co:john.doe@email.com?\
+                       secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co";
+
+    fn encode(text: &str, version: u8, level: QrCodeEcc, mask: u8) -> QrCode {
+        let segments = QrSegment::make_segments(text);
+        QrCode::encode_segments_advanced(
+            &segments,
+            level,
+            Version::new(version),
+            Version::new(version),
+            Some(Mask::new(mask)),
+            false,
+        )
+        .unwrap()
+    }
+
+    /// Draw the modules at the position, `scale` pixels on a side of each module.
+    fn draw(gray: &mut Gray, modules: &[Vec<bool>], left: usize, top: usize, scale: usize) {
+        for (y, row) in modules.iter().enumerate() {
+            for (x, dark) in row.iter().enumerate() {
+                for py in 0..scale {
+                    for px in 0..scale {
+                        let i = (top + y * scale + py) * gray.width + left + x * scale + px;
+                        gray.pixels[i] = if *dark { 20 } else { 230 };
+                    }
+                }
+            }
+        }
+    }
+
+    /// Return the modules rotated by `rotation` right angles.
+    fn modules(qr: &QrCode, rotation: usize) -> Vec<Vec<bool>> {
+        let size = qr.size();
+        (0..size)
+            .map(|y| {
+                (0..size)
+                    .map(|x| match rotation % 4 {
+                        0 => qr.get_module(x, y),
+                        1 => qr.get_module(y, size - 1 - x),
+                        2 => qr.get_module(size - 1 - x, size - 1 - y),
+                        _ => qr.get_module(size - 1 - y, x),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn canvas(width: usize, height: usize) -> Gray {
+        Gray {
+            width,
+            height,
+            pixels: Zeroizing::new(vec![230; width * height]),
+        }
+    }
+
+    fn render(modules: &[Vec<bool>], scale: usize) -> Gray {
+        let side = (modules.len() + 8) * scale;
+        let mut gray = canvas(side, side);
+        draw(&mut gray, modules, 4 * scale, 4 * scale, scale);
+        gray
+    }
+
+    fn scan(gray: &Gray) -> Vec<String> {
+        scan_gray(gray).iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn decode_versions_and_levels() {
+        let texts = [
+            URI,
+            "YATOTP:AEAJCWZCME6GEPRCFR5SE2DPORYCEOT3EJVWK6JC",
+            "31415926535897932384626433832795028841971",
+            "秘密のキー",
+        ];
+        let levels = [
+            QrCodeEcc::Low,
+            QrCodeEcc::Medium,
+            QrCodeEcc::Quartile,
+            QrCodeEcc::High,
+        ];
+        for (i, version) in [7, 10, 27].iter().enumerate() {
+            for (j, level) in levels.iter().enumerate() {
+                let text = texts[(i + j) % texts.len()];
+                let qr = encode(text, *version, *level, ((i + j) % 8) as u8);
+                assert_eq!(scan(&render(&modules(&qr, 0), 2)), vec![text]);
+            }
+        }
+        for (mask, text) in texts.iter().enumerate() {
+            let qr = encode(
+                &text[..text.len().min(20)],
+                2,
+                QrCodeEcc::Medium,
+                mask as u8 + 4,
+            );
+            assert_eq!(
+                scan(&render(&modules(&qr, 0), 3)),
+                vec![&text[..text.len().min(20)]]
+            );
+        }
+    }
+
+    #[test]
+    fn rotated_and_inverted() {
+        let qr = encode(URI, 6, QrCodeEcc::Medium, 3);
+        for rotation in 0..4 {
+            assert_eq!(scan(&render(&modules(&qr, rotation), 3)), vec![URI]);
+        }
+        let mut gray = render(&modules(&qr, 1), 4);
+        gray.pixels.iter_mut().for_each(|p| *p = 255 - *p);
+        assert_eq!(scan(&gray), vec![URI]);
+    }
+
+    #[test]
+    fn correct_errors() {
+        let qr = encode(URI, 7, QrCodeEcc::Medium, 0);
+        let mut broken = modules(&qr, 0);
+        for row in broken.iter_mut().skip(15).take(3) {
+            for module in row.iter_mut().skip(15).take(4) {
+                *module = !*module;
+            }
+        }
+        assert_eq!(scan(&render(&broken, 3)), vec![URI]);
+        for row in broken.iter_mut().skip(10).take(17) {
+            for module in row.iter_mut().skip(10).take(17) {
+                *module = !*module;
+            }
+        }
+        assert!(scan(&render(&broken, 3)).is_empty());
+    }
+
+    #[test]
+    fn multiple_codes() {
+        let first = encode(URI, 7, QrCodeEcc::Medium, 2);
+        let second = encode(
+            "otpauth://totp/bob?secret=JBSWY3DPEHPK3PXP",
+            3,
+            QrCodeEcc::Low,
+            6,
+        );
+        let mut gray = canvas(400, 300);
+        draw(&mut gray, &modules(&first, 0), 10, 10, 5);
+        draw(&mut gray, &modules(&second, 3), 260, 100, 3);
+        let mut texts = scan(&gray);
+        texts.sort();
+        assert_eq!(
+            texts,
+            vec![URI, "otpauth://totp/bob?secret=JBSWY3DPEHPK3PXP"]
+        );
+        assert!(scan(&canvas(100, 100)).is_empty());
+    }
+
+    #[test]
+    fn many_false_finders() {
+        let qr = encode(URI, 6, QrCodeEcc::Medium, 5);
+        let finder: Vec<Vec<bool>> = (0..7usize)
+            .map(|y| {
+                (0..7usize)
+                    .map(|x| x.abs_diff(3).max(y.abs_diff(3)) != 2)
+                    .collect()
+            })
+            .collect();
+        let mut gray = canvas(900, 800);
+        draw(&mut gray, &modules(&qr, 0), 12, 12, 3);
+        for row in 0..20 {
+            for column in 0..30 {
+                draw(&mut gray, &finder, 6 + column * 30, 180 + row * 30, 3);
+            }
+        }
+        assert_eq!(scan(&gray), vec![URI]);
+    }
+
+    #[test]
+    fn image_files() {
+        let qr = encode(URI, 7, QrCodeEcc::Medium, 1);
+        let gray = render(&modules(&qr, 0), 4);
+        let rgb: Vec<u8> = gray
+            .pixels
+            .iter()
+            .flat_map(|p| [*p / 2, *p, p.saturating_add(20)])
+            .collect();
+
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, gray.width as u32, gray.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&rgb)
+            .unwrap();
+        let texts = scan_image(&png).unwrap();
+        assert_eq!(texts[0].as_str(), URI);
+
+        let mut jpeg = Vec::new();
+        jpeg_encoder::Encoder::new(&mut jpeg, 80)
+            .encode(
+                &rgb,
+                gray.width as u16,
+                gray.height as u16,
+                jpeg_encoder::ColorType::Rgb,
+            )
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("qr.jpg");
+        std::fs::write(&path, &jpeg).unwrap();
+        let texts = scan_file(&path).unwrap();
+        assert_eq!(texts[0].as_str(), URI);
+
+        assert!(scan_image(b"GIF89a").is_err());
+    }
+}
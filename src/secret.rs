@@ -0,0 +1,108 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Container of secret values.
+//!
+//! Secret keys, PINs and passwords are held in [Secret],
+//! which wipes its content from memory when dropped and never shows it in `Debug` output.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use zeroize::Zeroize;
+
+pub use zeroize::Zeroizing;
+
+/// A secret value which is zeroized on drop and redacted in `Debug` output.
+///
+/// It is serialized transparently as the inner value.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::secret::Secret;
+/// let key = Secret::new(b"12345678901234567890".to_vec());
+/// assert_eq!(key.expose(), b"12345678901234567890");
+/// assert_eq!(format!("{:?}", key), "[REDACTED]");
+/// ```
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[serde(transparent)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    /// Wrap a secret value.
+    pub fn new(value: T) -> Secret<T> {
+        Secret(value)
+    }
+
+    /// Borrow the secret value.
+    ///
+    /// Be careful not to copy it to somewhere not zeroized.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Mutably borrow the secret value.
+    pub fn expose_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Secret<T> {
+        Secret(value)
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redacted_and_transparent() {
+        let pin = Secret::new("1234".to_string());
+        assert_eq!(format!("{:?}", Some(&pin)), "Some([REDACTED])");
+        assert_eq!(serde_json::to_string(&pin).unwrap(), r#""1234""#);
+        let deserialized: Secret<String> = serde_json::from_str(r#""1234""#).unwrap();
+        assert_eq!(deserialized, pin);
+    }
+
+    #[test]
+    fn zeroize_inner() {
+        let mut secret = Secret::new(vec![1u8, 2, 3]);
+        secret.expose_mut().zeroize();
+        assert!(secret.expose().is_empty());
+
+        // The storage of ManuallyDrop is still readable after the secret is dropped in it.
+        let mut secret = std::mem::ManuallyDrop::new(Secret::new([1u8, 2, 3]));
+        let inner: *const [u8; 3] = secret.expose();
+        unsafe { std::mem::ManuallyDrop::drop(&mut secret) };
+        assert_eq!(unsafe { inner.read() }, [0u8; 3]);
+    }
+}
@@ -0,0 +1,621 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! S/KEY One-Time Password calculation.
+//!
+//! Provide hash chain based One-Time Password, which conforms to [RFC 2289].
+//! The server challenges with algorithm, sequence number and seed like `otp-md5 487 dog2`,
+//! and the response is encoded as six words of the standard dictionary.
+//!
+//! [RFC 2289]: https://datatracker.ietf.org/doc/html/rfc2289
+
+use crate::secret::{Secret, Zeroizing};
+use anyhow::{bail, ensure, Context, Result};
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::fmt;
+
+/// Hash function of the hash chain.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum SkeyAlgorithm {
+    /// Use MD5 (`otp-md5`).
+    Md5,
+    /// Use SHA-1 (`otp-sha1`).
+    Sha1,
+}
+
+impl SkeyAlgorithm {
+    fn hash(&self, data: &[u8]) -> [u8; 8] {
+        let mut folded = [0u8; 8];
+        match self {
+            SkeyAlgorithm::Md5 => fold(&mut folded, &Md5::digest(data)),
+            SkeyAlgorithm::Sha1 => {
+                fold(&mut folded, &Sha1::digest(data));
+                // RFC 2289 Appendix A folds SHA-1 digest as little-endian 32 bit words.
+                folded[..4].reverse();
+                folded[4..].reverse();
+            }
+        }
+        folded
+    }
+}
+
+impl fmt::Display for SkeyAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SkeyAlgorithm::Md5 => "otp-md5",
+            SkeyAlgorithm::Sha1 => "otp-sha1",
+        })
+    }
+}
+
+/// Challenge from the server, such as `otp-md5 487 dog2`.
+#[derive(Debug, PartialEq)]
+pub struct SkeyChallenge {
+    /// Hash function of the hash chain.
+    pub algorithm: SkeyAlgorithm,
+    /// Sequence number to respond.
+    pub sequence: u32,
+    /// Seed of the hash chain, in lowercase.
+    pub seed: String,
+}
+
+impl std::str::FromStr for SkeyChallenge {
+    type Err = anyhow::Error;
+
+    /// Parse the challenge. Trailing extensions such as `ext` are ignored.
+    fn from_str(challenge: &str) -> Result<SkeyChallenge> {
+        let mut parts = challenge.split_whitespace();
+        let algorithm = match parts.next() {
+            Some("otp-md5") => SkeyAlgorithm::Md5,
+            Some("otp-sha1") => SkeyAlgorithm::Sha1,
+            Some(a) => bail!("Unsupported S/KEY algorithm: {}", a),
+            None => bail!("S/KEY challenge is empty."),
+        };
+        let sequence = parts
+            .next()
+            .context("S/KEY challenge lacks sequence number.")?
+            .parse()
+            .context("Invalid sequence number.")?;
+        let seed = parts.next().context("S/KEY challenge lacks seed.")?;
+        Ok(SkeyChallenge {
+            algorithm,
+            sequence,
+            seed: normalize_seed(seed)?,
+        })
+    }
+}
+
+impl fmt::Display for SkeyChallenge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.algorithm, self.sequence, self.seed)
+    }
+}
+
+/// Calculate the 64 bit One-Time Password of given sequence number.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::skey::*;
+/// // A test vector in RFC 2289 Appendix C.
+/// let otp = otp(SkeyAlgorithm::Md5, "This is a test.", "TeSt", 0).unwrap();
+/// assert_eq!(otp, [0x9e, 0x87, 0x61, 0x34, 0xd9, 0x04, 0x99, 0xdd]);
+/// assert_eq!(to_words(&otp), "INCH SEA ANNE LONG AHEM TOUR");
+/// ```
+pub fn otp(
+    algorithm: SkeyAlgorithm,
+    passphrase: &str,
+    seed: &str,
+    sequence: u32,
+) -> Result<[u8; 8]> {
+    let message = Zeroizing::new(format!("{}{}", normalize_seed(seed)?, passphrase));
+    let mut otp = algorithm.hash(message.as_bytes());
+    for _ in 0..sequence {
+        otp = algorithm.hash(&otp);
+    }
+    Ok(otp)
+}
+
+/// Encode the One-Time Password into six words of the standard dictionary.
+pub fn to_words(otp: &[u8; 8]) -> String {
+    let bits = u64::from_be_bytes(*otp);
+    let checksum = (0..32).map(|i| (bits >> (i * 2)) & 0b11).sum::<u64>() & 0b11;
+    // 64 bits of the password followed by 2 bits of checksum.
+    let bits = ((bits as u128) << 2) | checksum as u128;
+    (0..6)
+        .map(|i| DICTIONARY[((bits >> (55 - i * 11)) & 0x7ff) as usize])
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn fold(folded: &mut [u8; 8], digest: &[u8]) {
+    for (i, b) in digest.iter().enumerate() {
+        folded[i % 8] ^= b;
+    }
+}
+
+fn normalize_seed(seed: &str) -> Result<String> {
+    ensure!(
+        (1..=16).contains(&seed.len()) && seed.chars().all(|c| c.is_ascii_alphanumeric()),
+        "S/KEY seed must be 1 to 16 alphanumeric characters."
+    );
+    Ok(seed.to_ascii_lowercase())
+}
+
+/// A S/KEY client for each account.
+///
+/// It holds the pass phrase, seed and the sequence number to respond next,
+/// which is decremented each time it responds, and is `None` after 0 is responded.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(try_from = "SkeyClientData")]
+pub struct SkeyClient {
+    algorithm: SkeyAlgorithm,
+    passphrase: Secret<String>,
+    seed: String,
+    sequence: Option<u32>,
+}
+
+/// Fields of [SkeyClient] to be checked when it is deserialized.
+#[derive(Deserialize)]
+struct SkeyClientData {
+    algorithm: SkeyAlgorithm,
+    passphrase: Secret<String>,
+    seed: String,
+    sequence: Option<u32>,
+}
+
+impl TryFrom<SkeyClientData> for SkeyClient {
+    type Error = anyhow::Error;
+
+    fn try_from(data: SkeyClientData) -> Result<SkeyClient> {
+        let mut client = SkeyClient::new(
+            data.algorithm,
+            data.passphrase.expose().clone(),
+            &data.seed,
+            0,
+        )?;
+        client.sequence = data.sequence;
+        Ok(client)
+    }
+}
+
+impl SkeyClient {
+    /// Create a new S/KEY client.
+    ///
+    /// The pass phrase must be at least 10 characters long.
+    pub fn new(
+        algorithm: SkeyAlgorithm,
+        passphrase: String,
+        seed: &str,
+        sequence: u32,
+    ) -> Result<SkeyClient> {
+        ensure!(
+            passphrase.chars().count() >= 10,
+            "S/KEY pass phrase must be at least 10 characters."
+        );
+        Ok(SkeyClient {
+            algorithm,
+            passphrase: Secret::new(passphrase),
+            seed: normalize_seed(seed)?,
+            sequence: Some(sequence),
+        })
+    }
+
+    /// Return the sequence number to respond next, or `None` if 0 is already responded.
+    pub fn sequence(&self) -> Option<&u32> {
+        self.sequence.as_ref()
+    }
+
+    /// Return the challenge which the client expects next, if any.
+    pub fn challenge(&self) -> Option<SkeyChallenge> {
+        Some(SkeyChallenge {
+            algorithm: self.algorithm,
+            sequence: self.sequence?,
+            seed: self.seed.clone(),
+        })
+    }
+
+    /// Return whether responding to the challenge skips the sequence numbers
+    /// between it and the one expected next, which can not be responded after it.
+    pub fn skips(&self, challenge: &SkeyChallenge) -> bool {
+        self.sequence.is_some_and(|s| challenge.sequence < s)
+    }
+
+    /// Respond to the challenge with six words,
+    /// and set the sequence number to the one next to the challenged one.
+    ///
+    /// If the challenge is not given, the client's sequence number is used.
+    /// The challenge above the client's sequence number is refused,
+    /// since its response may already have been given to a replayed challenge.
+    /// After sequence number 0 is responded, there is nothing to count down to,
+    /// so the server must be re-initialized with new seed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use yatotp::skey::*;
+    /// let mut skey =
+    ///     SkeyClient::new(SkeyAlgorithm::Md5, "This is a test.".to_string(), "TeSt", 1).unwrap();
+    /// assert_eq!(skey.respond(None).unwrap(), "EASE OIL FUM CURE AWRY AVIS");
+    /// assert_eq!(skey.sequence(), Some(&0));
+    /// assert_eq!(skey.respond(None).unwrap(), "INCH SEA ANNE LONG AHEM TOUR");
+    /// assert_eq!(skey.sequence(), None);
+    /// assert!(skey.respond(None).is_err());
+    /// ```
+    pub fn respond(&mut self, challenge: Option<&SkeyChallenge>) -> Result<String> {
+        let expected = self
+            .sequence
+            .context("Sequence number is exhausted. Please re-initialize S/KEY with new seed.")?;
+        let sequence = match challenge {
+            Some(challenge) => {
+                ensure!(
+                    challenge.algorithm == self.algorithm && challenge.seed == self.seed,
+                    "Challenge does not match the entry: expected {} with seed {}",
+                    self.algorithm,
+                    self.seed
+                );
+                ensure!(
+                    challenge.sequence <= expected,
+                    "Sequence number {} is above the next one {}, so the challenge may be replayed.",
+                    challenge.sequence,
+                    expected
+                );
+                challenge.sequence
+            }
+            None => expected,
+        };
+        let response = to_words(&otp(
+            self.algorithm,
+            self.passphrase.expose(),
+            &self.seed,
+            sequence,
+        )?);
+        self.sequence = sequence.checked_sub(1);
+        Ok(response)
+    }
+}
+
+/// The standard dictionary of RFC 2289 Appendix D.
+#[rustfmt::skip]
+static DICTIONARY: [&str; 2048] = [
+    "A", "ABE", "ACE", "ACT", "AD", "ADA", "ADD", "AGO", "AID", "AIM", "AIR", "ALL", "ALP", "AM",
+    "AMY", "AN", "ANA", "AND", "ANN", "ANT", "ANY", "APE", "APS", "APT", "ARC", "ARE", "ARK", "ARM",
+    "ART", "AS", "ASH", "ASK", "AT", "ATE", "AUG", "AUK", "AVE", "AWE", "AWK", "AWL", "AWN", "AX",
+    "AYE", "BAD", "BAG", "BAH", "BAM", "BAN", "BAR", "BAT", "BAY", "BE", "BED", "BEE", "BEG", "BEN",
+    "BET", "BEY", "BIB", "BID", "BIG", "BIN", "BIT", "BOB", "BOG", "BON", "BOO", "BOP", "BOW",
+    "BOY", "BUB", "BUD", "BUG", "BUM", "BUN", "BUS", "BUT", "BUY", "BY", "BYE", "CAB", "CAL", "CAM",
+    "CAN", "CAP", "CAR", "CAT", "CAW", "COD", "COG", "COL", "CON", "COO", "COP", "COT", "COW",
+    "COY", "CRY", "CUB", "CUE", "CUP", "CUR", "CUT", "DAB", "DAD", "DAM", "DAN", "DAR", "DAY",
+    "DEE", "DEL", "DEN", "DES", "DEW", "DID", "DIE", "DIG", "DIN", "DIP", "DO", "DOE", "DOG", "DON",
+    "DOT", "DOW", "DRY", "DUB", "DUD", "DUE", "DUG", "DUN", "EAR", "EAT", "ED", "EEL", "EGG", "EGO",
+    "ELI", "ELK", "ELM", "ELY", "EM", "END", "EST", "ETC", "EVA", "EVE", "EWE", "EYE", "FAD", "FAN",
+    "FAR", "FAT", "FAY", "FED", "FEE", "FEW", "FIB", "FIG", "FIN", "FIR", "FIT", "FLO", "FLY",
+    "FOE", "FOG", "FOR", "FRY", "FUM", "FUN", "FUR", "GAB", "GAD", "GAG", "GAL", "GAM", "GAP",
+    "GAS", "GAY", "GEE", "GEL", "GEM", "GET", "GIG", "GIL", "GIN", "GO", "GOT", "GUM", "GUN", "GUS",
+    "GUT", "GUY", "GYM", "GYP", "HA", "HAD", "HAL", "HAM", "HAN", "HAP", "HAS", "HAT", "HAW", "HAY",
+    "HE", "HEM", "HEN", "HER", "HEW", "HEY", "HI", "HID", "HIM", "HIP", "HIS", "HIT", "HO", "HOB",
+    "HOC", "HOE", "HOG", "HOP", "HOT", "HOW", "HUB", "HUE", "HUG", "HUH", "HUM", "HUT", "I", "ICY",
+    "IDA", "IF", "IKE", "ILL", "INK", "INN", "IO", "ION", "IQ", "IRA", "IRE", "IRK", "IS", "IT",
+    "ITS", "IVY", "JAB", "JAG", "JAM", "JAN", "JAR", "JAW", "JAY", "JET", "JIG", "JIM", "JO", "JOB",
+    "JOE", "JOG", "JOT", "JOY", "JUG", "JUT", "KAY", "KEG", "KEN", "KEY", "KID", "KIM", "KIN",
+    "KIT", "LA", "LAB", "LAC", "LAD", "LAG", "LAM", "LAP", "LAW", "LAY", "LEA", "LED", "LEE", "LEG",
+    "LEN", "LEO", "LET", "LEW", "LID", "LIE", "LIN", "LIP", "LIT", "LO", "LOB", "LOG", "LOP", "LOS",
+    "LOT", "LOU", "LOW", "LOY", "LUG", "LYE", "MA", "MAC", "MAD", "MAE", "MAN", "MAO", "MAP", "MAT",
+    "MAW", "MAY", "ME", "MEG", "MEL", "MEN", "MET", "MEW", "MID", "MIN", "MIT", "MOB", "MOD", "MOE",
+    "MOO", "MOP", "MOS", "MOT", "MOW", "MUD", "MUG", "MUM", "MY", "NAB", "NAG", "NAN", "NAP", "NAT",
+    "NAY", "NE", "NED", "NEE", "NET", "NEW", "NIB", "NIL", "NIP", "NIT", "NO", "NOB", "NOD", "NON",
+    "NOR", "NOT", "NOV", "NOW", "NU", "NUN", "NUT", "O", "OAF", "OAK", "OAR", "OAT", "ODD", "ODE",
+    "OF", "OFF", "OFT", "OH", "OIL", "OK", "OLD", "ON", "ONE", "OR", "ORB", "ORE", "ORR", "OS",
+    "OTT", "OUR", "OUT", "OVA", "OW", "OWE", "OWL", "OWN", "OX", "PA", "PAD", "PAL", "PAM", "PAN",
+    "PAP", "PAR", "PAT", "PAW", "PAY", "PEA", "PEG", "PEN", "PEP", "PER", "PET", "PEW", "PHI", "PI",
+    "PIE", "PIN", "PIT", "PLY", "PO", "POD", "POE", "POP", "POT", "POW", "PRO", "PRY", "PUB", "PUG",
+    "PUN", "PUP", "PUT", "QUO", "RAG", "RAM", "RAN", "RAP", "RAT", "RAW", "RAY", "REB", "RED",
+    "REP", "RET", "RIB", "RID", "RIG", "RIM", "RIO", "RIP", "ROB", "ROD", "ROE", "RON", "ROT",
+    "ROW", "ROY", "RUB", "RUE", "RUG", "RUM", "RUN", "RYE", "SAC", "SAD", "SAG", "SAL", "SAM",
+    "SAN", "SAP", "SAT", "SAW", "SAY", "SEA", "SEC", "SEE", "SEN", "SET", "SEW", "SHE", "SHY",
+    "SIN", "SIP", "SIR", "SIS", "SIT", "SKI", "SKY", "SLY", "SO", "SOB", "SOD", "SON", "SOP", "SOW",
+    "SOY", "SPA", "SPY", "SUB", "SUD", "SUE", "SUM", "SUN", "SUP", "TAB", "TAD", "TAG", "TAN",
+    "TAP", "TAR", "TEA", "TED", "TEE", "TEN", "THE", "THY", "TIC", "TIE", "TIM", "TIN", "TIP", "TO",
+    "TOE", "TOG", "TOM", "TON", "TOO", "TOP", "TOW", "TOY", "TRY", "TUB", "TUG", "TUM", "TUN",
+    "TWO", "UN", "UP", "US", "USE", "VAN", "VAT", "VET", "VIE", "WAD", "WAG", "WAR", "WAS", "WAY",
+    "WE", "WEB", "WED", "WEE", "WET", "WHO", "WHY", "WIN", "WIT", "WOK", "WON", "WOO", "WOW", "WRY",
+    "WU", "YAM", "YAP", "YAW", "YE", "YEA", "YES", "YET", "YOU", "ABED", "ABEL", "ABET", "ABLE",
+    "ABUT", "ACHE", "ACID", "ACME", "ACRE", "ACTA", "ACTS", "ADAM", "ADDS", "ADEN", "AFAR", "AFRO",
+    "AGEE", "AHEM", "AHOY", "AIDA", "AIDE", "AIDS", "AIRY", "AJAR", "AKIN", "ALAN", "ALEC", "ALGA",
+    "ALIA", "ALLY", "ALMA", "ALOE", "ALSO", "ALTO", "ALUM", "ALVA", "AMEN", "AMES", "AMID", "AMMO",
+    "AMOK", "AMOS", "AMRA", "ANDY", "ANEW", "ANNA", "ANNE", "ANTE", "ANTI", "AQUA", "ARAB", "ARCH",
+    "AREA", "ARGO", "ARID", "ARMY", "ARTS", "ARTY", "ASIA", "ASKS", "ATOM", "AUNT", "AURA", "AUTO",
+    "AVER", "AVID", "AVIS", "AVON", "AVOW", "AWAY", "AWRY", "BABE", "BABY", "BACH", "BACK", "BADE",
+    "BAIL", "BAIT", "BAKE", "BALD", "BALE", "BALI", "BALK", "BALL", "BALM", "BAND", "BANE", "BANG",
+    "BANK", "BARB", "BARD", "BARE", "BARK", "BARN", "BARR", "BASE", "BASH", "BASK", "BASS", "BATE",
+    "BATH", "BAWD", "BAWL", "BEAD", "BEAK", "BEAM", "BEAN", "BEAR", "BEAT", "BEAU", "BECK", "BEEF",
+    "BEEN", "BEER", "BEET", "BELA", "BELL", "BELT", "BEND", "BENT", "BERG", "BERN", "BERT", "BESS",
+    "BEST", "BETA", "BETH", "BHOY", "BIAS", "BIDE", "BIEN", "BILE", "BILK", "BILL", "BIND", "BING",
+    "BIRD", "BITE", "BITS", "BLAB", "BLAT", "BLED", "BLEW", "BLOB", "BLOC", "BLOT", "BLOW", "BLUE",
+    "BLUM", "BLUR", "BOAR", "BOAT", "BOCA", "BOCK", "BODE", "BODY", "BOGY", "BOHR", "BOIL", "BOLD",
+    "BOLO", "BOLT", "BOMB", "BONA", "BOND", "BONE", "BONG", "BONN", "BONY", "BOOK", "BOOM", "BOON",
+    "BOOT", "BORE", "BORG", "BORN", "BOSE", "BOSS", "BOTH", "BOUT", "BOWL", "BOYD", "BRAD", "BRAE",
+    "BRAG", "BRAN", "BRAY", "BRED", "BREW", "BRIG", "BRIM", "BROW", "BUCK", "BUDD", "BUFF", "BULB",
+    "BULK", "BULL", "BUNK", "BUNT", "BUOY", "BURG", "BURL", "BURN", "BURR", "BURT", "BURY", "BUSH",
+    "BUSS", "BUST", "BUSY", "BYTE", "CADY", "CAFE", "CAGE", "CAIN", "CAKE", "CALF", "CALL", "CALM",
+    "CAME", "CANE", "CANT", "CARD", "CARE", "CARL", "CARR", "CART", "CASE", "CASH", "CASK", "CAST",
+    "CAVE", "CEIL", "CELL", "CENT", "CERN", "CHAD", "CHAR", "CHAT", "CHAW", "CHEF", "CHEN", "CHEW",
+    "CHIC", "CHIN", "CHOU", "CHOW", "CHUB", "CHUG", "CHUM", "CITE", "CITY", "CLAD", "CLAM", "CLAN",
+    "CLAW", "CLAY", "CLOD", "CLOG", "CLOT", "CLUB", "CLUE", "COAL", "COAT", "COCA", "COCK", "COCO",
+    "CODA", "CODE", "CODY", "COED", "COIL", "COIN", "COKE", "COLA", "COLD", "COLT", "COMA", "COMB",
+    "COME", "COOK", "COOL", "COON", "COOT", "CORD", "CORE", "CORK", "CORN", "COST", "COVE", "COWL",
+    "CRAB", "CRAG", "CRAM", "CRAY", "CREW", "CRIB", "CROW", "CRUD", "CUBA", "CUBE", "CUFF", "CULL",
+    "CULT", "CUNY", "CURB", "CURD", "CURE", "CURL", "CURT", "CUTS", "DADE", "DALE", "DAME", "DANA",
+    "DANE", "DANG", "DANK", "DARE", "DARK", "DARN", "DART", "DASH", "DATA", "DATE", "DAVE", "DAVY",
+    "DAWN", "DAYS", "DEAD", "DEAF", "DEAL", "DEAN", "DEAR", "DEBT", "DECK", "DEED", "DEEM", "DEER",
+    "DEFT", "DEFY", "DELL", "DENT", "DENY", "DESK", "DIAL", "DICE", "DIED", "DIET", "DIME", "DINE",
+    "DING", "DINT", "DIRE", "DIRT", "DISC", "DISH", "DISK", "DIVE", "DOCK", "DOES", "DOLE", "DOLL",
+    "DOLT", "DOME", "DONE", "DOOM", "DOOR", "DORA", "DOSE", "DOTE", "DOUG", "DOUR", "DOVE", "DOWN",
+    "DRAB", "DRAG", "DRAM", "DRAW", "DREW", "DRUB", "DRUG", "DRUM", "DUAL", "DUCK", "DUCT", "DUEL",
+    "DUET", "DUKE", "DULL", "DUMB", "DUNE", "DUNK", "DUSK", "DUST", "DUTY", "EACH", "EARL", "EARN",
+    "EASE", "EAST", "EASY", "EBEN", "ECHO", "EDDY", "EDEN", "EDGE", "EDGY", "EDIT", "EDNA", "EGAN",
+    "ELAN", "ELBA", "ELLA", "ELSE", "EMIL", "EMIT", "EMMA", "ENDS", "ERIC", "EROS", "EVEN", "EVER",
+    "EVIL", "EYED", "FACE", "FACT", "FADE", "FAIL", "FAIN", "FAIR", "FAKE", "FALL", "FAME", "FANG",
+    "FARM", "FAST", "FATE", "FAWN", "FEAR", "FEAT", "FEED", "FEEL", "FEET", "FELL", "FELT", "FEND",
+    "FERN", "FEST", "FEUD", "FIEF", "FIGS", "FILE", "FILL", "FILM", "FIND", "FINE", "FINK", "FIRE",
+    "FIRM", "FISH", "FISK", "FIST", "FITS", "FIVE", "FLAG", "FLAK", "FLAM", "FLAT", "FLAW", "FLEA",
+    "FLED", "FLEW", "FLIT", "FLOC", "FLOG", "FLOW", "FLUB", "FLUE", "FOAL", "FOAM", "FOGY", "FOIL",
+    "FOLD", "FOLK", "FOND", "FONT", "FOOD", "FOOL", "FOOT", "FORD", "FORE", "FORK", "FORM", "FORT",
+    "FOSS", "FOUL", "FOUR", "FOWL", "FRAU", "FRAY", "FRED", "FREE", "FRET", "FREY", "FROG", "FROM",
+    "FUEL", "FULL", "FUME", "FUND", "FUNK", "FURY", "FUSE", "FUSS", "GAFF", "GAGE", "GAIL", "GAIN",
+    "GAIT", "GALA", "GALE", "GALL", "GALT", "GAME", "GANG", "GARB", "GARY", "GASH", "GATE", "GAUL",
+    "GAUR", "GAVE", "GAWK", "GEAR", "GELD", "GENE", "GENT", "GERM", "GETS", "GIBE", "GIFT", "GILD",
+    "GILL", "GILT", "GINA", "GIRD", "GIRL", "GIST", "GIVE", "GLAD", "GLEE", "GLEN", "GLIB", "GLOB",
+    "GLOM", "GLOW", "GLUE", "GLUM", "GLUT", "GOAD", "GOAL", "GOAT", "GOER", "GOES", "GOLD", "GOLF",
+    "GONE", "GONG", "GOOD", "GOOF", "GORE", "GORY", "GOSH", "GOUT", "GOWN", "GRAB", "GRAD", "GRAY",
+    "GREG", "GREW", "GREY", "GRID", "GRIM", "GRIN", "GRIT", "GROW", "GRUB", "GULF", "GULL", "GUNK",
+    "GURU", "GUSH", "GUST", "GWEN", "GWYN", "HAAG", "HAAS", "HACK", "HAIL", "HAIR", "HALE", "HALF",
+    "HALL", "HALO", "HALT", "HAND", "HANG", "HANK", "HANS", "HARD", "HARK", "HARM", "HART", "HASH",
+    "HAST", "HATE", "HATH", "HAUL", "HAVE", "HAWK", "HAYS", "HEAD", "HEAL", "HEAR", "HEAT", "HEBE",
+    "HECK", "HEED", "HEEL", "HEFT", "HELD", "HELL", "HELM", "HERB", "HERD", "HERE", "HERO", "HERS",
+    "HESS", "HEWN", "HICK", "HIDE", "HIGH", "HIKE", "HILL", "HILT", "HIND", "HINT", "HIRE", "HISS",
+    "HIVE", "HOBO", "HOCK", "HOFF", "HOLD", "HOLE", "HOLM", "HOLT", "HOME", "HONE", "HONK", "HOOD",
+    "HOOF", "HOOK", "HOOT", "HORN", "HOSE", "HOST", "HOUR", "HOVE", "HOWE", "HOWL", "HOYT", "HUCK",
+    "HUED", "HUFF", "HUGE", "HUGH", "HUGO", "HULK", "HULL", "HUNK", "HUNT", "HURD", "HURL", "HURT",
+    "HUSH", "HYDE", "HYMN", "IBIS", "ICON", "IDEA", "IDLE", "IFFY", "INCA", "INCH", "INTO", "IONS",
+    "IOTA", "IOWA", "IRIS", "IRMA", "IRON", "ISLE", "ITCH", "ITEM", "IVAN", "JACK", "JADE", "JAIL",
+    "JAKE", "JANE", "JAVA", "JEAN", "JEFF", "JERK", "JESS", "JEST", "JIBE", "JILL", "JILT", "JIVE",
+    "JOAN", "JOBS", "JOCK", "JOEL", "JOEY", "JOHN", "JOIN", "JOKE", "JOLT", "JOVE", "JUDD", "JUDE",
+    "JUDO", "JUDY", "JUJU", "JUKE", "JULY", "JUNE", "JUNK", "JUNO", "JURY", "JUST", "JUTE", "KAHN",
+    "KALE", "KANE", "KANT", "KARL", "KATE", "KEEL", "KEEN", "KENO", "KENT", "KERN", "KERR", "KEYS",
+    "KICK", "KILL", "KIND", "KING", "KIRK", "KISS", "KITE", "KLAN", "KNEE", "KNEW", "KNIT", "KNOB",
+    "KNOT", "KNOW", "KOCH", "KONG", "KUDO", "KURD", "KURT", "KYLE", "LACE", "LACK", "LACY", "LADY",
+    "LAID", "LAIN", "LAIR", "LAKE", "LAMB", "LAME", "LAND", "LANE", "LANG", "LARD", "LARK", "LASS",
+    "LAST", "LATE", "LAUD", "LAVA", "LAWN", "LAWS", "LAYS", "LEAD", "LEAF", "LEAK", "LEAN", "LEAR",
+    "LEEK", "LEER", "LEFT", "LEND", "LENS", "LENT", "LEON", "LESK", "LESS", "LEST", "LETS", "LIAR",
+    "LICE", "LICK", "LIED", "LIEN", "LIES", "LIEU", "LIFE", "LIFT", "LIKE", "LILA", "LILT", "LILY",
+    "LIMA", "LIMB", "LIME", "LIND", "LINE", "LINK", "LINT", "LION", "LISA", "LIST", "LIVE", "LOAD",
+    "LOAF", "LOAM", "LOAN", "LOCK", "LOFT", "LOGE", "LOIS", "LOLA", "LONE", "LONG", "LOOK", "LOON",
+    "LOOT", "LORD", "LORE", "LOSE", "LOSS", "LOST", "LOUD", "LOVE", "LOWE", "LUCK", "LUCY", "LUGE",
+    "LUKE", "LULU", "LUND", "LUNG", "LURA", "LURE", "LURK", "LUSH", "LUST", "LYLE", "LYNN", "LYON",
+    "LYRA", "MACE", "MADE", "MAGI", "MAID", "MAIL", "MAIN", "MAKE", "MALE", "MALI", "MALL", "MALT",
+    "MANA", "MANN", "MANY", "MARC", "MARE", "MARK", "MARS", "MART", "MARY", "MASH", "MASK", "MASS",
+    "MAST", "MATE", "MATH", "MAUL", "MAYO", "MEAD", "MEAL", "MEAN", "MEAT", "MEEK", "MEET", "MELD",
+    "MELT", "MEMO", "MEND", "MENU", "MERT", "MESH", "MESS", "MICE", "MIKE", "MILD", "MILE", "MILK",
+    "MILL", "MILT", "MIMI", "MIND", "MINE", "MINI", "MINK", "MINT", "MIRE", "MISS", "MIST", "MITE",
+    "MITT", "MOAN", "MOAT", "MOCK", "MODE", "MOLD", "MOLE", "MOLL", "MOLT", "MONA", "MONK", "MONT",
+    "MOOD", "MOON", "MOOR", "MOOT", "MORE", "MORN", "MORT", "MOSS", "MOST", "MOTH", "MOVE", "MUCH",
+    "MUCK", "MUDD", "MUFF", "MULE", "MULL", "MURK", "MUSH", "MUST", "MUTE", "MUTT", "MYRA", "MYTH",
+    "NAGY", "NAIL", "NAIR", "NAME", "NARY", "NASH", "NAVE", "NAVY", "NEAL", "NEAR", "NEAT", "NECK",
+    "NEED", "NEIL", "NELL", "NEON", "NERO", "NESS", "NEST", "NEWS", "NEWT", "NIBS", "NICE", "NICK",
+    "NILE", "NINA", "NINE", "NOAH", "NODE", "NOEL", "NOLL", "NONE", "NOOK", "NOON", "NORM", "NOSE",
+    "NOTE", "NOUN", "NOVA", "NUDE", "NULL", "NUMB", "OATH", "OBEY", "OBOE", "ODIN", "OHIO", "OILY",
+    "OINT", "OKAY", "OLAF", "OLDY", "OLGA", "OLIN", "OMAN", "OMEN", "OMIT", "ONCE", "ONES", "ONLY",
+    "ONTO", "ONUS", "ORAL", "ORGY", "OSLO", "OTIS", "OTTO", "OUCH", "OUST", "OUTS", "OVAL", "OVEN",
+    "OVER", "OWLY", "OWNS", "QUAD", "QUIT", "QUOD", "RACE", "RACK", "RACY", "RAFT", "RAGE", "RAID",
+    "RAIL", "RAIN", "RAKE", "RANK", "RANT", "RARE", "RASH", "RATE", "RAVE", "RAYS", "READ", "REAL",
+    "REAM", "REAR", "RECK", "REED", "REEF", "REEK", "REEL", "REID", "REIN", "RENA", "REND", "RENT",
+    "REST", "RICE", "RICH", "RICK", "RIDE", "RIFT", "RILL", "RIME", "RING", "RINK", "RISE", "RISK",
+    "RITE", "ROAD", "ROAM", "ROAR", "ROBE", "ROCK", "RODE", "ROIL", "ROLL", "ROME", "ROOD", "ROOF",
+    "ROOK", "ROOM", "ROOT", "ROSA", "ROSE", "ROSS", "ROSY", "ROTH", "ROUT", "ROVE", "ROWE", "ROWS",
+    "RUBE", "RUBY", "RUDE", "RUDY", "RUIN", "RULE", "RUNG", "RUNS", "RUNT", "RUSE", "RUSH", "RUSK",
+    "RUSS", "RUST", "RUTH", "SACK", "SAFE", "SAGE", "SAID", "SAIL", "SALE", "SALK", "SALT", "SAME",
+    "SAND", "SANE", "SANG", "SANK", "SARA", "SAUL", "SAVE", "SAYS", "SCAN", "SCAR", "SCAT", "SCOT",
+    "SEAL", "SEAM", "SEAR", "SEAT", "SEED", "SEEK", "SEEM", "SEEN", "SEES", "SELF", "SELL", "SEND",
+    "SENT", "SETS", "SEWN", "SHAG", "SHAM", "SHAW", "SHAY", "SHED", "SHIM", "SHIN", "SHOD", "SHOE",
+    "SHOT", "SHOW", "SHUN", "SHUT", "SICK", "SIDE", "SIFT", "SIGH", "SIGN", "SILK", "SILL", "SILO",
+    "SILT", "SINE", "SING", "SINK", "SIRE", "SITE", "SITS", "SITU", "SKAT", "SKEW", "SKID", "SKIM",
+    "SKIN", "SKIT", "SLAB", "SLAM", "SLAT", "SLAY", "SLED", "SLEW", "SLID", "SLIM", "SLIT", "SLOB",
+    "SLOG", "SLOT", "SLOW", "SLUG", "SLUM", "SLUR", "SMOG", "SMUG", "SNAG", "SNOB", "SNOW", "SNUB",
+    "SNUG", "SOAK", "SOAR", "SOCK", "SODA", "SOFA", "SOFT", "SOIL", "SOLD", "SOME", "SONG", "SOON",
+    "SOOT", "SORE", "SORT", "SOUL", "SOUR", "SOWN", "STAB", "STAG", "STAN", "STAR", "STAY", "STEM",
+    "STEW", "STIR", "STOW", "STUB", "STUN", "SUCH", "SUDS", "SUIT", "SULK", "SUMS", "SUNG", "SUNK",
+    "SURE", "SURF", "SWAB", "SWAG", "SWAM", "SWAN", "SWAT", "SWAY", "SWIM", "SWUM", "TACK", "TACT",
+    "TAIL", "TAKE", "TALE", "TALK", "TALL", "TANK", "TASK", "TATE", "TAUT", "TEAL", "TEAM", "TEAR",
+    "TECH", "TEEM", "TEEN", "TEET", "TELL", "TEND", "TENT", "TERM", "TERN", "TESS", "TEST", "THAN",
+    "THAT", "THEE", "THEM", "THEN", "THEY", "THIN", "THIS", "THUD", "THUG", "TICK", "TIDE", "TIDY",
+    "TIED", "TIER", "TILE", "TILL", "TILT", "TIME", "TINA", "TINE", "TINT", "TINY", "TIRE", "TOAD",
+    "TOGO", "TOIL", "TOLD", "TOLL", "TONE", "TONG", "TONY", "TOOK", "TOOL", "TOOT", "TORE", "TORN",
+    "TOTE", "TOUR", "TOUT", "TOWN", "TRAG", "TRAM", "TRAY", "TREE", "TREK", "TRIG", "TRIM", "TRIO",
+    "TROD", "TROT", "TROY", "TRUE", "TUBA", "TUBE", "TUCK", "TUFT", "TUNA", "TUNE", "TUNG", "TURF",
+    "TURN", "TUSK", "TWIG", "TWIN", "TWIT", "ULAN", "UNIT", "URGE", "USED", "USER", "USES", "UTAH",
+    "VAIL", "VAIN", "VALE", "VARY", "VASE", "VAST", "VEAL", "VEDA", "VEIL", "VEIN", "VEND", "VENT",
+    "VERB", "VERY", "VETO", "VICE", "VIEW", "VINE", "VISE", "VOID", "VOLT", "VOTE", "WACK", "WADE",
+    "WAGE", "WAIL", "WAIT", "WAKE", "WALE", "WALK", "WALL", "WALT", "WAND", "WANE", "WANG", "WANT",
+    "WARD", "WARM", "WARN", "WART", "WASH", "WAST", "WATS", "WATT", "WAVE", "WAVY", "WAYS", "WEAK",
+    "WEAL", "WEAN", "WEAR", "WEED", "WEEK", "WEIR", "WELD", "WELL", "WELT", "WENT", "WERE", "WERT",
+    "WEST", "WHAM", "WHAT", "WHEE", "WHEN", "WHET", "WHOA", "WHOM", "WICK", "WIFE", "WILD", "WILL",
+    "WIND", "WINE", "WING", "WINK", "WINO", "WIRE", "WISE", "WISH", "WITH", "WOLF", "WONT", "WOOD",
+    "WOOL", "WORD", "WORE", "WORK", "WORM", "WORN", "WOVE", "WRIT", "WYNN", "YALE", "YANG", "YANK",
+    "YARD", "YARN", "YAWL", "YAWN", "YEAH", "YEAR", "YELL", "YOGA", "YOKE",
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check(algorithm: SkeyAlgorithm, cases: &[(&str, &str, u32, u64, &str)]) {
+        for (passphrase, seed, sequence, hex, words) in cases.iter() {
+            let otp = otp(algorithm, passphrase, seed, *sequence).unwrap();
+            assert_eq!(u64::from_be_bytes(otp), *hex);
+            assert_eq!(to_words(&otp), *words);
+        }
+    }
+
+    #[test]
+    fn rfc2289_md5() {
+        check(
+            SkeyAlgorithm::Md5,
+            &[
+                (
+                    "This is a test.",
+                    "TeSt",
+                    0,
+                    0x9E876134D90499DD,
+                    "INCH SEA ANNE LONG AHEM TOUR",
+                ),
+                (
+                    "This is a test.",
+                    "TeSt",
+                    1,
+                    0x7965E05436F5029F,
+                    "EASE OIL FUM CURE AWRY AVIS",
+                ),
+                (
+                    "This is a test.",
+                    "TeSt",
+                    99,
+                    0x50FE1962C4965880,
+                    "BAIL TUFT BITS GANG CHEF THY",
+                ),
+                (
+                    "AbCdEfGhIjK",
+                    "alpha1",
+                    0,
+                    0x87066DD9644BF206,
+                    "FULL PEW DOWN ONCE MORT ARC",
+                ),
+                (
+                    "AbCdEfGhIjK",
+                    "alpha1",
+                    1,
+                    0x7CD34C1040ADD14B,
+                    "FACT HOOF AT FIST SITE KENT",
+                ),
+                (
+                    "AbCdEfGhIjK",
+                    "alpha1",
+                    99,
+                    0x5AA37A81F212146C,
+                    "BODE HOP JAKE STOW JUT RAP",
+                ),
+                (
+                    "OTP's are good",
+                    "correct",
+                    0,
+                    0xF205753943DE4CF9,
+                    "ULAN NEW ARMY FUSE SUIT EYED",
+                ),
+                (
+                    "OTP's are good",
+                    "correct",
+                    1,
+                    0xDDCDAC956F234937,
+                    "SKIM CULT LOB SLAM POE HOWL",
+                ),
+                (
+                    "OTP's are good",
+                    "correct",
+                    99,
+                    0xB203E28FA525BE47,
+                    "LONG IVY JULY AJAR BOND LEE",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn rfc2289_sha1() {
+        check(
+            SkeyAlgorithm::Sha1,
+            &[
+                (
+                    "This is a test.",
+                    "TeSt",
+                    0,
+                    0xBB9E6AE1979D8FF4,
+                    "MILT VARY MAST OK SEES WENT",
+                ),
+                (
+                    "This is a test.",
+                    "TeSt",
+                    1,
+                    0x63D936639734385B,
+                    "CART OTTO HIVE ODE VAT NUT",
+                ),
+                (
+                    "This is a test.",
+                    "TeSt",
+                    99,
+                    0x87FEC7768B73CCF9,
+                    "GAFF WAIT SKID GIG SKY EYED",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn client_sequence() {
+        let challenge: SkeyChallenge = "otp-md5 99 TeSt ext".parse().unwrap();
+        assert_eq!(challenge.to_string(), "otp-md5 99 test");
+        let mut skey = SkeyClient::new(
+            SkeyAlgorithm::Md5,
+            "This is a test.".to_string(),
+            "test",
+            100,
+        )
+        .unwrap();
+        assert_eq!(
+            skey.respond(Some(&challenge)).unwrap(),
+            "BAIL TUFT BITS GANG CHEF THY"
+        );
+        assert_eq!(skey.sequence(), Some(&98));
+
+        // Challenges above the sequence number are refused, and skipping below it is told.
+        let replayed: SkeyChallenge = "otp-md5 99 test".parse().unwrap();
+        assert!(skey.respond(Some(&replayed)).is_err());
+        assert_eq!(skey.sequence(), Some(&98));
+        assert!(!skey.skips(&"otp-md5 98 test".parse().unwrap()));
+        let last: SkeyChallenge = "otp-md5 0 test".parse().unwrap();
+        assert!(skey.skips(&last));
+        assert_eq!(
+            skey.respond(Some(&last)).unwrap(),
+            to_words(&otp(SkeyAlgorithm::Md5, "This is a test.", "test", 0).unwrap())
+        );
+        assert_eq!(skey.sequence(), None);
+        assert!(!skey.skips(&last));
+        assert!(skey.challenge().is_none());
+        assert!(skey.respond(None).is_err());
+        let serialized = serde_json::to_string(&skey).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SkeyClient>(&serialized).unwrap(),
+            skey
+        );
+        let other: SkeyChallenge = "otp-sha1 97 test".parse().unwrap();
+        assert!(skey.respond(Some(&other)).is_err());
+        assert!(skey.respond(Some(&last)).is_err());
+        assert!(SkeyClient::new(SkeyAlgorithm::Md5, "short".to_string(), "test", 1).is_err());
+    }
+}
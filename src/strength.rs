@@ -0,0 +1,471 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Estimate password strength, and the cost to crack it offline.
+//!
+//! Like [zxcvbn](https://github.com/dropbox/zxcvbn), the password is split into patterns,
+//! such as common passwords, sequences, repeats, rows of keys and years,
+//! and the number of guesses is estimated for the cheapest split.
+//! The cost to crack is estimated from the guesses and the parameters of Argon2id,
+//! which bound how many guesses an attacker can try per second.
+
+use crate::database::KdfParams;
+
+/// Common passwords and words, ordered from the most common one.
+const COMMON_WORDS: &[&str] = &[
+    "123456",
+    "password",
+    "12345678",
+    "qwerty",
+    "123456789",
+    "12345",
+    "1234",
+    "111111",
+    "1234567",
+    "dragon",
+    "123123",
+    "baseball",
+    "abc123",
+    "football",
+    "monkey",
+    "letmein",
+    "696969",
+    "shadow",
+    "master",
+    "666666",
+    "qwertyuiop",
+    "123321",
+    "mustang",
+    "1234567890",
+    "michael",
+    "654321",
+    "superman",
+    "1qaz2wsx",
+    "7777777",
+    "121212",
+    "000000",
+    "qazwsx",
+    "123qwe",
+    "killer",
+    "trustno1",
+    "jordan",
+    "jennifer",
+    "zxcvbnm",
+    "asdfgh",
+    "hunter",
+    "buster",
+    "soccer",
+    "harley",
+    "batman",
+    "andrew",
+    "tigger",
+    "sunshine",
+    "iloveyou",
+    "2000",
+    "charlie",
+    "robert",
+    "thomas",
+    "hockey",
+    "ranger",
+    "daniel",
+    "starwars",
+    "klaster",
+    "112233",
+    "george",
+    "computer",
+    "michelle",
+    "jessica",
+    "pepper",
+    "1111",
+    "zxcvbn",
+    "555555",
+    "11111111",
+    "131313",
+    "freedom",
+    "777777",
+    "pass",
+    "maggie",
+    "159753",
+    "aaaaaa",
+    "ginger",
+    "princess",
+    "joshua",
+    "cheese",
+    "amanda",
+    "summer",
+    "love",
+    "ashley",
+    "nicole",
+    "chelsea",
+    "biteme",
+    "matthew",
+    "access",
+    "yankees",
+    "987654321",
+    "dallas",
+    "austin",
+    "thunder",
+    "taylor",
+    "matrix",
+    "admin",
+    "welcome",
+    "login",
+    "secret",
+    "passw0rd",
+    "hello",
+    "monday",
+    "sunday",
+    "winter",
+    "spring",
+    "autumn",
+    "flower",
+    "orange",
+    "banana",
+    "apple",
+    "google",
+    "internet",
+    "qwerty123",
+    "password1",
+    "changeme",
+    "default",
+    "root",
+    "user",
+    "test",
+    "guest",
+    "token",
+    "totp",
+    "yatotp",
+    "otp",
+    "authenticator",
+    "database",
+];
+/// Rows of keys on a QWERTY keyboard.
+const KEYBOARD_ROWS: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+const MIN_PATTERN_LEN: usize = 3;
+/// Memory bandwidth of the attacker's GPU, in bytes per second.
+const ATTACKER_BANDWIDTH: f64 = 1e12;
+
+/// Strength of a password.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    /// Estimated number of guesses to find the password.
+    pub guesses: f64,
+    /// Score from 0 (too guessable) to 4 (very unguessable), as zxcvbn does.
+    pub score: u8,
+    /// Why the password is weak, if any.
+    pub warning: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pattern {
+    Common,
+    Sequence,
+    Repeat,
+    Keyboard,
+    Year,
+}
+
+impl Pattern {
+    fn warning(self) -> &'static str {
+        match self {
+            Pattern::Common => "Common passwords and words are easy to guess.",
+            Pattern::Sequence => "Sequences like abc or 6543 are easy to guess.",
+            Pattern::Repeat => "Repeats like aaa are easy to guess.",
+            Pattern::Keyboard => "Straight rows of keys are easy to guess.",
+            Pattern::Year => "Years are easy to guess.",
+        }
+    }
+}
+
+/// A substring `password[start..end]` which matches the pattern.
+struct Match {
+    start: usize,
+    end: usize,
+    guesses: f64,
+    pattern: Pattern,
+}
+
+/// Estimate the strength of the password.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::strength::estimate;
+/// assert_eq!(estimate("password1").score, 0);
+/// assert_eq!(estimate("correct horse battery staple").score, 4);
+/// ```
+pub fn estimate(password: &str) -> Strength {
+    let chars: Vec<char> = password.chars().collect();
+    let matches = find_matches(&chars);
+    let brute_force = cardinality(&chars).log10();
+    // Minimum log10 of guesses for the prefix of each length, and the match ending there.
+    let mut best = vec![(0.0f64, None); chars.len() + 1];
+    for end in 1..=chars.len() {
+        best[end] = (best[end - 1].0 + brute_force, None);
+        for (i, m) in matches.iter().enumerate().filter(|(_, m)| m.end == end) {
+            let guesses = best[m.start].0 + m.guesses.log10();
+            if guesses < best[end].0 {
+                best[end] = (guesses, Some(i));
+            }
+        }
+    }
+    let mut weakest: Option<&Match> = None;
+    let mut end = chars.len();
+    while end > 0 {
+        end = match best[end].1 {
+            Some(i) => {
+                let m = &matches[i];
+                if weakest.is_none_or(|w| w.end - w.start < m.end - m.start) {
+                    weakest = Some(m);
+                }
+                m.start
+            }
+            None => end - 1,
+        };
+    }
+    let guesses = 10f64.powf(best[chars.len()].0);
+    let score = match guesses {
+        g if g < 1e3 => 0,
+        g if g < 1e6 => 1,
+        g if g < 1e8 => 2,
+        g if g < 1e10 => 3,
+        _ => 4,
+    };
+    let warning = match (weakest, score) {
+        (_, 4) => None,
+        (Some(m), _) if 2 * (m.end - m.start) >= chars.len() => Some(m.pattern.warning()),
+        _ if chars.len() < 12 => Some("Short passwords are easy to guess. Add a few more words."),
+        _ => None,
+    };
+    Strength {
+        guesses,
+        score,
+        warning,
+    }
+}
+
+/// Return the size of the character set, from which each character seems to be chosen.
+fn cardinality(chars: &[char]) -> f64 {
+    let mut size = 0.0;
+    if chars.iter().any(char::is_ascii_lowercase) {
+        size += 26.0;
+    }
+    if chars.iter().any(char::is_ascii_uppercase) {
+        size += 26.0;
+    }
+    if chars.iter().any(char::is_ascii_digit) {
+        size += 10.0;
+    }
+    if chars.iter().any(|c| !c.is_ascii_alphanumeric()) {
+        size += 33.0;
+    }
+    f64::max(size, 10.0)
+}
+
+fn find_matches(chars: &[char]) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for start in 0..chars.len() {
+        for end in start + MIN_PATTERN_LEN..=chars.len() {
+            let token = &chars[start..end];
+            let mut push = |guesses: f64, pattern| {
+                matches.push(Match {
+                    start,
+                    end,
+                    guesses: guesses.max(1.0),
+                    pattern,
+                })
+            };
+            if let Some(guesses) = common_guesses(token) {
+                push(guesses, Pattern::Common);
+            }
+            if let Some(guesses) = sequence_guesses(token) {
+                push(guesses, Pattern::Sequence);
+            }
+            if token.iter().all(|c| *c == token[0]) {
+                push(
+                    cardinality(&token[..1]) * token.len() as f64,
+                    Pattern::Repeat,
+                );
+            }
+            if let Some(guesses) = keyboard_guesses(token) {
+                push(guesses, Pattern::Keyboard);
+            }
+            if let Some(guesses) = year_guesses(token) {
+                push(guesses, Pattern::Year);
+            }
+        }
+    }
+    matches
+}
+
+/// Common word in any case, possibly with l33t substitutions.
+fn common_guesses(token: &[char]) -> Option<f64> {
+    let mut substituted = false;
+    let word: String = token
+        .iter()
+        .map(|c| {
+            let unl33t = match c {
+                '0' => 'o',
+                '1' | '!' => 'i',
+                '3' => 'e',
+                '4' | '@' => 'a',
+                '5' | '$' => 's',
+                '7' => 't',
+                c => c.to_ascii_lowercase(),
+            };
+            substituted |= unl33t != c.to_ascii_lowercase();
+            unl33t
+        })
+        .collect();
+    let lower: String = token.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let (rank, substituted) = match COMMON_WORDS.iter().position(|w| *w == lower) {
+        Some(rank) => (rank, false),
+        None => (COMMON_WORDS.iter().position(|w| *w == word)?, substituted),
+    };
+    let uppercase = token.iter().filter(|c| c.is_ascii_uppercase()).count();
+    let case = match uppercase {
+        0 => 1.0,
+        1 if token[0].is_ascii_uppercase() => 2.0,
+        n => 2f64.powi(n as i32).min(2f64.powi(token.len() as i32)),
+    };
+    let l33t = if substituted { 2.0 } else { 1.0 };
+    Some((rank + 1) as f64 * case * l33t)
+}
+
+/// Characters increasing or decreasing one by one, such as `abc` and `6543`.
+fn sequence_guesses(token: &[char]) -> Option<f64> {
+    let delta = token[1] as i64 - token[0] as i64;
+    if delta.abs() != 1 || token.windows(2).any(|w| w[1] as i64 - w[0] as i64 != delta) {
+        return None;
+    }
+    let start = match token[0] {
+        'a' | 'A' | 'z' | 'Z' | '0' | '1' | '9' => 4.0,
+        c if c.is_ascii_digit() => 10.0,
+        _ => 26.0,
+    };
+    let direction = if delta < 0 { 2.0 } else { 1.0 };
+    Some(start * token.len() as f64 * direction)
+}
+
+/// A straight run on a row of keys, in either direction.
+fn keyboard_guesses(token: &[char]) -> Option<f64> {
+    if token.len() < 4 {
+        return None;
+    }
+    let lower: String = token.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let reversed: String = lower.chars().rev().collect();
+    KEYBOARD_ROWS
+        .iter()
+        .any(|row| row.contains(&lower) || row.contains(&reversed))
+        .then(|| (KEYBOARD_ROWS.len() * 10 * 2 * token.len()) as f64)
+}
+
+/// A year from 1900 to 2099.
+fn year_guesses(token: &[char]) -> Option<f64> {
+    let year: String = token.iter().collect();
+    match year.len() == 4 && year.parse::<u32>().is_ok_and(|y| (1900..2100).contains(&y)) {
+        true => Some(200.0),
+        false => None,
+    }
+}
+
+/// Estimate how long it takes in seconds to try the guesses with an attacker's GPU,
+/// when each guess is hashed by Argon2id with the parameters.
+///
+/// Argon2id is memory-hard, so it is bounded by memory bandwidth:
+/// each guess reads and writes `m_cost` KiB of memory `t_cost` times.
+/// The GPU is assumed to have 1 TB/s of memory bandwidth.
+/// Divide the result by the number of GPUs the attacker has.
+pub fn crack_seconds(guesses: f64, params: &KdfParams) -> f64 {
+    let bytes_per_guess = 2.0 * 1024.0 * params.m_cost as f64 * params.t_cost as f64;
+    guesses * bytes_per_guess / ATTACKER_BANDWIDTH
+}
+
+/// Format the duration in seconds roughly, such as `3 days` and `centuries`.
+pub fn format_duration(seconds: f64) -> String {
+    const UNITS: &[(&str, f64)] = &[
+        ("second", 1.0),
+        ("minute", 60.0),
+        ("hour", 3600.0),
+        ("day", 86400.0),
+        ("month", 86400.0 * 31.0),
+        ("year", 86400.0 * 365.0),
+    ];
+    if seconds < 1.0 {
+        return "less than a second".to_string();
+    }
+    if seconds >= 86400.0 * 365.0 * 100.0 {
+        return "centuries".to_string();
+    }
+    let (unit, size) = UNITS
+        .iter()
+        .rev()
+        .find(|(_, size)| seconds >= *size)
+        .expect("Duration is at least a second.");
+    let count = (seconds / size).round() as u64;
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn weak_passwords() {
+        for password in [
+            "", "password", "P@ssw0rd", "qwerty", "abcdef", "aaaaaaaa", "1987",
+        ] {
+            assert_eq!(estimate(password).score, 0, "{}", password);
+        }
+        assert_eq!(
+            estimate("P@ssw0rd").warning,
+            Some(Pattern::Common.warning())
+        );
+        assert_eq!(
+            estimate("asdfghjkl").warning,
+            Some(Pattern::Keyboard.warning())
+        );
+        assert_eq!(estimate("98765").warning, Some(Pattern::Sequence.warning()));
+        assert!(estimate("Password1987").score < 2);
+    }
+
+    #[test]
+    fn strong_passwords() {
+        assert_eq!(estimate("correct horse battery staple").score, 4);
+        assert_eq!(estimate("Tr0ub4dour&3x9Kq").score, 4);
+        assert!(estimate("kx8#fQ2").guesses > estimate("abc1234").guesses);
+        assert_eq!(estimate("correct horse battery staple").warning, None);
+    }
+
+    #[test]
+    fn crack_time() {
+        let params = KdfParams::default();
+        let seconds = crack_seconds(1e10, &params);
+        assert!((seconds - 1e10 * 2.0 * 4096.0 * 1024.0 * 3.0 / 1e12).abs() < 1.0);
+        let heavier = KdfParams {
+            m_cost: params.m_cost * 16,
+            ..params
+        };
+        assert_eq!(crack_seconds(1e10, &heavier), seconds * 16.0);
+        assert_eq!(format_duration(0.5), "less than a second");
+        assert_eq!(format_duration(1.0), "1 second");
+        assert_eq!(format_duration(7200.0), "2 hours");
+        assert_eq!(format_duration(86400.0 * 365.0 * 3.0), "3 years");
+        assert_eq!(format_duration(1e12), "centuries");
+    }
+}
@@ -0,0 +1,330 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Parse and format `otpauth://` URI.
+//!
+//! It conforms to [Key Uri Format] used by Google Authenticator and most of other apps.
+//! Steam Guard accounts are accepted either as `otpauth://` URI with `encoder=steam` parameter
+//! or as `steam://` URI whose body is the base32-encoded secret.
+//!
+//! [Key Uri Format]: https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+
+use crate::otp::{Encoding, HashType, TotpClient};
+use crate::secret::Zeroizing;
+use anyhow::{bail, ensure, Context, Result};
+use data_encoding::BASE32_NOPAD;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use url::Url;
+
+const STEAM_SCHEME: &str = "steam://";
+/// The most digits of codes, which still cut the 31-bit truncated value.
+const MAX_DIGITS: u32 = 10;
+/// Characters percent-encoded in query values.
+const QUERY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'@');
+/// Characters percent-encoded in the label, which keeps `:` between issuer and account name.
+const LABEL_ENCODE: &AsciiSet = &QUERY_ENCODE.remove(b':');
+
+/// Account information contained in the URI.
+#[derive(Debug, PartialEq)]
+pub struct OtpauthUri {
+    /// Label of the account, usually `issuer:account name`. Empty for `steam://` URI.
+    pub label: String,
+    /// Provider or service of the account, if given.
+    pub issuer: Option<String>,
+    /// TOTP client constructed from the URI parameters.
+    pub client: TotpClient,
+}
+
+/// Parse `otpauth://totp/...` or `steam://...` URI.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::uri;
+/// let parsed = uri::parse(
+///     "otpauth://totp/Example:alice@google.com?secret=JBSWY3DPEHPK3PXP&issuer=Example",
+/// )
+/// .unwrap();
+/// assert_eq!(parsed.label, "Example:alice@google.com");
+/// assert_eq!(parsed.issuer, Some("Example".to_string()));
+/// ```
+pub fn parse(uri: &str) -> Result<OtpauthUri> {
+    let uri = uri.trim();
+    if let Some(secret) = strip_prefix_ignore_case(uri, STEAM_SCHEME) {
+        return Ok(OtpauthUri {
+            label: String::new(),
+            issuer: None,
+            client: TotpClient::new_steam(decode_secret(secret)?),
+        });
+    }
+    let url = Url::parse(uri).context("Failed to parse URI.")?;
+    ensure!(
+        url.scheme() == "otpauth",
+        "Unsupported URI scheme: {}",
+        url.scheme()
+    );
+    ensure!(
+        url.host_str() == Some("totp"),
+        "Unsupported OTP type: {}",
+        url.host_str().unwrap_or("")
+    );
+    let label = percent_decode_str(url.path().trim_start_matches('/'))
+        .decode_utf8()
+        .context("Label is not valid UTF-8.")?
+        .to_string();
+
+    let mut secret = None;
+    let mut issuer = None;
+    let mut hashtype = HashType::Sha1;
+    let mut digit = 6;
+    let mut timestep = 30;
+    let mut steam = false;
+    for (k, v) in url.query_pairs() {
+        match k.as_ref() {
+            "secret" => secret = Some(v.to_string()),
+            "issuer" => issuer = Some(v.to_string()),
+            "algorithm" => {
+                hashtype = match v.to_ascii_uppercase().as_str() {
+                    "SHA1" => HashType::Sha1,
+                    "SHA256" => HashType::Sha256,
+                    "SHA512" => HashType::Sha512,
+                    _ => bail!("Unsupported algorithm: {}", v),
+                }
+            }
+            "digits" => digit = v.parse().context("Invalid digits parameter.")?,
+            "period" => timestep = v.parse().context("Invalid period parameter.")?,
+            "encoder" => steam = v.eq_ignore_ascii_case("steam"),
+            _ => {}
+        }
+    }
+    ensure!(
+        (1..=MAX_DIGITS).contains(&digit),
+        "Digits must be between 1 and {}.",
+        MAX_DIGITS
+    );
+    ensure!(timestep > 0, "Period must be positive.");
+    let secret = secret.context("URI does not contain secret.")?;
+    let secret = match strip_prefix_ignore_case(&secret, STEAM_SCHEME) {
+        Some(s) => {
+            steam = true;
+            s
+        }
+        None => &secret,
+    };
+    let key = decode_secret(secret)?;
+    let client = match steam {
+        true => TotpClient::new_steam(key),
+        false => TotpClient::new(key, timestep, 0, digit, hashtype),
+    };
+    Ok(OtpauthUri {
+        label,
+        issuer,
+        client,
+    })
+}
+
+/// Format `otpauth://totp/...` URI of the client, which other apps can import.
+///
+/// The issuer is the part of the label before `:`, if any.
+/// Steam Guard clients have `encoder=steam` parameter.
+/// Returns error if the client can not be represented in the URI,
+/// such as SHA-3, non-zero T0 or custom encodings.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::{otp::*, uri};
+/// let client =
+///     TotpClient::from_base32key("JBSWY3DPEHPK3PXP".to_string(), 30, 0, 6, HashType::Sha1).unwrap();
+/// assert_eq!(
+///     uri::format("Example:alice@google.com", &client).unwrap().as_str(),
+///     "otpauth://totp/Example:alice@google.com?secret=JBSWY3DPEHPK3PXP&issuer=Example\
+///      &algorithm=SHA1&digits=6&period=30",
+/// );
+/// ```
+pub fn format(label: &str, client: &TotpClient) -> Result<Zeroizing<String>> {
+    ensure!(
+        *client.t0() == 0,
+        "T0 other than 0 can not be represented in otpauth URI."
+    );
+    let algorithm = match client.hashtype() {
+        HashType::Sha1 => "SHA1",
+        HashType::Sha256 => "SHA256",
+        HashType::Sha512 => "SHA512",
+        hashtype => bail!("{:?} can not be represented in otpauth URI.", hashtype),
+    };
+    let mut uri = Zeroizing::new(format!(
+        "otpauth://totp/{}?secret=",
+        utf8_percent_encode(label, LABEL_ENCODE)
+    ));
+    uri.push_str(&Zeroizing::new(BASE32_NOPAD.encode(client.key())));
+    if let Some((issuer, _)) = label.split_once(':') {
+        uri.push_str("&issuer=");
+        uri.extend(utf8_percent_encode(issuer, QUERY_ENCODE));
+    }
+    match client.encoding() {
+        Encoding::Decimal => uri.push_str(&format!(
+            "&algorithm={}&digits={}&period={}",
+            algorithm,
+            client.digit(),
+            client.timestep()
+        )),
+        Encoding::Steam => uri.push_str("&encoder=steam"),
+        encoding => bail!(
+            "{:?} encoding can not be represented in otpauth URI.",
+            encoding
+        ),
+    }
+    Ok(uri)
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    match s.get(..prefix.len()) {
+        Some(head) if head.eq_ignore_ascii_case(prefix) => Some(&s[prefix.len()..]),
+        _ => None,
+    }
+}
+
+/// Decode the base32 secret key, ignoring case, spaces and padding.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::uri;
+/// assert_eq!(uri::decode_secret("jbsw y3dp").unwrap(), b"Hello");
+/// ```
+pub fn decode_secret(secret: &str) -> Result<Vec<u8>> {
+    let secret: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .context("Failed to decode base32-encoded key.")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::otp::Encoding;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn parse_totp() {
+        let parsed = parse(
+            "otpauth://totp/ACME%20Co:john.doe@email.com?\
+             secret=HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ&issuer=ACME%20Co\
+             &algorithm=SHA256&digits=8&period=60",
+        )
+        .unwrap();
+        assert_eq!(parsed.label, "ACME Co:john.doe@email.com");
+        assert_eq!(parsed.issuer, Some("ACME Co".to_string()));
+        assert_eq!(
+            parsed.client,
+            TotpClient::from_base32key(
+                "HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ".to_string(),
+                60,
+                0,
+                8,
+                HashType::Sha256
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_steam() {
+        let expected = TotpClient::new_steam("12345678901234567890".as_bytes().to_vec());
+        let parsed = parse(
+            "otpauth://totp/Steam:alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&encoder=steam",
+        )
+        .unwrap();
+        assert_eq!(parsed.client, expected);
+        assert_eq!(*parsed.client.encoding(), Encoding::Steam);
+        let parsed =
+            parse("otpauth://totp/Steam:alice?secret=steam://GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ")
+                .unwrap();
+        assert_eq!(parsed.client, expected);
+        let parsed = parse("steam://GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+        assert_eq!(parsed.label, "");
+        assert_eq!(parsed.client, expected);
+    }
+
+    #[test]
+    fn format_and_parse() {
+        let clients = vec![
+            TotpClient::from_base32key(
+                "HXDMVJECJJWSRB3HWIZR4IFUGFTMXBOZ".to_string(),
+                60,
+                0,
+                8,
+                HashType::Sha256,
+            )
+            .unwrap(),
+            TotpClient::new_steam("12345678901234567890".as_bytes().to_vec()),
+        ];
+        for client in clients {
+            let uri = format("ACME Co:john.doe+1@email.com", &client).unwrap();
+            assert!(uri.starts_with("otpauth://totp/ACME%20Co:john.doe%2B1@email.com?"));
+            let parsed = parse(&uri).unwrap();
+            assert_eq!(parsed.label, "ACME Co:john.doe+1@email.com");
+            assert_eq!(parsed.issuer, Some("ACME Co".to_string()));
+            assert_eq!(parsed.client, client);
+        }
+        let parsed = parse(&format("alice", &TotpClient::new_steam(vec![1; 10])).unwrap()).unwrap();
+        assert_eq!(parsed.issuer, None);
+
+        let key = "12345678901234567890".as_bytes().to_vec();
+        assert!(format(
+            "alice",
+            &TotpClient::new(key.clone(), 30, 0, 6, HashType::Sha3_256)
+        )
+        .is_err());
+        assert!(format(
+            "alice",
+            &TotpClient::new(key.clone(), 30, 10, 6, HashType::Sha1)
+        )
+        .is_err());
+        let hex = TotpClient::new(key, 30, 0, 6, HashType::Sha1).with_encoding(Encoding::Hex);
+        assert!(format("alice", &hex).is_err());
+    }
+
+    #[test]
+    fn reject_invalid_parameters() {
+        for query in ["digits=0", "digits=11", "digits=4294967295", "period=0"] {
+            assert!(parse(&format!(
+                "otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&{}",
+                query
+            ))
+            .is_err());
+        }
+        let parsed = parse("otpauth://totp/alice?secret=JBSWY3DPEHPK3PXP&digits=10").unwrap();
+        assert_eq!(parsed.client.code(&Utc.timestamp(59, 0)).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn reject_hotp() {
+        assert!(parse("otpauth://hotp/alice?secret=JBSWY3DPEHPK3PXP&counter=0").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+// yatotp: Yet Another Time-Based OTP client.
+// Copyright 2021, Nobuto Kaitoh
+//
+// This file is part of yatotp.
+//
+// Yatotp is free software: you can redistribute it and/or
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Yatotp is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with yatotp.  If not, see <https://www.gnu.org/licenses/>.
+
+//! Yandex.Key OTP calculation.
+//!
+//! Yandex.Key is a variant of TOTP whose HMAC-SHA-256 key is
+//! SHA-256 hash of the PIN followed by the secret,
+//! and whose code is 8 lowercase latin letters.
+
+use crate::otp::HashType;
+use crate::secret::{Secret, Zeroizing};
+use anyhow::{ensure, Context, Result};
+use chrono::prelude::*;
+use data_encoding::BASE32_NOPAD;
+use serde::{Deserialize, Serialize};
+
+/// Time step of Yandex.Key in seconds.
+pub const YANDEX_TIMESTEP: u64 = 30;
+
+/// Length of Yandex.Key code.
+pub const YANDEX_DIGIT: u32 = 8;
+
+const YANDEX_SECRET_LEN: usize = 16;
+
+/// A Yandex.Key client for each account.
+///
+/// # Example
+///
+/// ```
+/// # use yatotp::yandex::*;
+/// # use chrono::prelude::*;
+/// let yandex = YandexClient::from_base32key(
+///     "LA2V6KMCGYMWWVEW64RNP3JA3IAAAAAAHTSG4HRZPI",
+///     "7586".to_string(),
+/// )
+/// .unwrap();
+/// assert_eq!(yandex.code(&Utc.timestamp(1581064020, 0)).unwrap(), "oactmacq");
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(try_from = "YandexClientData")]
+pub struct YandexClient {
+    key: Secret<Vec<u8>>,
+    pin: Secret<String>,
+}
+
+/// Fields of [YandexClient] to be checked when it is deserialized.
+#[derive(Deserialize)]
+struct YandexClientData {
+    key: Secret<Vec<u8>>,
+    pin: Secret<String>,
+}
+
+impl TryFrom<YandexClientData> for YandexClient {
+    type Error = anyhow::Error;
+
+    fn try_from(data: YandexClientData) -> Result<YandexClient> {
+        YandexClient::new(data.key.expose().clone(), data.pin.expose().clone())
+    }
+}
+
+impl YandexClient {
+    /// Create a new Yandex.Key client with 16 bytes secret and PIN.
+    pub fn new(key: Vec<u8>, pin: String) -> Result<YandexClient> {
+        ensure!(
+            key.len() == YANDEX_SECRET_LEN,
+            "Yandex.Key secret must be {} bytes.",
+            YANDEX_SECRET_LEN
+        );
+        ensure!(
+            (4..=16).contains(&pin.len()) && pin.chars().all(|c| c.is_ascii_digit()),
+            "Yandex.Key PIN must be 4 to 16 decimal digits."
+        );
+        Ok(YandexClient {
+            key: Secret::new(key),
+            pin: Secret::new(pin),
+        })
+    }
+
+    /// Create a new Yandex.Key client with base32-encoded secret and PIN.
+    ///
+    /// The secret given by Yandex has 26 bytes, and its first 16 bytes are used as the key.
+    pub fn from_base32key(key: &str, pin: String) -> Result<YandexClient> {
+        let key = key.trim_end_matches('=').to_ascii_uppercase();
+        let mut key = BASE32_NOPAD
+            .decode(key.as_bytes())
+            .context("Failed to decode base32-encoded key.")?;
+        ensure!(
+            key.len() >= YANDEX_SECRET_LEN,
+            "Yandex.Key secret is too short."
+        );
+        key.truncate(YANDEX_SECRET_LEN);
+        YandexClient::new(key, pin)
+    }
+
+    /// Calculate the Yandex.Key code of given datetime.
+    ///
+    /// Returns `None` if the datetime is before the Unix epoch.
+    pub fn code(&self, datetime: &DateTime<Utc>) -> Option<String> {
+        let counter = u64::try_from(datetime.timestamp()).ok()? / YANDEX_TIMESTEP;
+        let mut pin_and_key = Zeroizing::new(self.pin.expose().as_bytes().to_vec());
+        pin_and_key.extend_from_slice(self.key.expose());
+        let mut hmac_key = Zeroizing::new(HashType::Sha256.digest(&pin_and_key));
+        if hmac_key[0] == 0 {
+            hmac_key.remove(0);
+        }
+        let hs = HashType::Sha256.hmac(&hmac_key, &counter.to_be_bytes());
+        let offset = (hs[hs.len() - 1] & 0xf) as usize;
+        let mut bin_code = [0u8; 8];
+        bin_code.copy_from_slice(&hs[offset..offset + 8]);
+        let mut bin_code =
+            (u64::from_be_bytes(bin_code) & 0x7fff_ffff_ffff_ffff) % 26u64.pow(YANDEX_DIGIT);
+        let mut code = vec![b'a'; YANDEX_DIGIT as usize];
+        for c in code.iter_mut().rev() {
+            *c += (bin_code % 26) as u8;
+            bin_code /= 26;
+        }
+        Some(String::from_utf8(code).expect("Code consists of ASCII letters"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn yandex_example() {
+        let cases = [
+            (
+                "5239",
+                "6SB2IKNM6OBZPAVBVTOHDKS4FAAAAAAADFUTQMBTRY",
+                1641559648,
+                "umozdicq",
+            ),
+            (
+                "7586",
+                "LA2V6KMCGYMWWVEW64RNP3JA3IAAAAAAHTSG4HRZPI",
+                1581064020,
+                "oactmacq",
+            ),
+            (
+                "7586",
+                "LA2V6KMCGYMWWVEW64RNP3JA3IAAAAAAHTSG4HRZPI",
+                1581090810,
+                "wemdwrix",
+            ),
+            (
+                "5210481216086702",
+                "JBGSAU4G7IEZG6OY4UAXX62JU4AAAAAAHTSG4HXU3M",
+                1581091469,
+                "dfrpywob",
+            ),
+            (
+                "5210481216086702",
+                "JBGSAU4G7IEZG6OY4UAXX62JU4AAAAAAHTSG4HXU3M",
+                1581093059,
+                "vunyprpd",
+            ),
+        ];
+        for (pin, key, time, code) in cases.iter() {
+            let yandex = YandexClient::from_base32key(key, pin.to_string()).unwrap();
+            assert_eq!(yandex.code(&Utc.timestamp(*time, 0)).unwrap(), *code);
+        }
+        assert!(YandexClient::new(vec![0; 16], "12".to_string()).is_err());
+        assert!(YandexClient::new(vec![0; 10], "1234".to_string()).is_err());
+    }
+}